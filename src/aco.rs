@@ -0,0 +1,148 @@
+use crate::{
+    error::{Error, Result},
+    format::FileFormat,
+    palette::{Color, Palette, PaletteFormat},
+    read_write_ext::DataReader,
+};
+
+/// Color space of RGB colors, with channels from 0 to 65535.
+const COLOR_SPACE_RGB: u16 = 0;
+
+/// Color space of grayscale colors, from 0 (white) to 10000 (black).
+const COLOR_SPACE_GRAYSCALE: u16 = 8;
+
+/// Adobe Color Swatches. Only RGB and grayscale colors are supported, and
+/// color names are not kept.
+///
+/// Files are written with both the version 1 and version 2 sections, as
+/// Photoshop does.
+pub struct Aco {
+    palette: Palette,
+}
+
+impl Aco {
+    pub fn from_palette(palette: Palette) -> Self {
+        Aco { palette }
+    }
+
+    pub fn to_palette(self) -> Palette {
+        self.palette
+    }
+
+    /// Reads the section of the given version, whose header has already been
+    /// read.
+    fn read_section(reader: &mut DataReader, version: u16) -> Result<Vec<Color>> {
+        let num_colors = reader.read_u16_be()?;
+        let mut colors = vec![];
+        for _ in 0..num_colors {
+            let offset = reader.offset();
+            let color_space = reader.read_u16_be()?;
+            let mut values = [0; 4];
+            for value in &mut values {
+                *value = reader.read_u16_be()?;
+            }
+            let color = match color_space {
+                COLOR_SPACE_RGB => {
+                    let [red, green, blue, _] =
+                        values.map(|value| ((u32::from(value) + 128) / 257) as u8);
+                    Color::new(red, green, blue)
+                }
+                COLOR_SPACE_GRAYSCALE if values[0] <= 10000 => {
+                    let value = 255 - ((u32::from(values[0]) * 255 + 5000) / 10000) as u8;
+                    Color::new(value, value, value)
+                }
+                _ => {
+                    return Err(
+                        reader.error_at(offset, format!("unsupported color space {}", color_space))
+                    )
+                }
+            };
+            if version == 2 {
+                let name_length = reader.read_u32_be()?;
+                reader.read_sized(usize::try_from(name_length).unwrap() * 2)?;
+            }
+            colors.push(color);
+        }
+        Ok(colors)
+    }
+
+    fn write_section(&self, data: &mut Vec<u8>, version: u16) -> Result<()> {
+        let num_colors = u16::try_from(self.palette.colors().len()).map_err(|_| {
+            Error::invalid_input(format!(
+                "palette has {} colors, but at most {} are supported",
+                self.palette.colors().len(),
+                u16::MAX
+            ))
+        })?;
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&num_colors.to_be_bytes());
+        for color in self.palette.colors() {
+            data.extend_from_slice(&COLOR_SPACE_RGB.to_be_bytes());
+            for value in [color.red, color.green, color.blue, 0] {
+                data.extend_from_slice(&(u16::from(value) * 257).to_be_bytes());
+            }
+            if version == 2 {
+                // An empty name, which is only the terminating null.
+                data.extend_from_slice(&1u32.to_be_bytes());
+                data.extend_from_slice(&0u16.to_be_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PaletteFormat for Aco {
+    fn from_palette(palette: Palette) -> Self {
+        Self::from_palette(palette)
+    }
+
+    fn to_palette(self) -> Palette {
+        self.to_palette()
+    }
+}
+
+impl FileFormat for Aco {
+    fn extension() -> String {
+        "aco".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        let mut reader = DataReader::new(BLOCK, data, 0);
+        let version_offset = reader.offset();
+        let mut colors = match reader.read_u16_be()? {
+            version @ (1 | 2) => Self::read_section(&mut reader, version)?,
+            version => {
+                return Err(
+                    reader.error_at(version_offset, format!("unsupported version {}", version))
+                )
+            }
+        };
+
+        // The version 2 section holds the same colors along with their names.
+        if reader.remaining() > 0 {
+            let version_offset = reader.offset();
+            match reader.read_u16_be()? {
+                2 => colors = Self::read_section(&mut reader, 2)?,
+                version => {
+                    return Err(
+                        reader.error_at(version_offset, format!("unexpected version {}", version))
+                    )
+                }
+            }
+        }
+
+        Ok(Aco {
+            palette: Palette::new(colors),
+        })
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        self.write_section(&mut data, 1)?;
+        self.write_section(&mut data, 2)?;
+        Ok(data)
+    }
+}
+
+/// Name used to refer to the file in errors, in place of a block name.
+const BLOCK: &str = "ACO";
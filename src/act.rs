@@ -0,0 +1,118 @@
+use crate::{
+    error::{Error, Result},
+    format::FileFormat,
+    palette::{Color, Palette, PaletteFormat},
+};
+
+/// Number of colors stored in every ACT file, whether used or not.
+const NUM_COLORS: usize = 256;
+
+/// An Adobe Color Table, which holds at most 256 colors.
+///
+/// The optional trailer stores the number of colors and a transparent color,
+/// which is read as the first color with an alpha of 0. All other colors are
+/// opaque.
+pub struct Act {
+    palette: Palette,
+}
+
+impl Act {
+    pub fn from_palette(palette: Palette) -> Self {
+        Act { palette }
+    }
+
+    pub fn to_palette(self) -> Palette {
+        self.palette
+    }
+}
+
+impl PaletteFormat for Act {
+    fn from_palette(palette: Palette) -> Self {
+        Self::from_palette(palette)
+    }
+
+    fn to_palette(self) -> Palette {
+        self.to_palette()
+    }
+}
+
+impl FileFormat for Act {
+    fn extension() -> String {
+        "act".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        let (num_colors, transparent_index) = match data.len() {
+            768 => (NUM_COLORS, None),
+            772 => {
+                let num_colors = u16::from_be_bytes([data[768], data[769]]);
+                let transparent_index = u16::from_be_bytes([data[770], data[771]]);
+                if usize::from(num_colors) > NUM_COLORS {
+                    return Err(Error::invalid_data(
+                        768,
+                        BLOCK,
+                        format!("invalid color count {}", num_colors),
+                    ));
+                }
+                (
+                    usize::from(num_colors),
+                    (transparent_index != 0xFFFF).then_some(usize::from(transparent_index)),
+                )
+            }
+            size => {
+                return Err(Error::invalid_data(
+                    0,
+                    BLOCK,
+                    format!("file size {} is neither 768 nor 772", size),
+                ))
+            }
+        };
+
+        let colors = data[..num_colors * 3]
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let color = Color::new(chunk[0], chunk[1], chunk[2]);
+                match Some(i) == transparent_index {
+                    true => color.with_alpha(0),
+                    false => color,
+                }
+            })
+            .collect();
+
+        Ok(Act {
+            palette: Palette::new(colors),
+        })
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        let colors = self.palette.colors();
+        if colors.len() > NUM_COLORS {
+            return Err(Error::invalid_input(format!(
+                "palette has {} colors, but at most {} are supported",
+                colors.len(),
+                NUM_COLORS
+            )));
+        }
+
+        let mut data = vec![0; NUM_COLORS * 3];
+        for (i, color) in colors.iter().enumerate() {
+            data[i * 3..i * 3 + 3].copy_from_slice(&[color.red, color.green, color.blue]);
+        }
+
+        // The trailer is only needed if the palette is not full or has a
+        // transparent color.
+        let transparent_index = colors.iter().position(Color::is_transparent);
+        if colors.len() < NUM_COLORS || transparent_index.is_some() {
+            let num_colors = u16::try_from(colors.len()).unwrap();
+            let transparent_index = transparent_index.map_or(0xFFFF, |i| u16::try_from(i).unwrap());
+            data.extend_from_slice(&num_colors.to_be_bytes());
+            data.extend_from_slice(&transparent_index.to_be_bytes());
+        }
+
+        Ok(data)
+    }
+}
+
+/// Name used to refer to the file in errors, in place of a block name.
+const BLOCK: &str = "ACT";
@@ -0,0 +1,83 @@
+use std::collections::BTreeSet;
+
+use crate::CliError;
+
+/// Options given after the positional arguments of a command, in the form
+/// `--name` for flags and `--name value` for options that take a value.
+pub struct Options {
+    options: Vec<(String, Option<String>)>,
+
+    /// Names of the options that have been queried so far, so that any
+    /// remaining ones can be reported as unknown.
+    used: BTreeSet<String>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut options = vec![];
+        let mut args = args.iter().peekable();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| CliError::Usage(format!("unexpected argument {:?}", arg)))?;
+            let value = args.next_if(|next| !next.starts_with("--")).cloned();
+            options.push((name.to_string(), value));
+        }
+        Ok(Self {
+            options,
+            used: BTreeSet::new(),
+        })
+    }
+
+    pub fn flag(&mut self, name: &str) -> Result<bool, CliError> {
+        self.used.insert(name.to_string());
+        match self.options.iter().find(|(n, _)| n == name) {
+            Some((_, Some(value))) => Err(CliError::Usage(format!(
+                "--{} does not take a value, found {:?}",
+                name, value
+            ))),
+            Some((_, None)) => Ok(true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn value(&mut self, name: &str) -> Result<Option<&str>, CliError> {
+        self.used.insert(name.to_string());
+        match self.options.iter().find(|(n, _)| n == name) {
+            Some((_, Some(value))) => Ok(Some(value)),
+            Some((_, None)) => Err(CliError::Usage(format!("--{} requires a value", name))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn required_value(&mut self, name: &str) -> Result<&str, CliError> {
+        self.value(name)?
+            .ok_or_else(|| CliError::Usage(format!("--{} is required", name)))
+    }
+
+    /// Parses the value of an option with `parse`, if the option is present.
+    pub fn parsed_value<T>(
+        &mut self,
+        name: &str,
+        parse: fn(&str) -> Option<T>,
+    ) -> Result<Option<T>, CliError> {
+        match self.value(name)? {
+            Some(value) => parse(value).map(Some).ok_or_else(|| {
+                CliError::Usage(format!("invalid value {:?} for --{}", value, name))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Fails if any option was given that the command does not know about.
+    pub fn finish(self) -> Result<(), CliError> {
+        match self
+            .options
+            .iter()
+            .find(|(name, _)| !self.used.contains(name))
+        {
+            Some((name, _)) => Err(CliError::Usage(format!("unknown option --{}", name))),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,622 @@
+mod args;
+mod pret;
+
+use std::{path::Path, process::ExitCode};
+
+use nitrogfx::{
+    Aco, Act, ColorScaling, Dithering, FileFormat, Gpl, Image, Jasc, Nanr, Ncer, Ncgr,
+    NcgrMetadata, Nclr, NclrMetadata, Nscr, NtrCharacterFormat, NtrFileVersion, NtrMappingType,
+    NtrTextureFormat, PaintNet, Palette, Png, Quantization, RawPalette, RawPaletteOptions, RiffPal,
+};
+
+use crate::args::Options;
+
+const USAGE: &str = "\
+Usage: nitrogfx <command> <input> <output> [options]
+       nitrogfx <input> <output> [pret options]
+
+Commands:
+  ncgr2png   [--palette <file> [--bank <n>] [--color-scaling <scaling>]
+             [--transparent]] [--width <tiles>]
+  png2ncgr   [ncgr options] [quantization options]
+  nclr2pal   [--bank <n>] [--color-scaling <scaling>] [--format <format>]
+             [--pad <colors>] [--clear-high-bit]
+  pal2nclr   [--format <format>] [nclr options]
+  png2nclr   [nclr options] [quantization options]
+  ncer2json
+  json2ncer  [--recompute-bounds] [--verify-bounds]
+  png2ncer   --ncgr <file> [--vram-transfer] [ncgr options]
+             [quantization options]
+  nanr2json
+  json2nanr
+  nscr2png   --ncgr <file> --palette <file> [--bank <n>]
+             [--color-scaling <scaling>] [--transparent]
+
+NCGR options:
+  --version <100|101>
+  --texture-format <palette16|palette256>
+  --mapping-type <2d|1d-32k|1d-64k|1d-128k|1d-256k>
+  --character-format <character|bitmap|character256>
+  --cpos
+
+NCLR options:
+  --version <100|101>
+  --texture-format <palette16|palette256>
+  --pltt-0002 <value>
+  --extended
+  --invert-size
+  --high-color-bit
+  --high-bit-as-alpha
+  --pcmp <index>[,<index>...]
+  --color-scaling <scaling>
+
+Quantization options:
+  --palette <file> [--bank <n>] [--nearest]
+  --colors <n>
+  --color-scaling <scaling>
+  --dither <none|floyd-steinberg|bayer|atkinson>
+  --transparent-index <n>
+
+Palette files are JASC or RIFF (.pal), GIMP (.gpl), Adobe (.act, .aco),
+paint.net (.txt) or raw BGR555 (.gbapal, .bin, .nbfp) palettes, based on
+their extension. --format overrides this with one of jasc, riff, gpl, act,
+aco, txt or raw; output .pal files are JASC unless --format riff is given.
+Palettes given with --palette may also be NCLR files.
+Raw palettes written by nclr2pal can be padded to a multiple of --pad colors,
+and have the high bit of every color cleared with --clear-high-bit.
+--bank selects a single 256-color bank of an extended palette.
+--color-scaling converts between the 5-bit channels of NCLR colors and 8-bit
+RGB, and is one of full (x * 8 + x / 4, the default, so that white is 255),
+x8 (x * 8, as used by pret and by NCLR sidecar files) or a comma-separated
+table of 32 distinct 8-bit values. Colors that cannot be represented exactly
+are reduced with a warning.
+Truecolor and grayscale PNGs are converted to indexed images: with --palette,
+each color must match a color of the palette exactly, or is replaced by the
+closest one with --nearest. Otherwise a palette of at most --colors colors
+(16 or 256 by default, following the texture format) is built. Transparent
+pixels take color 0, or the one given with --transparent-index, which opaque
+pixels never take. Indexed PNGs are used as they are.
+--dither hides the banding left by --nearest or by the reduction to the 15-bit
+colors of the DS, and is never applied to transparent pixels.
+--transparent makes the first color of every palette bank transparent in the
+PNG, as the DS does not draw it.
+The input of png2ncer is either a single PNG or a JSON array of PNG paths,
+relative to the JSON file, with one PNG per cell. Its NCGR uses 1D 32K
+mapping unless --mapping-type is given.
+LZ10/LZ11 compressed input files are decompressed automatically.
+PNGs written by ncgr2png embed the NCGR metadata, which png2ncgr restores
+unless overridden by the options above.
+
+When the first argument is a file rather than a command, the arguments are
+interpreted like those of pret's C nitrogfx, and the conversion is inferred
+from the file extensions. Supported conversions are png <-> NCGR,
+pal <-> NCLR, json <-> NCER and json <-> NANR. The JSON is written in the
+schema of pret's nitrogfx, and either that schema or the one of ncer2json and
+nanr2json is accepted as input.";
+
+pub enum CliError {
+    Usage(String),
+    Nitrogfx(nitrogfx::Error),
+}
+
+impl From<nitrogfx::Error> for CliError {
+    fn from(value: nitrogfx::Error) -> Self {
+        Self::Nitrogfx(value)
+    }
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(reason)) => {
+            eprintln!("error: {}\n\n{}", reason, USAGE);
+            ExitCode::FAILURE
+        }
+        Err(CliError::Nitrogfx(error)) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), CliError> {
+    if let [input, output, pret_args @ ..] = args {
+        if Path::new(input).extension().is_some() {
+            return pret::run(input, output, pret_args);
+        }
+    }
+
+    let (command, input, output) = match args {
+        [command, input, output, ..] => (command.as_str(), input, output),
+        _ => return Err(CliError::Usage("missing arguments".to_string())),
+    };
+    let mut options = Options::parse(&args[3..])?;
+
+    match command {
+        "ncgr2png" => {
+            let ncgr = Ncgr::read_from_compressed_file(input)?;
+            let image = match ncgr.metadata().mapping_type {
+                NtrMappingType::Mode2D => ncgr.to_image(),
+                _ => {
+                    let width_in_tiles = options
+                        .parsed_value("width", |value| value.parse::<usize>().ok())?
+                        .unwrap_or(1);
+                    ncgr.to_image_with_width(width_in_tiles * 8)
+                }
+            };
+            let scaling = color_scaling(&mut options)?;
+            let image = match options.value("palette")?.map(str::to_string) {
+                Some(path) => {
+                    let palette = read_palette(&path, scaling)?;
+                    let palette = palette_bank(palette, &mut options)?;
+                    let palette = match options.flag("transparent")? {
+                        true => {
+                            palette.with_transparent_first_colors(ncgr.metadata().texture_format)
+                        }
+                        false => palette,
+                    };
+                    image.with_palette(palette)
+                }
+                None => image,
+            };
+            options.finish()?;
+            Png::from_image(image)
+                .with_ncgr_metadata(ncgr.metadata())
+                .write_to_file(output)?;
+        }
+        "png2ncgr" => {
+            let png = Png::read_from_file(input)?;
+            let metadata = ncgr_metadata(png.ncgr_metadata().unwrap_or_default(), &mut options)?;
+            let quantization = quantization(metadata.texture_format, &mut options)?;
+            options.finish()?;
+            let image = png.to_image_with_quantization(&quantization)?;
+            Ncgr::from_image(image, metadata).write_to_file(output)?;
+        }
+        "nclr2pal" => {
+            let scaling = color_scaling(&mut options)?;
+            let palette = Nclr::read_from_compressed_file(input)?.to_palette_with_scaling(scaling);
+            let palette = palette_bank(palette, &mut options)?;
+            let format = palette_format(output, false, &mut options)?;
+            let mut raw_options = RawPaletteOptions::default().with_color_scaling(scaling);
+            if matches!(format, PaletteFileFormat::Raw) {
+                if let Some(padding) = options.parsed_value("pad", |value| value.parse().ok())? {
+                    raw_options = raw_options.with_padding(padding);
+                }
+                raw_options = raw_options.preserve_high_bit(!options.flag("clear-high-bit")?);
+            }
+            options.finish()?;
+            write_palette_file(output, palette, format, raw_options)?;
+        }
+        "pal2nclr" => {
+            let metadata = nclr_metadata(&mut options)?;
+            let format = palette_format(input, true, &mut options)?;
+            options.finish()?;
+            let palette = read_palette_file(input, format, metadata.color_scaling)?;
+            nclr_from_palette(palette, metadata).write_to_file(output)?;
+        }
+        "png2nclr" => {
+            let metadata = nclr_metadata(&mut options)?;
+            let quantization = quantization(metadata.texture_format, &mut options)?;
+            options.finish()?;
+            let palette = Png::read_from_file(input)?
+                .to_image_with_quantization(&quantization)?
+                .palette()
+                .ok_or_else(|| CliError::Usage(format!("{:?} has no palette", input)))?;
+            nclr_from_palette(palette, metadata).write_to_file(output)?;
+        }
+        "ncer2json" => {
+            options.finish()?;
+            let json = Ncer::read_from_compressed_file(input)?.to_json()?;
+            write_text(output, &json)?;
+        }
+        "json2ncer" => {
+            let recompute_bounds = options.flag("recompute-bounds")?;
+            let verify_bounds = options.flag("verify-bounds")?;
+            options.finish()?;
+            let mut ncer = Ncer::from_json(&read_text(input)?)?;
+            if recompute_bounds {
+                ncer.recompute_bounds();
+            }
+            let incorrect = ncer.cells_with_incorrect_bounds();
+            if verify_bounds && !incorrect.is_empty() {
+                return Err(nitrogfx::Error::InvalidInput {
+                    reason: format!(
+                        "cells {:?} have bounds that do not match their objects",
+                        incorrect
+                    ),
+                }
+                .into());
+            }
+            ncer.write_to_file(output)?;
+        }
+        "png2ncer" => {
+            let ncgr_path = options.required_value("ncgr")?.to_string();
+            let default_metadata =
+                NcgrMetadata::default().with_mapping_type(NtrMappingType::Mode1D32K);
+            let metadata = ncgr_metadata(default_metadata, &mut options)?;
+            let vram_transfer = options.flag("vram-transfer")?;
+            let quantization = quantization(metadata.texture_format, &mut options)?;
+            options.finish()?;
+            let images = read_cell_images(input, &quantization)?;
+            let (mut ncer, ncgr) = Ncer::from_images(&images, metadata)?;
+            if vram_transfer {
+                ncer.compute_vram_transfer_data(&ncgr)?;
+            }
+            ncer.write_to_file(output)?;
+            ncgr.write_to_file(ncgr_path)?;
+        }
+        "nanr2json" => {
+            options.finish()?;
+            let json = Nanr::read_from_compressed_file(input)?.to_json()?;
+            write_text(output, &json)?;
+        }
+        "json2nanr" => {
+            options.finish()?;
+            Nanr::from_json(&read_text(input)?)?.write_to_file(output)?;
+        }
+        "nscr2png" => {
+            let ncgr = Ncgr::read_from_compressed_file(options.required_value("ncgr")?)?;
+            let palette_path = options.required_value("palette")?.to_string();
+            let palette = read_palette(&palette_path, color_scaling(&mut options)?)?;
+            let palette = palette_bank(palette, &mut options)?;
+            let transparent = options.flag("transparent")?;
+            options.finish()?;
+            let nscr = Nscr::read_from_compressed_file(input)?;
+            let palette = match transparent {
+                true => palette.with_transparent_first_colors(nscr.metadata().texture_format),
+                false => palette,
+            };
+            let tileset = tileset_image(&ncgr).with_palette(palette);
+            let image = nscr.to_image(&tileset)?;
+            Png::from_image(image)
+                .with_origin(input)
+                .write_to_file(output)?;
+        }
+        _ => return Err(CliError::Usage(format!("unknown command {:?}", command))),
+    }
+
+    Ok(())
+}
+
+fn read_text(path: &str) -> Result<String, CliError> {
+    std::fs::read_to_string(path).map_err(|e| nitrogfx::Error::Io(e).into())
+}
+
+fn write_text(path: &str, text: &str) -> Result<(), CliError> {
+    std::fs::write(path, text).map_err(|e| nitrogfx::Error::Io(e).into())
+}
+
+/// Reads a palette from either an NCLR or a palette file, based on its
+/// extension. The colors of an NCLR are converted with `scaling`.
+fn read_palette(path: &str, scaling: ColorScaling) -> Result<Palette, CliError> {
+    let is_nclr = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(Nclr::extension()));
+    if is_nclr {
+        Ok(Nclr::read_from_compressed_file(path)?.to_palette_with_scaling(scaling))
+    } else {
+        read_palette_file(path, infer_palette_format(path, true), scaling)
+    }
+}
+
+/// Palette file formats other than NCLR.
+#[derive(Clone, Copy)]
+enum PaletteFileFormat {
+    Raw,
+    Jasc,
+    Riff,
+    Gpl,
+    Act,
+    Aco,
+    PaintNet,
+}
+
+/// Returns the palette file format given with `--format`, or else the one
+/// inferred from `path`.
+fn palette_format(
+    path: &str,
+    is_input: bool,
+    options: &mut Options,
+) -> Result<PaletteFileFormat, CliError> {
+    Ok(options
+        .parsed_value("format", parse_palette_format)?
+        .unwrap_or_else(|| infer_palette_format(path, is_input)))
+}
+
+/// Infers the palette file format from the extension of `path`. As both JASC
+/// and RIFF palettes use `.pal`, input files are told apart by their magic,
+/// and output files default to JASC.
+fn infer_palette_format(path: &str, is_input: bool) -> PaletteFileFormat {
+    let extension = Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "gbapal" | "bin" | "nbfp" => PaletteFileFormat::Raw,
+        "gpl" => PaletteFileFormat::Gpl,
+        "act" => PaletteFileFormat::Act,
+        "aco" => PaletteFileFormat::Aco,
+        "txt" => PaletteFileFormat::PaintNet,
+        _ if is_input && std::fs::read(path).is_ok_and(|data| data.starts_with(b"RIFF")) => {
+            PaletteFileFormat::Riff
+        }
+        _ => PaletteFileFormat::Jasc,
+    }
+}
+
+/// Reads a palette file. The colors of a raw palette are converted with
+/// `scaling`.
+fn read_palette_file(
+    path: &str,
+    format: PaletteFileFormat,
+    scaling: ColorScaling,
+) -> Result<Palette, CliError> {
+    Ok(match format {
+        PaletteFileFormat::Raw => RawPalette::read_from_file(path)?
+            .to_palette_with_options(RawPaletteOptions::default().with_color_scaling(scaling)),
+        PaletteFileFormat::Jasc => Jasc::read_from_file(path)?.to_palette(),
+        PaletteFileFormat::Riff => RiffPal::read_from_file(path)?.to_palette(),
+        PaletteFileFormat::Gpl => Gpl::read_from_file(path)?.to_palette(),
+        PaletteFileFormat::Act => Act::read_from_file(path)?.to_palette(),
+        PaletteFileFormat::Aco => Aco::read_from_file(path)?.to_palette(),
+        PaletteFileFormat::PaintNet => PaintNet::read_from_file(path)?.to_palette(),
+    })
+}
+
+fn write_palette_file(
+    path: &str,
+    palette: Palette,
+    format: PaletteFileFormat,
+    raw_options: RawPaletteOptions,
+) -> Result<(), CliError> {
+    match format {
+        PaletteFileFormat::Raw => {
+            warn_lossy_colors(&palette, raw_options.color_scaling);
+            RawPalette::from_palette_with_options(palette, raw_options).write_to_file(path)?
+        }
+        PaletteFileFormat::Jasc => Jasc::from_palette(palette).write_to_file(path)?,
+        PaletteFileFormat::Riff => RiffPal::from_palette(palette).write_to_file(path)?,
+        PaletteFileFormat::Gpl => Gpl::from_palette(palette).write_to_file(path)?,
+        PaletteFileFormat::Act => Act::from_palette(palette).write_to_file(path)?,
+        PaletteFileFormat::Aco => Aco::from_palette(palette).write_to_file(path)?,
+        PaletteFileFormat::PaintNet => PaintNet::from_palette(palette).write_to_file(path)?,
+    }
+    Ok(())
+}
+
+/// Builds an NCLR, warning about any colors that cannot be represented
+/// exactly.
+fn nclr_from_palette(palette: Palette, metadata: NclrMetadata) -> Nclr {
+    warn_lossy_colors(&palette, metadata.color_scaling);
+    Nclr::from_palette(palette, metadata)
+}
+
+fn warn_lossy_colors(palette: &Palette, scaling: ColorScaling) {
+    let lossy_colors = palette.lossy_colors(scaling);
+    if !lossy_colors.is_empty() {
+        eprintln!(
+            "warning: colors {:?} cannot be represented exactly and have been reduced",
+            lossy_colors
+        );
+    }
+}
+
+/// Returns the color scaling given with `--color-scaling`, which defaults to
+/// [`ColorScaling::FullRange`] so that white is exported as pure white.
+fn color_scaling(options: &mut Options) -> Result<ColorScaling, CliError> {
+    Ok(options
+        .parsed_value("color-scaling", parse_color_scaling)?
+        .unwrap_or(ColorScaling::FullRange))
+}
+
+/// Selects the 256-color bank given with `--bank`, if any.
+fn palette_bank(palette: Palette, options: &mut Options) -> Result<Palette, CliError> {
+    match options.parsed_value("bank", |value| value.parse::<usize>().ok())? {
+        Some(bank) => palette.extended_bank(bank).ok_or_else(|| {
+            CliError::Usage(format!(
+                "palette has {} banks, so there is no bank {}",
+                palette.num_extended_banks(),
+                bank
+            ))
+        }),
+        None => Ok(palette),
+    }
+}
+
+/// Returns how truecolor PNGs are converted to indexed images, based on the
+/// quantization options and the texture format they are converted to.
+fn quantization(
+    texture_format: NtrTextureFormat,
+    options: &mut Options,
+) -> Result<Quantization, CliError> {
+    let scaling = color_scaling(options)?;
+    let nearest = options.flag("nearest")?;
+    let quantization = match options.value("palette")?.map(str::to_string) {
+        Some(path) => {
+            let palette = palette_bank(read_palette(&path, scaling)?, options)?;
+            match nearest {
+                true => Quantization::nearest(palette),
+                false => Quantization::exact(palette),
+            }
+        }
+        None if nearest => return Err(CliError::Usage("--nearest requires --palette".to_string())),
+        None => match options.parsed_value("colors", |value| value.parse::<usize>().ok())? {
+            Some(colors) => Quantization::automatic(colors),
+            None => Quantization::for_texture_format(texture_format),
+        },
+    };
+    let dithering = options
+        .parsed_value("dither", parse_dithering)?
+        .unwrap_or_default();
+    let transparent_index = options
+        .parsed_value("transparent-index", |value| value.parse::<u8>().ok())?
+        .unwrap_or(0);
+    Ok(quantization
+        .with_color_scaling(scaling)
+        .with_dithering(dithering)
+        .with_transparent_index(transparent_index))
+}
+
+/// Reads the images of the cells, either from a single PNG or from a JSON
+/// array of PNG paths relative to the JSON file.
+fn read_cell_images(path: &str, quantization: &Quantization) -> Result<Vec<Image>, CliError> {
+    let read_image = |path: &Path| -> Result<Image, CliError> {
+        Ok(Png::read_from_file(path)?.to_image_with_quantization(quantization)?)
+    };
+    let is_json = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if !is_json {
+        return Ok(vec![read_image(Path::new(path))?]);
+    }
+    let paths =
+        serde_json::from_str::<Vec<String>>(&read_text(path)?).map_err(nitrogfx::Error::from)?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    paths
+        .iter()
+        .map(|png_path| read_image(&directory.join(png_path)))
+        .collect()
+}
+
+/// Returns the tiles of the NCGR as an image, regardless of mapping type.
+fn tileset_image(ncgr: &Ncgr) -> Image {
+    match ncgr.metadata().mapping_type {
+        NtrMappingType::Mode2D => ncgr.to_image(),
+        _ => ncgr.to_image_with_width(8),
+    }
+}
+
+/// Applies the NCGR options on top of `metadata`.
+fn ncgr_metadata(
+    mut metadata: NcgrMetadata,
+    options: &mut Options,
+) -> Result<NcgrMetadata, CliError> {
+    if let Some(version) = options.parsed_value("version", parse_version)? {
+        metadata = metadata.with_version(version);
+    }
+    if let Some(texture_format) = options.parsed_value("texture-format", parse_texture_format)? {
+        metadata = metadata.with_texture_format(texture_format);
+    }
+    if let Some(mapping_type) = options.parsed_value("mapping-type", parse_mapping_type)? {
+        metadata = metadata.with_mapping_type(mapping_type);
+    }
+    if let Some(character_format) =
+        options.parsed_value("character-format", parse_character_format)?
+    {
+        metadata = metadata.with_character_format(character_format);
+    }
+    if options.flag("cpos")? {
+        metadata = metadata.include_cpos(true);
+    }
+    Ok(metadata)
+}
+
+fn nclr_metadata(options: &mut Options) -> Result<NclrMetadata, CliError> {
+    let mut metadata = NclrMetadata::default();
+    if let Some(version) = options.parsed_value("version", parse_version)? {
+        metadata = metadata.with_version(version);
+    }
+    if let Some(texture_format) = options.parsed_value("texture-format", parse_texture_format)? {
+        metadata = metadata.with_texture_format(texture_format);
+    }
+    if let Some(value) = options.parsed_value("pltt-0002", parse_u16)? {
+        metadata = metadata.pltt_0002(value);
+    }
+    if let Some(palette_indexes) = options.parsed_value("pcmp", |value| {
+        value.split(',').map(parse_u16).collect::<Option<Vec<_>>>()
+    })? {
+        metadata = metadata.with_palette_indexes(palette_indexes);
+    }
+    metadata = metadata.with_color_scaling(color_scaling(options)?);
+    Ok(metadata
+        .extended(options.flag("extended")?)
+        .invert_size(options.flag("invert-size")?)
+        .high_color_bit(options.flag("high-color-bit")?)
+        .high_bit_as_alpha(options.flag("high-bit-as-alpha")?))
+}
+
+fn parse_u16(value: &str) -> Option<u16> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_version(value: &str) -> Option<NtrFileVersion> {
+    match value {
+        "100" => Some(NtrFileVersion::Version0100),
+        "101" => Some(NtrFileVersion::Version0101),
+        _ => None,
+    }
+}
+
+fn parse_texture_format(value: &str) -> Option<NtrTextureFormat> {
+    match value {
+        "palette16" => Some(NtrTextureFormat::Palette16),
+        "palette256" => Some(NtrTextureFormat::Palette256),
+        _ => None,
+    }
+}
+
+fn parse_palette_format(value: &str) -> Option<PaletteFileFormat> {
+    match value {
+        "raw" => Some(PaletteFileFormat::Raw),
+        "jasc" => Some(PaletteFileFormat::Jasc),
+        "riff" => Some(PaletteFileFormat::Riff),
+        "gpl" => Some(PaletteFileFormat::Gpl),
+        "act" => Some(PaletteFileFormat::Act),
+        "aco" => Some(PaletteFileFormat::Aco),
+        "txt" => Some(PaletteFileFormat::PaintNet),
+        _ => None,
+    }
+}
+
+fn parse_dithering(value: &str) -> Option<Dithering> {
+    match value {
+        "none" => Some(Dithering::None),
+        "floyd-steinberg" => Some(Dithering::FloydSteinberg),
+        "bayer" => Some(Dithering::Bayer),
+        "atkinson" => Some(Dithering::Atkinson),
+        _ => None,
+    }
+}
+
+fn parse_color_scaling(value: &str) -> Option<ColorScaling> {
+    match value {
+        "x8" => Some(ColorScaling::Multiply8),
+        "full" => Some(ColorScaling::FullRange),
+        _ => value
+            .split(',')
+            .map(|value| value.parse::<u8>().ok())
+            .collect::<Option<Vec<_>>>()?
+            .try_into()
+            .ok()
+            .and_then(|table| ColorScaling::table(table).ok()),
+    }
+}
+
+fn parse_mapping_type(value: &str) -> Option<NtrMappingType> {
+    match value {
+        "2d" => Some(NtrMappingType::Mode2D),
+        "1d-32k" => Some(NtrMappingType::Mode1D32K),
+        "1d-64k" => Some(NtrMappingType::Mode1D64K),
+        "1d-128k" => Some(NtrMappingType::Mode1D128K),
+        "1d-256k" => Some(NtrMappingType::Mode1D256K),
+        _ => None,
+    }
+}
+
+fn parse_character_format(value: &str) -> Option<NtrCharacterFormat> {
+    match value {
+        "character" => Some(NtrCharacterFormat::Character),
+        "bitmap" => Some(NtrCharacterFormat::Bitmap),
+        "character256" => Some(NtrCharacterFormat::Character256),
+        _ => None,
+    }
+}
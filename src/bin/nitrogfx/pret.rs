@@ -0,0 +1,335 @@
+//! Compatibility with the argument syntax of pret's C `nitrogfx`, i.e.
+//! `nitrogfx <input> <output> [options]`, where the conversion is inferred
+//! from the file extensions.
+//!
+//! Options that cannot be represented by this crate are rejected rather than
+//! ignored, so that a build never silently produces different output.
+//! Colors are scaled with `x * 8` as pret does, not with the full range that
+//! the other commands default to.
+//!
+//! NCER and NANR files are converted to and from pret's JSON schema (see
+//! [`json`]). JSON files in the schema of this crate, as written by
+//! `ncer2json` and `nanr2json`, are accepted as input too.
+
+mod json;
+
+use std::path::Path;
+
+use nitrogfx::{
+    ColorScaling, FileFormat, Image, Jasc, Nanr, Ncer, Ncgr, NcgrMetadata, Nclr, NclrMetadata,
+    NtrCharacterFormat, NtrFileVersion, NtrMappingType, NtrTextureFormat, Png, Quantization,
+};
+
+use crate::{nclr_from_palette, read_palette, read_text, write_text, CliError};
+
+/// Options of pret's `nitrogfx` that have no equivalent here.
+const UNSUPPORTED_OPTIONS: &[&str] = &["-handleempty"];
+
+struct PretArgs<'a> {
+    args: std::slice::Iter<'a, String>,
+}
+
+impl<'a> PretArgs<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        self.args.next().map(String::as_str)
+    }
+
+    fn value(&mut self, option: &str) -> Result<&'a str, CliError> {
+        self.next()
+            .ok_or_else(|| CliError::Usage(format!("{} requires a value", option)))
+    }
+
+    fn parsed_value<T: std::str::FromStr>(&mut self, option: &str) -> Result<T, CliError> {
+        let value = self.value(option)?;
+        value
+            .parse()
+            .map_err(|_| CliError::Usage(format!("invalid value {:?} for {}", value, option)))
+    }
+}
+
+fn unknown_option(option: &str) -> CliError {
+    if UNSUPPORTED_OPTIONS.contains(&option) {
+        CliError::Usage(format!("{} is not supported", option))
+    } else {
+        CliError::Usage(format!("unknown option {}", option))
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+pub fn run(input: &str, output: &str, args: &[String]) -> Result<(), CliError> {
+    let mut args = PretArgs { args: args.iter() };
+    match (extension(input).as_str(), extension(output).as_str()) {
+        ("png", "ncgr") => png_to_ncgr(input, output, &mut args),
+        ("ncgr", "png") => ncgr_to_png(input, output, &mut args),
+        ("pal", "nclr") => pal_to_nclr(input, output, &mut args),
+        ("nclr", "pal") => {
+            no_options(&mut args)?;
+            let palette = Nclr::read_from_compressed_file(input)?.to_palette();
+            Ok(Jasc::from_palette(palette).write_to_file(output)?)
+        }
+        ("json", "ncer") => json_to_ncer(input, output, &mut args),
+        ("ncer", "json") => {
+            no_options(&mut args)?;
+            let ncer = Ncer::read_from_compressed_file(input)?;
+            write_text(output, &json::ncer_to_json(&ncer)?)
+        }
+        ("json", "nanr") => {
+            let mut byte_order = true;
+            while let Some(option) = args.next() {
+                match option {
+                    "-nobyteorder" => byte_order = false,
+                    _ => return Err(unknown_option(option)),
+                }
+            }
+            let text = read_text(input)?;
+            let nanr = match json::is_pret_schema(&text) {
+                true => json::nanr_from_json(&text)?,
+                false => Nanr::from_json(&text)?,
+            };
+            write_ntr_file(output, nanr.write_to_data()?, byte_order)
+        }
+        ("nanr", "json") => {
+            no_options(&mut args)?;
+            let nanr = Nanr::read_from_compressed_file(input)?;
+            write_text(output, &json::nanr_to_json(&nanr)?)
+        }
+        (input_ext, output_ext) => Err(CliError::Usage(format!(
+            "don't know how to convert .{} to .{}",
+            input_ext, output_ext
+        ))),
+    }
+}
+
+/// Writes an NTR file. Without `byte_order`, the byte order mark is stored as
+/// `FE FF` instead of `FF FE`, as pret does with `-nobyteorder`.
+fn write_ntr_file(path: &str, mut data: Vec<u8>, byte_order: bool) -> Result<(), CliError> {
+    if !byte_order {
+        data[4..6].copy_from_slice(&0xFFFE_u16.to_le_bytes());
+    }
+    std::fs::write(path, data).map_err(|e| nitrogfx::Error::Io(e).into())
+}
+
+fn json_to_ncer(input: &str, output: &str, args: &mut PretArgs) -> Result<(), CliError> {
+    let mut cell_bounding = false;
+    let mut vram = false;
+    let mut byte_order = true;
+    while let Some(option) = args.next() {
+        match option {
+            "-cellbounding" => cell_bounding = true,
+            "-vram" => vram = true,
+            "-nobyteorder" => byte_order = false,
+            _ => return Err(unknown_option(option)),
+        }
+    }
+
+    let text = read_text(input)?;
+    let mut ncer = match json::is_pret_schema(&text) {
+        true => json::ncer_from_json(&text, vram)?,
+        false => Ncer::from_json(&text)?,
+    };
+    if vram && ncer.vram_data().is_none() {
+        return Err(CliError::Usage(format!(
+            "-vram requires VRAM transfer data in {}, as there is no NCGR to compute it from",
+            input
+        )));
+    }
+    if cell_bounding {
+        ncer.recompute_bounds();
+    }
+    write_ntr_file(output, ncer.write_to_data()?, byte_order)
+}
+
+fn no_options(args: &mut PretArgs) -> Result<(), CliError> {
+    match args.next() {
+        Some(option) => Err(unknown_option(option)),
+        None => Ok(()),
+    }
+}
+
+fn texture_format(bit_depth: u32) -> Result<NtrTextureFormat, CliError> {
+    match bit_depth {
+        4 => Ok(NtrTextureFormat::Palette16),
+        8 => Ok(NtrTextureFormat::Palette256),
+        _ => Err(CliError::Usage(format!(
+            "invalid value {} for -bitdepth",
+            bit_depth
+        ))),
+    }
+}
+
+fn png_to_ncgr(input: &str, output: &str, args: &mut PretArgs) -> Result<(), CliError> {
+    let mut metadata = NcgrMetadata::default();
+    let mut clobber_size = false;
+    let mut num_tiles = None;
+    let mut metatile_width = 1;
+    let mut metatile_height = 1;
+    let mut byte_order = true;
+    let mut wrong_size = false;
+    while let Some(option) = args.next() {
+        match option {
+            "-bitdepth" => {
+                metadata = metadata.with_texture_format(texture_format(args.parsed_value(option)?)?)
+            }
+            "-version101" => metadata = metadata.with_version(NtrFileVersion::Version0101),
+            "-sopc" => metadata = metadata.include_cpos(true),
+            "-scanned" => metadata = metadata.with_character_format(NtrCharacterFormat::Bitmap),
+            "-clobbersize" => clobber_size = true,
+            "-mappingtype" => {
+                metadata = metadata.with_mapping_type(match args.parsed_value::<u32>(option)? {
+                    0 => NtrMappingType::Mode2D,
+                    32 => NtrMappingType::Mode1D32K,
+                    64 => NtrMappingType::Mode1D64K,
+                    128 => NtrMappingType::Mode1D128K,
+                    256 => NtrMappingType::Mode1D256K,
+                    value => {
+                        return Err(CliError::Usage(format!(
+                            "invalid value {} for -mappingtype",
+                            value
+                        )))
+                    }
+                })
+            }
+            "-num_tiles" => num_tiles = Some(args.parsed_value(option)?),
+            "-mwidth" => metatile_width = args.parsed_value(option)?,
+            "-mheight" => metatile_height = args.parsed_value(option)?,
+            "-nobyteorder" => byte_order = false,
+            "-wrongsize" => wrong_size = true,
+            _ => return Err(unknown_option(option)),
+        }
+    }
+
+    // The dimensions can only be cleared (i.e. set to 0xFFFF) in 1D mapping,
+    // where pret stores them unless -clobbersize is given.
+    match metadata.mapping_type {
+        NtrMappingType::Mode2D if clobber_size => {
+            return Err(CliError::Usage(
+                "-clobbersize requires -mappingtype to be 32, 64, 128 or 256".to_string(),
+            ))
+        }
+        NtrMappingType::Mode2D => {}
+        _ => metadata = metadata.store_dimensions(!clobber_size),
+    }
+
+    let quantization = Quantization::for_texture_format(metadata.texture_format);
+    let image = Png::read_from_file(input)?.to_image_with_quantization(&quantization)?;
+    let image = metatiles_to_rows(&image, metatile_width, metatile_height)?;
+    let mut ncgr = Ncgr::from_image(image, metadata);
+    if let Some(num_tiles) = num_tiles {
+        ncgr = ncgr.with_num_tiles(num_tiles)?;
+    }
+
+    let mut data = ncgr.write_to_data()?;
+    if wrong_size {
+        // pret's -wrongsize stores a file size 8 bytes too small, as some
+        // retail files do.
+        let file_size = u32::from_le_bytes(data[8..12].try_into().unwrap());
+        data[8..12].copy_from_slice(&(file_size - 8).to_le_bytes());
+    }
+    write_ntr_file(output, data, byte_order)
+}
+
+/// Rearranges the tiles of the image so that those of each metatile of
+/// `width` x `height` tiles come one after the other in reading order, which
+/// is the order pret stores them in with `-mwidth` and `-mheight`. Metatiles
+/// are taken row by row, as are the tiles within each of them.
+fn metatiles_to_rows(image: &Image, width: usize, height: usize) -> Result<Image, CliError> {
+    let width_in_tiles = image.width() / 8;
+    let height_in_tiles = image.height() / 8;
+    if width == 0
+        || height == 0
+        || !width_in_tiles.is_multiple_of(width)
+        || !height_in_tiles.is_multiple_of(height)
+    {
+        return Err(CliError::Usage(format!(
+            "an image of {}x{} tiles cannot be split into metatiles of {}x{} tiles",
+            width_in_tiles, height_in_tiles, width, height
+        )));
+    }
+
+    let metatiles_per_row = width_in_tiles / width;
+    let mut pixels = vec![0; image.pixels().len()];
+    for i in 0..width_in_tiles * height_in_tiles {
+        let (metatile, tile) = (i / (width * height), i % (width * height));
+        let source_x = metatile % metatiles_per_row * width + tile % width;
+        let source_y = metatile / metatiles_per_row * height + tile / width;
+        let (x, y) = (i % width_in_tiles, i / width_in_tiles);
+        for row in 0..8 {
+            let source = (source_y * 8 + row) * image.width() + source_x * 8;
+            let destination = (y * 8 + row) * image.width() + x * 8;
+            pixels[destination..destination + 8]
+                .copy_from_slice(&image.pixels()[source..source + 8]);
+        }
+    }
+    Ok(Image::new(image.width(), &pixels, image.palette()))
+}
+
+fn ncgr_to_png(input: &str, output: &str, args: &mut PretArgs) -> Result<(), CliError> {
+    let mut palette = None;
+    let mut width_in_tiles = 1;
+    while let Some(option) = args.next() {
+        match option {
+            "-palette" => {
+                palette = Some(read_palette(args.value(option)?, ColorScaling::Multiply8)?)
+            }
+            "-width" => width_in_tiles = args.parsed_value(option)?,
+            _ => return Err(unknown_option(option)),
+        }
+    }
+
+    let ncgr = Ncgr::read_from_compressed_file(input)?;
+    let image = match ncgr.metadata().mapping_type {
+        NtrMappingType::Mode2D => ncgr.to_image(),
+        _ => ncgr.to_image_with_width(width_in_tiles * 8),
+    };
+    let image = match palette {
+        Some(palette) => image.with_palette(palette),
+        None => image,
+    };
+    Ok(Png::from_image(image).write_to_file(output)?)
+}
+
+fn pal_to_nclr(input: &str, output: &str, args: &mut PretArgs) -> Result<(), CliError> {
+    let mut metadata = NclrMetadata::default();
+    let mut bit_depth = 4;
+    let mut pad = true;
+    let mut pcmp = false;
+    let mut byte_order = true;
+    while let Some(option) = args.next() {
+        match option {
+            "-bitdepth" => bit_depth = args.parsed_value(option)?,
+            "-version101" => metadata = metadata.with_version(NtrFileVersion::Version0101),
+            "-ir" => metadata = metadata.invert_size(true),
+            "-nopad" => pad = false,
+            "-pcmp" => pcmp = true,
+            "-ncpr" => metadata = metadata.ncpr(true),
+            "-nobyteorder" => byte_order = false,
+            _ => return Err(unknown_option(option)),
+        }
+    }
+    metadata = metadata.with_texture_format(texture_format(bit_depth)?);
+
+    let mut palette = Jasc::read_from_file(input)?.to_palette();
+    let is_256_colors = matches!(metadata.texture_format, NtrTextureFormat::Palette256);
+    if pad {
+        palette = match is_256_colors {
+            true => palette.padded_to_extended_banks(),
+            false => palette.padded_to_banks(),
+        };
+    }
+    if pcmp {
+        let num_palettes = match is_256_colors {
+            true => palette.num_extended_banks(),
+            false => palette.num_banks(),
+        };
+        metadata = metadata.with_palette_indexes((0..num_palettes as u16).collect());
+    }
+
+    let data = nclr_from_palette(palette, metadata).write_to_data()?;
+    write_ntr_file(output, data, byte_order)
+}
@@ -0,0 +1,683 @@
+//! The JSON schema of pret's `nitrogfx` for NCER and NANR files, mapped onto
+//! [`Ncer`] and [`Nanr`].
+//!
+//! Cell attributes are read either from the raw `readOnly` value or from a
+//! `cellAttrs` object, and are written as `readOnly`. Objects are stored as
+//! `OAM0`, `OAM1`, etc. in each cell, with the fields of their three OAM
+//! attributes. VRAM transfer data is stored in `vramTransferMaxSize` and
+//! `transferData` when `vramTransferEnabled` is set.
+//!
+//! Data that the schema has no place for, e.g. user extended attributes, is
+//! an error when writing the JSON rather than being dropped.
+
+use std::collections::BTreeMap;
+
+use nitrogfx::{
+    AnimationElement, AnimationType, BoundingRectangle, Cell, CellAttribute, CellVramTransferData,
+    Error, Nanr, Ncer, NtrFileVersion, NtrMappingType, OamData, OamSize, ObjMode, PlaybackMode,
+    Result, VramData,
+};
+use serde::{de::Error as _, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidInput {
+        reason: reason.into(),
+    }
+}
+
+/// Checks a count stored in the JSON against the number of entries it counts.
+fn expect_count(name: &str, count: usize, actual: usize) -> Result<()> {
+    if count != actual {
+        return Err(invalid(format!(
+            "{} is {}, but there are {} entries",
+            name, count, actual
+        )));
+    }
+    Ok(())
+}
+
+/// Whether the JSON uses pret's schema rather than that of this crate.
+pub fn is_pret_schema(json: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(json)
+        .is_ok_and(|value| value.get("labelEnabled").is_some())
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretNcer {
+    label_enabled: bool,
+    extended: bool,
+    #[serde(default)]
+    vram_transfer_enabled: bool,
+    cell_count: usize,
+    mapping_type: u32,
+    cells: Vec<PretCell>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vram_transfer_max_size: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transfer_data: Option<Vec<PretVramTransfer>>,
+    #[serde(default)]
+    label_count: usize,
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretCell {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    read_only: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cell_attrs: Option<PretCellAttrs>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_x: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_y: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_x: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_y: Option<i16>,
+    oam_count: usize,
+    #[serde(flatten)]
+    oams: PretOams,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretCellAttrs {
+    h_flip: bool,
+    v_flip: bool,
+    #[serde(default)]
+    hv_flip: bool,
+    #[serde(default)]
+    bounding_rect: bool,
+    bounding_sphere_radius: u16,
+}
+
+/// The objects of a cell, stored as `OAM0`, `OAM1`, etc.
+struct PretOams(Vec<PretOam>);
+
+impl Serialize for PretOams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (i, oam) in self.0.iter().enumerate() {
+            map.serialize_entry(&format!("OAM{}", i), oam)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for PretOams {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let mut oams = BTreeMap::new();
+        for (key, oam) in BTreeMap::<String, PretOam>::deserialize(deserializer)? {
+            let index = key
+                .strip_prefix("OAM")
+                .and_then(|index| index.parse::<usize>().ok())
+                .ok_or_else(|| D::Error::custom(format!("unknown field `{}`", key)))?;
+            oams.insert(index, oam);
+        }
+        if oams.keys().enumerate().any(|(i, index)| i != *index) {
+            return Err(D::Error::custom("objects are not numbered from OAM0 on"));
+        }
+        Ok(Self(oams.into_values().collect()))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PretOam {
+    attr0: PretAttr0,
+    attr1: PretAttr1,
+    attr2: PretAttr2,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PretAttr0 {
+    /// The raw 8 bits, although negative values are accepted too.
+    y_coordinate: i16,
+    rotation: bool,
+    size_disable: bool,
+    mode: u16,
+    mosaic: bool,
+    colours: u16,
+    shape: u8,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PretAttr1 {
+    /// The raw 9 bits, although negative values are accepted too.
+    x_coordinate: i16,
+    rotation_scaling: u8,
+    size: u8,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PretAttr2 {
+    char_name: u16,
+    priority: u8,
+    palette: u8,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretVramTransfer {
+    offset: u32,
+    size: u32,
+}
+
+impl PretOam {
+    fn to_oam_data(&self) -> Result<OamData> {
+        let PretOam {
+            attr0,
+            attr1,
+            attr2,
+        } = self;
+        let oam_size = OamSize::try_from((attr0.shape, attr1.size)).map_err(|_| {
+            invalid(format!(
+                "invalid OAM shape {} and size {}",
+                attr0.shape, attr1.size
+            ))
+        })?;
+        let y = match attr0.y_coordinate {
+            y @ -128..=127 => y as i8,
+            y @ 128..=255 => y as u8 as i8,
+            y => return Err(invalid(format!("YCoordinate {} is out of range", y))),
+        };
+        let x = match attr1.x_coordinate {
+            x @ -256..=255 => x,
+            x @ 256..=511 => x - 512,
+            x => return Err(invalid(format!("XCoordinate {} is out of range", x))),
+        };
+        let color_mode = match attr0.colours {
+            16 => 0,
+            256 => 1,
+            colours => return Err(invalid(format!("invalid Colours {}", colours))),
+        };
+        let mode = ObjMode::try_from(attr0.mode)
+            .map_err(|_| invalid(format!("invalid OAM mode {}", attr0.mode)))?;
+
+        let oam = OamData::new(oam_size, attr2.char_name)
+            .with_position(x, y)
+            .with_affine(attr0.rotation)
+            .with_mode(mode)
+            .with_mosaic(attr0.mosaic)
+            .with_color_mode(color_mode)
+            .with_priority(attr2.priority)
+            .with_palette_number(attr2.palette);
+        // Affine objects use the bits of the flips for the index of their
+        // affine parameters, and the disable bit for the double size flag.
+        Ok(match attr0.rotation {
+            true => oam
+                .with_affine_index(attr1.rotation_scaling)
+                .with_double_size(attr0.size_disable),
+            false => oam
+                .with_disable(attr0.size_disable)
+                .with_unused_attr1_bits(attr1.rotation_scaling & 0b00111)
+                .with_h_flip(attr1.rotation_scaling & 0b01000 != 0)
+                .with_v_flip(attr1.rotation_scaling & 0b10000 != 0),
+        })
+    }
+
+    fn from_oam_data(oam: &OamData) -> Self {
+        let (shape, size) = oam.oam_size().into();
+        let (size_disable, rotation_scaling) = match oam.affine() {
+            true => (oam.double_size(), oam.affine_index()),
+            false => (
+                oam.disable(),
+                oam.unused_attr1_bits()
+                    | (u8::from(oam.h_flip()) << 3)
+                    | (u8::from(oam.v_flip()) << 4),
+            ),
+        };
+        Self {
+            attr0: PretAttr0 {
+                y_coordinate: (oam.y() as u8).into(),
+                rotation: oam.affine(),
+                size_disable,
+                mode: oam.mode().into(),
+                mosaic: oam.mosaic(),
+                colours: match oam.color_mode() {
+                    0 => 16,
+                    _ => 256,
+                },
+                shape,
+            },
+            attr1: PretAttr1 {
+                x_coordinate: oam.x().rem_euclid(512),
+                rotation_scaling,
+                size,
+            },
+            attr2: PretAttr2 {
+                char_name: oam.tile_number(),
+                priority: oam.priority(),
+                palette: oam.palette_number(),
+            },
+        }
+    }
+}
+
+/// Reads an NCER from pret's JSON. VRAM transfer data is included if either
+/// the JSON enables it or `vram` is set.
+pub fn ncer_from_json(json: &str, vram: bool) -> Result<Ncer> {
+    let pret = serde_json::from_str::<PretNcer>(json)?;
+    expect_count("cellCount", pret.cell_count, pret.cells.len())?;
+    if pret.label_enabled {
+        expect_count("labelCount", pret.label_count, pret.labels.len())?;
+    }
+    let mapping_type = NtrMappingType::try_from_u32_ncer(pret.mapping_type)
+        .map_err(|_| invalid(format!("invalid mappingType {}", pret.mapping_type)))?;
+
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, mapping_type);
+    for (i, pret_cell) in pret.cells.iter().enumerate() {
+        expect_count("oamCount", pret_cell.oam_count, pret_cell.oams.0.len())?;
+        let attribute = match (pret_cell.read_only, &pret_cell.cell_attrs) {
+            (Some(read_only), _) => CellAttribute::from(read_only),
+            (None, Some(attrs)) => CellAttribute::default()
+                .with_h_flip(attrs.h_flip)
+                .with_v_flip(attrs.v_flip)
+                .with_bounding_sphere_radius(attrs.bounding_sphere_radius),
+            (None, None) => {
+                return Err(invalid(format!(
+                    "cell {} has neither readOnly nor cellAttrs",
+                    i
+                )))
+            }
+        };
+        let bounding_rectangle = match pret.extended {
+            true => match (
+                pret_cell.min_x,
+                pret_cell.min_y,
+                pret_cell.max_x,
+                pret_cell.max_y,
+            ) {
+                (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) => {
+                    Some(BoundingRectangle::new(min_x, min_y, max_x, max_y))
+                }
+                _ => {
+                    return Err(invalid(format!(
+                        "cell {} of an extended NCER lacks minX, minY, maxX or maxY",
+                        i
+                    )))
+                }
+            },
+            false => None,
+        };
+        let oam_data = pret_cell
+            .oams
+            .0
+            .iter()
+            .map(PretOam::to_oam_data)
+            .collect::<Result<Vec<_>>>()?;
+        ncer.push_cell(
+            Cell::new(oam_data)
+                .with_bounding_rectangle(bounding_rectangle)
+                .with_attribute(attribute),
+        );
+    }
+    if pret.label_enabled {
+        *ncer.labels_mut() = pret.labels;
+    }
+
+    if pret.vram_transfer_enabled || vram {
+        let (Some(max_size), Some(transfer_data)) =
+            (pret.vram_transfer_max_size, pret.transfer_data)
+        else {
+            return Err(invalid(
+                "VRAM transfer requires vramTransferMaxSize and transferData, as there is no \
+                 NCGR to compute them from",
+            ));
+        };
+        let data = transfer_data
+            .iter()
+            .map(|transfer| CellVramTransferData::new(transfer.offset, transfer.size))
+            .collect();
+        ncer = ncer.with_vram_data(Some(VramData::new(max_size, data)));
+    }
+    Ok(ncer)
+}
+
+/// Writes an NCER as pret's JSON.
+pub fn ncer_to_json(ncer: &Ncer) -> Result<String> {
+    if ncer.user_extended_attributes().is_some() {
+        return Err(invalid(
+            "user extended attributes cannot be represented in pret's JSON schema",
+        ));
+    }
+    if ncer.user_extended_data() != [0, 0, 0, 0] {
+        return Err(invalid(
+            "user extended data cannot be represented in pret's JSON schema",
+        ));
+    }
+
+    let extended = ncer
+        .cells()
+        .first()
+        .is_some_and(|cell| cell.bounding_rectangle().is_some());
+    let cells = ncer
+        .cells()
+        .iter()
+        .map(|cell| {
+            let br = cell.bounding_rectangle();
+            PretCell {
+                read_only: Some(cell.attribute().into()),
+                cell_attrs: None,
+                max_x: br.map(|br| br.max_x()),
+                max_y: br.map(|br| br.max_y()),
+                min_x: br.map(|br| br.min_x()),
+                min_y: br.map(|br| br.min_y()),
+                oam_count: cell.oam_data().len(),
+                oams: PretOams(cell.oam_data().iter().map(PretOam::from_oam_data).collect()),
+            }
+        })
+        .collect::<Vec<_>>();
+    let vram_data = ncer.vram_data();
+    let pret = PretNcer {
+        label_enabled: !ncer.labels().is_empty(),
+        extended,
+        vram_transfer_enabled: vram_data.is_some(),
+        cell_count: cells.len(),
+        mapping_type: ncer.mapping_type().into_u32_ncer(),
+        cells,
+        vram_transfer_max_size: vram_data.map(VramData::max_size),
+        transfer_data: vram_data.map(|vram_data| {
+            vram_data
+                .data()
+                .iter()
+                .map(|transfer| PretVramTransfer {
+                    offset: transfer.src_offset(),
+                    size: transfer.size(),
+                })
+                .collect()
+        }),
+        label_count: ncer.labels().len(),
+        labels: ncer.labels().to_vec(),
+    };
+    Ok(serde_json::to_string_pretty(&pret)?)
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretNanr {
+    label_enabled: bool,
+    sequence_count: usize,
+    frame_count: usize,
+    sequences: Vec<PretSequence>,
+    result_count: usize,
+    animation_results: Vec<PretResult>,
+    #[serde(default)]
+    label_count: usize,
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretSequence {
+    frame_count: usize,
+    loop_start_frame: u16,
+    animation_element: u16,
+    animation_type: u16,
+    playback_mode: u32,
+    frame_data: Vec<PretFrame>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretFrame {
+    frame_delay: u16,
+    result_id: usize,
+}
+
+/// An animation result, whose `resultType` is the animation element it is
+/// used with, i.e. 0 for an index, 1 for SRT and 2 for T.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PretResult {
+    result_type: u16,
+    index: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rotation: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scale_x: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scale_y: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    position_x: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    position_y: Option<i16>,
+}
+
+// `Nanr` is only built from and converted to JSON, so pret's schema is mapped
+// onto the JSON of this crate, which the following types mirror.
+
+#[derive(Deserialize, Serialize)]
+struct NanrJson {
+    version: NtrFileVersion,
+    sequences: Vec<SequenceJson>,
+    results: Vec<ResultJson>,
+    user_extended_data: Vec<u8>,
+    labels: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SequenceJson {
+    loop_start_frame: u16,
+    animation_element: AnimationElement,
+    animation_type: AnimationType,
+    playback_mode: PlaybackMode,
+    frames: Vec<FrameJson>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct FrameJson {
+    result_id: usize,
+    frame_delay: u16,
+}
+
+#[derive(Deserialize, Serialize)]
+enum ResultJson {
+    Index {
+        index: u16,
+    },
+    IndexSrt {
+        index: u16,
+        rotation: u16,
+        scale_x: i32,
+        scale_y: i32,
+        position_x: i16,
+        position_y: i16,
+    },
+    IndexT {
+        index: u16,
+        position_x: i16,
+        position_y: i16,
+    },
+    Raw {
+        data: Vec<u8>,
+    },
+}
+
+impl PretResult {
+    fn to_result_json(&self) -> Result<ResultJson> {
+        let missing = |field: &str| {
+            invalid(format!(
+                "animation result with resultType {} lacks {}",
+                self.result_type, field
+            ))
+        };
+        let position_x = || self.position_x.ok_or_else(|| missing("positionX"));
+        let position_y = || self.position_y.ok_or_else(|| missing("positionY"));
+        Ok(match self.result_type {
+            0 => ResultJson::Index { index: self.index },
+            1 => ResultJson::IndexSrt {
+                index: self.index,
+                rotation: self.rotation.ok_or_else(|| missing("rotation"))?,
+                scale_x: self.scale_x.ok_or_else(|| missing("scaleX"))?,
+                scale_y: self.scale_y.ok_or_else(|| missing("scaleY"))?,
+                position_x: position_x()?,
+                position_y: position_y()?,
+            },
+            2 => ResultJson::IndexT {
+                index: self.index,
+                position_x: position_x()?,
+                position_y: position_y()?,
+            },
+            result_type => return Err(invalid(format!("invalid resultType {}", result_type))),
+        })
+    }
+
+    fn from_result_json(json: &ResultJson) -> Result<Self> {
+        let result = Self {
+            result_type: 0,
+            index: 0,
+            rotation: None,
+            scale_x: None,
+            scale_y: None,
+            position_x: None,
+            position_y: None,
+        };
+        Ok(match *json {
+            ResultJson::Index { index } => Self { index, ..result },
+            ResultJson::IndexSrt {
+                index,
+                rotation,
+                scale_x,
+                scale_y,
+                position_x,
+                position_y,
+            } => Self {
+                result_type: 1,
+                index,
+                rotation: Some(rotation),
+                scale_x: Some(scale_x),
+                scale_y: Some(scale_y),
+                position_x: Some(position_x),
+                position_y: Some(position_y),
+            },
+            ResultJson::IndexT {
+                index,
+                position_x,
+                position_y,
+            } => Self {
+                result_type: 2,
+                index,
+                position_x: Some(position_x),
+                position_y: Some(position_y),
+                ..result
+            },
+            ResultJson::Raw { .. } => {
+                return Err(invalid(
+                    "data between animation results cannot be represented in pret's JSON schema",
+                ))
+            }
+        })
+    }
+}
+
+/// Reads a NANR from pret's JSON.
+pub fn nanr_from_json(json: &str) -> Result<Nanr> {
+    let pret = serde_json::from_str::<PretNanr>(json)?;
+    expect_count("sequenceCount", pret.sequence_count, pret.sequences.len())?;
+    expect_count(
+        "resultCount",
+        pret.result_count,
+        pret.animation_results.len(),
+    )?;
+    let total_frames = pret.sequences.iter().map(|s| s.frame_data.len()).sum();
+    expect_count("frameCount", pret.frame_count, total_frames)?;
+    if pret.label_enabled {
+        expect_count("labelCount", pret.label_count, pret.labels.len())?;
+    }
+
+    let mut sequences = vec![];
+    for sequence in &pret.sequences {
+        expect_count(
+            "frameCount",
+            sequence.frame_count,
+            sequence.frame_data.len(),
+        )?;
+        let enum_error = |name: &str, value: u32| invalid(format!("invalid {} {}", name, value));
+        sequences.push(SequenceJson {
+            loop_start_frame: sequence.loop_start_frame,
+            animation_element: AnimationElement::try_from(sequence.animation_element)
+                .map_err(|e| enum_error("animationElement", e.value))?,
+            animation_type: AnimationType::try_from(sequence.animation_type)
+                .map_err(|e| enum_error("animationType", e.value))?,
+            playback_mode: PlaybackMode::try_from(sequence.playback_mode)
+                .map_err(|e| enum_error("playbackMode", e.value))?,
+            frames: sequence
+                .frame_data
+                .iter()
+                .map(|frame| FrameJson {
+                    result_id: frame.result_id,
+                    frame_delay: frame.frame_delay,
+                })
+                .collect(),
+        });
+    }
+    let nanr = NanrJson {
+        version: NtrFileVersion::Version0100,
+        sequences,
+        results: pret
+            .animation_results
+            .iter()
+            .map(PretResult::to_result_json)
+            .collect::<Result<_>>()?,
+        user_extended_data: vec![0, 0, 0, 0],
+        labels: match pret.label_enabled {
+            true => pret.labels,
+            false => vec![],
+        },
+    };
+    Nanr::from_json(&serde_json::to_string(&nanr)?)
+}
+
+/// Writes a NANR as pret's JSON.
+pub fn nanr_to_json(nanr: &Nanr) -> Result<String> {
+    let nanr = serde_json::from_str::<NanrJson>(&nanr.to_json()?)?;
+    if nanr.user_extended_data != [0, 0, 0, 0] {
+        return Err(invalid(
+            "user extended data cannot be represented in pret's JSON schema",
+        ));
+    }
+
+    let sequences = nanr
+        .sequences
+        .iter()
+        .map(|sequence| PretSequence {
+            frame_count: sequence.frames.len(),
+            loop_start_frame: sequence.loop_start_frame,
+            animation_element: sequence.animation_element.into(),
+            animation_type: sequence.animation_type.into(),
+            playback_mode: sequence.playback_mode.into(),
+            frame_data: sequence
+                .frames
+                .iter()
+                .map(|frame| PretFrame {
+                    frame_delay: frame.frame_delay,
+                    result_id: frame.result_id,
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let animation_results = nanr
+        .results
+        .iter()
+        .map(PretResult::from_result_json)
+        .collect::<Result<Vec<_>>>()?;
+    let pret = PretNanr {
+        label_enabled: !nanr.labels.is_empty(),
+        sequence_count: sequences.len(),
+        frame_count: sequences.iter().map(|s| s.frame_data.len()).sum(),
+        sequences,
+        result_count: animation_results.len(),
+        animation_results,
+        label_count: nanr.labels.len(),
+        labels: nanr.labels,
+    };
+    Ok(serde_json::to_string_pretty(&pret)?)
+}
@@ -0,0 +1,230 @@
+use crate::{
+    error::{Error, Result},
+    read_write_ext::{DataReader, WriteExt},
+};
+
+/// LZ77 variants supported by the DS BIOS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Matches of 3 to 18 bytes. This is the variant that the BIOS can
+    /// decompress directly into VRAM.
+    Lz10,
+
+    /// Matches of 3 to 65808 bytes.
+    Lz11,
+}
+
+impl Compression {
+    const WINDOW_SIZE: usize = 0x1000;
+
+    /// Returns the compression used by the data, based on its header.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.len() < 4 {
+            return None;
+        }
+        match data[0] {
+            0x10 => Some(Self::Lz10),
+            0x11 => Some(Self::Lz11),
+            _ => None,
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Self::Lz10 => 0x10,
+            Self::Lz11 => 0x11,
+        }
+    }
+
+    fn block(self) -> &'static str {
+        match self {
+            Self::Lz10 => "LZ10",
+            Self::Lz11 => "LZ11",
+        }
+    }
+
+    fn max_match_length(self) -> usize {
+        match self {
+            Self::Lz10 => 0x12,
+            Self::Lz11 => 0x10110,
+        }
+    }
+
+    /// Matches at a distance of 1 are avoided for LZ10, as the BIOS writes to
+    /// VRAM 16 bits at a time and would read back a byte it has not written yet.
+    fn min_match_distance(self) -> usize {
+        match self {
+            Self::Lz10 => 2,
+            Self::Lz11 => 1,
+        }
+    }
+
+    pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut reader = DataReader::new("LZ", data, 0);
+        let compression = match reader.read_u8()? {
+            0x10 => Self::Lz10,
+            0x11 => Self::Lz11,
+            value => {
+                return Err(reader.error_at(0, format!("unknown compression type {:#04x}", value)))
+            }
+        };
+        let mut reader = DataReader::new(compression.block(), data, 0);
+        let mut size: usize = (reader.read_u32()? >> 8).try_into().unwrap();
+        if size == 0 && compression == Self::Lz11 {
+            size = reader.read_u32()?.try_into().unwrap();
+        }
+
+        // The size comes from the header, so avoid trusting it for the
+        // initial allocation.
+        let mut decompressed = Vec::with_capacity(size.min(data.len() * 8));
+        while decompressed.len() < size {
+            let flags = reader.read_u8()?;
+            for bit in 0..8 {
+                if decompressed.len() >= size {
+                    break;
+                }
+                if flags & (0x80 >> bit) == 0 {
+                    decompressed.push(reader.read_u8()?);
+                    continue;
+                }
+
+                let offset = reader.offset();
+                let (length, distance) = compression.read_match(&mut reader)?;
+                if distance > decompressed.len() {
+                    return Err(reader.error_at(
+                        offset,
+                        format!(
+                            "match distance {} is before the start of the data",
+                            distance
+                        ),
+                    ));
+                }
+                for _ in 0..length.min(size - decompressed.len()) {
+                    decompressed.push(decompressed[decompressed.len() - distance]);
+                }
+            }
+        }
+
+        Ok(decompressed)
+    }
+
+    /// Compresses the data by greedily taking the longest match at each
+    /// position, preferring the closest one when there are several.
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut compressed = vec![];
+        match self {
+            // A size of 0 in the short header means that the extended header
+            // follows, so empty data needs the extended header too.
+            Self::Lz11 if data.is_empty() || data.len() > 0xFFFFFF => {
+                compressed.write_u32(self.id().into())?;
+                compressed.write_u32(data.len().try_into().unwrap())?;
+            }
+            _ => {
+                if data.len() > 0xFFFFFF {
+                    return Err(Error::invalid_input(format!(
+                        "{} bytes is too large to be compressed with {}",
+                        data.len(),
+                        self.block()
+                    )));
+                }
+                compressed
+                    .write_u32(u32::from(self.id()) | (u32::try_from(data.len()).unwrap() << 8))?;
+            }
+        }
+
+        let mut position = 0;
+        while position < data.len() {
+            let flags_position = compressed.len();
+            compressed.write_u8(0)?;
+            for bit in 0..8 {
+                if position >= data.len() {
+                    break;
+                }
+                let (length, distance) = self.find_match(data, position);
+                if length >= 3 {
+                    compressed[flags_position] |= 0x80 >> bit;
+                    self.write_match(&mut compressed, length, distance)?;
+                    position += length;
+                } else {
+                    compressed.write_u8(data[position])?;
+                    position += 1;
+                }
+            }
+        }
+
+        while !compressed.len().is_multiple_of(4) {
+            compressed.write_u8(0)?;
+        }
+
+        Ok(compressed)
+    }
+
+    fn find_match(self, data: &[u8], position: usize) -> (usize, usize) {
+        let max_length = self.max_match_length().min(data.len() - position);
+        let mut best = (0, 0);
+        for distance in self.min_match_distance()..=position.min(Self::WINDOW_SIZE) {
+            let start = position - distance;
+            let length = (0..max_length)
+                .take_while(|i| data[start + i] == data[position + i])
+                .count();
+            if length > best.0 {
+                best = (length, distance);
+                if length == max_length {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    fn read_match(self, reader: &mut DataReader) -> Result<(usize, usize)> {
+        let b0 = usize::from(reader.read_u8()?);
+        let b1 = usize::from(reader.read_u8()?);
+        Ok(match self {
+            Self::Lz10 => ((b0 >> 4) + 3, ((b0 & 0xF) << 8 | b1) + 1),
+            Self::Lz11 => match b0 >> 4 {
+                0 => {
+                    let b2 = usize::from(reader.read_u8()?);
+                    (
+                        ((b0 & 0xF) << 4 | b1 >> 4) + 0x11,
+                        ((b1 & 0xF) << 8 | b2) + 1,
+                    )
+                }
+                1 => {
+                    let b2 = usize::from(reader.read_u8()?);
+                    let b3 = usize::from(reader.read_u8()?);
+                    (
+                        ((b0 & 0xF) << 12 | b1 << 4 | b2 >> 4) + 0x111,
+                        ((b2 & 0xF) << 8 | b3) + 1,
+                    )
+                }
+                indicator => (indicator + 1, ((b0 & 0xF) << 8 | b1) + 1),
+            },
+        })
+    }
+
+    fn write_match(self, compressed: &mut Vec<u8>, length: usize, distance: usize) -> Result<()> {
+        let distance = distance - 1;
+        let bytes = match (self, length) {
+            (Self::Lz10, _) => vec![((length - 3) << 4 | distance >> 8), distance],
+            (Self::Lz11, 3..=0x10) => vec![((length - 1) << 4 | distance >> 8), distance],
+            (Self::Lz11, 0x11..=0x110) => {
+                let length = length - 0x11;
+                vec![length >> 4, ((length & 0xF) << 4 | distance >> 8), distance]
+            }
+            (Self::Lz11, _) => {
+                let length = length - 0x111;
+                vec![
+                    0x10 | length >> 12,
+                    length >> 4,
+                    ((length & 0xF) << 4 | distance >> 8),
+                    distance,
+                ]
+            }
+        };
+        for byte in bytes {
+            compressed.write_u8(byte as u8)?;
+        }
+        Ok(())
+    }
+}
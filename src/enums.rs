@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::error::InvalidEnumValue;
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum NtrFileVersion {
     Version0100,
@@ -12,26 +14,28 @@ impl Default for NtrFileVersion {
     }
 }
 
-impl From<u16> for NtrFileVersion {
-    fn from(value: u16) -> Self {
-        match value {
+impl TryFrom<u16> for NtrFileVersion {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
             0x0100 => Self::Version0100,
             0x0101 => Self::Version0101,
-            _ => panic!(),
-        }
+            _ => return Err(InvalidEnumValue::new("NtrFileVersion", value)),
+        })
     }
 }
 
-impl Into<u16> for NtrFileVersion {
-    fn into(self) -> u16 {
-        match self {
+impl From<NtrFileVersion> for u16 {
+    fn from(value: NtrFileVersion) -> Self {
+        match value {
             NtrFileVersion::Version0100 => 0x0100,
             NtrFileVersion::Version0101 => 0x0101,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum NtrTextureFormat {
     None,
     A3i5,
@@ -49,9 +53,11 @@ impl Default for NtrTextureFormat {
     }
 }
 
-impl From<u16> for NtrTextureFormat {
-    fn from(value: u16) -> Self {
-        match value {
+impl TryFrom<u16> for NtrTextureFormat {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
             0 => Self::None,
             1 => Self::A3i5,
             2 => Self::Palette4,
@@ -60,14 +66,14 @@ impl From<u16> for NtrTextureFormat {
             5 => Self::Compressed,
             6 => Self::A5i3,
             7 => Self::Direct,
-            _ => panic!(),
-        }
+            _ => return Err(InvalidEnumValue::new("NtrTextureFormat", value)),
+        })
     }
 }
 
-impl Into<u16> for NtrTextureFormat {
-    fn into(self) -> u16 {
-        match self {
+impl From<NtrTextureFormat> for u16 {
+    fn from(value: NtrTextureFormat) -> Self {
+        match value {
             NtrTextureFormat::None => 0,
             NtrTextureFormat::A3i5 => 1,
             NtrTextureFormat::Palette4 => 2,
@@ -96,15 +102,15 @@ impl Default for NtrMappingType {
 }
 
 impl NtrMappingType {
-    pub fn from_u32_ncgr(value: u32) -> Self {
-        match value {
+    pub fn try_from_u32_ncgr(value: u32) -> Result<Self, InvalidEnumValue> {
+        Ok(match value {
             0 => Self::Mode2D,
             0x00000010 => Self::Mode1D32K,
             0x00100010 => Self::Mode1D64K,
             0x00200010 => Self::Mode1D128K,
             0x00300010 => Self::Mode1D256K,
-            _ => panic!(),
-        }
+            _ => return Err(InvalidEnumValue::new("NtrMappingType", value)),
+        })
     }
 
     pub fn into_u32_ncgr(self) -> u32 {
@@ -117,15 +123,15 @@ impl NtrMappingType {
         }
     }
 
-    pub fn from_u32_ncer(value: u32) -> Self {
-        match value {
+    pub fn try_from_u32_ncer(value: u32) -> Result<Self, InvalidEnumValue> {
+        Ok(match value {
             0x00000000 => Self::Mode1D32K,
             0x00000001 => Self::Mode1D64K,
             0x00000002 => Self::Mode1D128K,
             0x00000003 => Self::Mode1D256K,
             0x00000004 => Self::Mode2D,
-            _ => panic!(),
-        }
+            _ => return Err(InvalidEnumValue::new("NtrMappingType", value)),
+        })
     }
 
     pub fn into_u32_ncer(self) -> u32 {
@@ -139,7 +145,7 @@ impl NtrMappingType {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum NtrCharacterFormat {
     /// Data is arranged in 8x8 tiles. Also sometimes known as "tiled".
     Character,
@@ -158,20 +164,22 @@ impl Default for NtrCharacterFormat {
     }
 }
 
-impl From<u32> for NtrCharacterFormat {
-    fn from(value: u32) -> Self {
-        match value {
+impl TryFrom<u32> for NtrCharacterFormat {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
             0 => Self::Character,
             1 => Self::Bitmap,
             256 => Self::Character256,
-            _ => panic!(),
-        }
+            _ => return Err(InvalidEnumValue::new("NtrCharacterFormat", value)),
+        })
     }
 }
 
-impl Into<u32> for NtrCharacterFormat {
-    fn into(self) -> u32 {
-        match self {
+impl From<NtrCharacterFormat> for u32 {
+    fn from(value: NtrCharacterFormat) -> Self {
+        match value {
             NtrCharacterFormat::Character => 0,
             NtrCharacterFormat::Bitmap => 1,
             NtrCharacterFormat::Character256 => 256,
@@ -195,10 +203,14 @@ pub enum OamSize {
     Oam32x64,
 }
 
-impl From<(u8, u8)> for OamSize {
-    fn from(value: (u8, u8)) -> Self {
+/// Converts a (shape, size) pair. The value of the error holds the shape in
+/// its upper byte and the size in its lower byte.
+impl TryFrom<(u8, u8)> for OamSize {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: (u8, u8)) -> Result<Self, Self::Error> {
         let (shape, size) = value;
-        match (shape, size) {
+        Ok(match (shape, size) {
             (0, 0) => Self::Oam8x8,
             (0, 1) => Self::Oam16x16,
             (0, 2) => Self::Oam32x32,
@@ -211,14 +223,54 @@ impl From<(u8, u8)> for OamSize {
             (2, 1) => Self::Oam8x32,
             (2, 2) => Self::Oam16x32,
             (2, 3) => Self::Oam32x64,
-            _ => panic!(),
-        }
+            _ => {
+                return Err(InvalidEnumValue::new(
+                    "OamSize (shape, size)",
+                    u16::from_be_bytes([shape, size]),
+                ))
+            }
+        })
     }
 }
 
-impl Into<(u8, u8)> for OamSize {
-    fn into(self) -> (u8, u8) {
+impl OamSize {
+    pub(crate) const ALL: [OamSize; 12] = [
+        OamSize::Oam8x8,
+        OamSize::Oam16x16,
+        OamSize::Oam32x32,
+        OamSize::Oam64x64,
+        OamSize::Oam16x8,
+        OamSize::Oam32x8,
+        OamSize::Oam32x16,
+        OamSize::Oam64x32,
+        OamSize::Oam8x16,
+        OamSize::Oam8x32,
+        OamSize::Oam16x32,
+        OamSize::Oam32x64,
+    ];
+
+    /// Returns the width and height in pixels.
+    pub(crate) fn dimensions(self) -> (usize, usize) {
         match self {
+            OamSize::Oam8x8 => (8, 8),
+            OamSize::Oam16x16 => (16, 16),
+            OamSize::Oam32x32 => (32, 32),
+            OamSize::Oam64x64 => (64, 64),
+            OamSize::Oam16x8 => (16, 8),
+            OamSize::Oam32x8 => (32, 8),
+            OamSize::Oam32x16 => (32, 16),
+            OamSize::Oam64x32 => (64, 32),
+            OamSize::Oam8x16 => (8, 16),
+            OamSize::Oam8x32 => (8, 32),
+            OamSize::Oam16x32 => (16, 32),
+            OamSize::Oam32x64 => (32, 64),
+        }
+    }
+}
+
+impl From<OamSize> for (u8, u8) {
+    fn from(value: OamSize) -> Self {
+        match value {
             OamSize::Oam8x8 => (0, 0),
             OamSize::Oam16x16 => (0, 1),
             OamSize::Oam32x32 => (0, 2),
@@ -243,21 +295,23 @@ pub enum ObjMode {
     Bitmap,
 }
 
-impl From<u16> for ObjMode {
-    fn from(value: u16) -> Self {
-        match value {
+impl TryFrom<u16> for ObjMode {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
             0 => Self::Normal,
             1 => Self::Translucent,
             2 => Self::Window,
             3 => Self::Bitmap,
-            _ => panic!(),
-        }
+            _ => return Err(InvalidEnumValue::new("ObjMode", value)),
+        })
     }
 }
 
-impl Into<u16> for ObjMode {
-    fn into(self) -> u16 {
-        match self {
+impl From<ObjMode> for u16 {
+    fn from(value: ObjMode) -> Self {
+        match value {
             ObjMode::Normal => 0,
             ObjMode::Translucent => 1,
             ObjMode::Window => 2,
@@ -265,3 +319,135 @@ impl Into<u16> for ObjMode {
         }
     }
 }
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AnimationElement {
+    /// Each frame only references a cell index.
+    Index,
+
+    /// Each frame references a cell index along with scale, rotation and
+    /// translation values.
+    IndexSrt,
+
+    /// Each frame references a cell index along with translation values.
+    IndexT,
+}
+
+impl TryFrom<u16> for AnimationElement {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Index,
+            1 => Self::IndexSrt,
+            2 => Self::IndexT,
+            _ => return Err(InvalidEnumValue::new("AnimationElement", value)),
+        })
+    }
+}
+
+impl From<AnimationElement> for u16 {
+    fn from(value: AnimationElement) -> Self {
+        match value {
+            AnimationElement::Index => 0,
+            AnimationElement::IndexSrt => 1,
+            AnimationElement::IndexT => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AnimationType {
+    Cell,
+    MultiCell,
+}
+
+impl TryFrom<u16> for AnimationType {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Cell,
+            2 => Self::MultiCell,
+            _ => return Err(InvalidEnumValue::new("AnimationType", value)),
+        })
+    }
+}
+
+impl From<AnimationType> for u16 {
+    fn from(value: AnimationType) -> Self {
+        match value {
+            AnimationType::Cell => 1,
+            AnimationType::MultiCell => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum PlaybackMode {
+    Forward,
+    ForwardLoop,
+    Reverse,
+    ReverseLoop,
+}
+
+impl TryFrom<u32> for PlaybackMode {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Forward,
+            2 => Self::ForwardLoop,
+            3 => Self::Reverse,
+            4 => Self::ReverseLoop,
+            _ => return Err(InvalidEnumValue::new("PlaybackMode", value)),
+        })
+    }
+}
+
+impl From<PlaybackMode> for u32 {
+    fn from(value: PlaybackMode) -> Self {
+        match value {
+            PlaybackMode::Forward => 1,
+            PlaybackMode::ForwardLoop => 2,
+            PlaybackMode::Reverse => 3,
+            PlaybackMode::ReverseLoop => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum NtrBgType {
+    /// Screen entries are 16 bits, with flip and palette bits.
+    #[default]
+    Text,
+
+    /// Screen entries are 8 bits, consisting of only the tile index.
+    Affine,
+
+    /// Screen entries are 16 bits, with flip and palette bits.
+    AffineExtended,
+}
+
+impl TryFrom<u16> for NtrBgType {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Text,
+            1 => Self::Affine,
+            2 => Self::AffineExtended,
+            _ => return Err(InvalidEnumValue::new("NtrBgType", value)),
+        })
+    }
+}
+
+impl From<NtrBgType> for u16 {
+    fn from(value: NtrBgType) -> Self {
+        match value {
+            NtrBgType::Text => 0,
+            NtrBgType::Affine => 1,
+            NtrBgType::AffineExtended => 2,
+        }
+    }
+}
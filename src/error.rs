@@ -0,0 +1,185 @@
+/// Errors that can occur while reading or writing any of the supported file
+/// formats.
+///
+/// Errors raised while parsing carry the offset into the file at which the
+/// problem was found, along with the name of the block (or section) being
+/// parsed at the time.
+#[derive(Debug)]
+pub enum Error {
+    /// The magic at the start of the file or block does not match the format.
+    BadMagic {
+        offset: usize,
+        block: String,
+        expected: String,
+        found: String,
+    },
+
+    /// A block was found where a block with a different id was expected.
+    UnexpectedBlockId {
+        offset: usize,
+        block: String,
+        expected: String,
+    },
+
+    /// The texture format is valid but not supported by the file format.
+    UnsupportedTextureFormat {
+        offset: usize,
+        block: String,
+        value: u16,
+    },
+
+    /// The data ended before the whole file or block could be read.
+    TruncatedData {
+        offset: usize,
+        block: String,
+    },
+
+    /// A value does not correspond to any variant of the enum it encodes.
+    InvalidEnumValue {
+        offset: usize,
+        block: String,
+        name: &'static str,
+        value: u32,
+    },
+
+    /// A value is not what the file format allows for.
+    InvalidData {
+        offset: usize,
+        block: String,
+        reason: String,
+    },
+
+    /// The data to be written cannot be represented in the file format.
+    InvalidInput {
+        reason: String,
+    },
+
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    PngDecoding(png::DecodingError),
+    PngEncoding(png::EncodingError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error returned when converting a raw value into an enum fails.
+#[derive(Debug, Clone, Copy)]
+pub struct InvalidEnumValue {
+    pub name: &'static str,
+    pub value: u32,
+}
+
+impl InvalidEnumValue {
+    pub(crate) fn new(name: &'static str, value: impl Into<u32>) -> Self {
+        Self {
+            name,
+            value: value.into(),
+        }
+    }
+}
+
+impl Error {
+    pub(crate) fn invalid_data(offset: usize, block: &str, reason: impl Into<String>) -> Self {
+        Self::InvalidData {
+            offset,
+            block: block.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn invalid_input(reason: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BadMagic {
+                offset,
+                block,
+                expected,
+                found,
+            } => write!(
+                f,
+                "bad magic in {block} at {offset:#x}: expected {expected:?}, found {found:?}"
+            ),
+            Error::UnexpectedBlockId {
+                offset,
+                block,
+                expected,
+            } => write!(
+                f,
+                "unexpected block {block:?} at {offset:#x}: expected {expected:?}"
+            ),
+            Error::UnsupportedTextureFormat {
+                offset,
+                block,
+                value,
+            } => write!(
+                f,
+                "unsupported texture format {value} in {block} at {offset:#x}"
+            ),
+            Error::TruncatedData { offset, block } => {
+                write!(f, "data truncated in {block} at {offset:#x}")
+            }
+            Error::InvalidEnumValue {
+                offset,
+                block,
+                name,
+                value,
+            } => write!(
+                f,
+                "invalid value {value:#x} for {name} in {block} at {offset:#x}"
+            ),
+            Error::InvalidData {
+                offset,
+                block,
+                reason,
+            } => write!(f, "invalid data in {block} at {offset:#x}: {reason}"),
+            Error::InvalidInput { reason } => write!(f, "invalid input: {reason}"),
+            Error::Io(error) => error.fmt(f),
+            Error::Json(error) => error.fmt(f),
+            Error::PngDecoding(error) => error.fmt(f),
+            Error::PngEncoding(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Json(error) => Some(error),
+            Error::PngDecoding(error) => Some(error),
+            Error::PngEncoding(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(value: png::DecodingError) -> Self {
+        Self::PngDecoding(value)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(value: png::EncodingError) -> Self {
+        Self::PngEncoding(value)
+    }
+}
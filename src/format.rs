@@ -1,4 +1,12 @@
-use std::{fs::File, io::Read};
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{compression::Compression, error::Result};
 
 pub trait FileFormat
 where
@@ -6,7 +14,7 @@ where
 {
     fn extension() -> String;
 
-    fn read_from_file<P>(path: P) -> std::io::Result<Self>
+    fn read_from_file<P>(path: P) -> Result<Self>
     where
         P: AsRef<std::path::Path>,
     {
@@ -15,14 +23,77 @@ where
         Self::read_from_data(&data)
     }
 
-    fn write_to_file<P>(&self, path: P) -> std::io::Result<()>
+    fn write_to_file<P>(&self, path: P) -> Result<()>
     where
         P: AsRef<std::path::Path>,
     {
-        std::fs::write(path, self.write_to_data()?)
+        Ok(std::fs::write(path, self.write_to_data()?)?)
     }
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self>;
+    /// Same as [`read_from_file`](FileFormat::read_from_file), except that
+    /// LZ10/LZ11 compressed files are decompressed first. Uncompressed files
+    /// are read as-is.
+    fn read_from_compressed_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let mut data = vec![];
+        File::open(path)?.read_to_end(&mut data)?;
+        Self::read_from_compressed_data(&data)
+    }
+
+    fn write_to_compressed_file<P>(&self, path: P, compression: Compression) -> Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Ok(std::fs::write(
+            path,
+            self.write_to_compressed_data(compression)?,
+        )?)
+    }
+
+    fn read_from_compressed_data(data: &[u8]) -> Result<Self> {
+        match Compression::detect(data) {
+            Some(_) => Self::read_from_data(&Compression::decompress(data)?),
+            None => Self::read_from_data(data),
+        }
+    }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>>;
+    fn write_to_compressed_data(&self, compression: Compression) -> Result<Vec<u8>> {
+        compression.compress(&self.write_to_data()?)
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self>;
+
+    fn write_to_data(&self) -> Result<Vec<u8>>;
+}
+
+/// Returns the path of the metadata sidecar file that accompanies `path`, e.g.
+/// `foo.ncgr.json` for `foo.png`.
+pub(crate) fn sidecar_path(path: &Path, extension: &str) -> PathBuf {
+    path.with_extension(format!("{}.json", extension.to_lowercase()))
+}
+
+/// Reads the metadata sidecar file that accompanies `path`, if there is one.
+pub(crate) fn read_sidecar<T>(path: &Path, extension: &str) -> Result<Option<T>>
+where
+    T: DeserializeOwned,
+{
+    let sidecar_path = sidecar_path(path, extension);
+    if !sidecar_path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&std::fs::read_to_string(
+        sidecar_path,
+    )?)?))
+}
+
+pub(crate) fn write_sidecar<T>(metadata: &T, path: &Path, extension: &str) -> Result<()>
+where
+    T: Serialize,
+{
+    Ok(std::fs::write(
+        sidecar_path(path, extension),
+        serde_json::to_string_pretty(metadata)?,
+    )?)
 }
@@ -0,0 +1,102 @@
+use crate::{
+    error::{Error, Result},
+    format::FileFormat,
+    palette::{Color, Palette, PaletteFormat},
+    read_write_ext::LineReader,
+};
+
+/// A GIMP palette. The name, column count and color names are not kept.
+pub struct Gpl {
+    palette: Palette,
+}
+
+impl Gpl {
+    pub fn from_palette(palette: Palette) -> Self {
+        Gpl { palette }
+    }
+
+    pub fn to_palette(self) -> Palette {
+        self.palette
+    }
+}
+
+impl PaletteFormat for Gpl {
+    fn from_palette(palette: Palette) -> Self {
+        Self::from_palette(palette)
+    }
+
+    fn to_palette(self) -> Palette {
+        self.to_palette()
+    }
+}
+
+impl FileFormat for Gpl {
+    fn extension() -> String {
+        "gpl".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        let mut lines = LineReader::new(BLOCK, data);
+
+        let (offset, magic) = lines.next_line()?;
+        if magic.trim_end() != "GIMP Palette" {
+            return Err(Error::BadMagic {
+                offset,
+                block: BLOCK.to_string(),
+                expected: "GIMP Palette".to_string(),
+                found: magic.to_string(),
+            });
+        }
+
+        let mut colors = vec![];
+        for (offset, line) in lines {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:")
+            {
+                continue;
+            }
+            // Each color may be followed by a name, which may contain spaces.
+            let components = line
+                .split_ascii_whitespace()
+                .take(3)
+                .map(|component| component.parse::<u8>())
+                .collect::<std::result::Result<Vec<_>, _>>();
+            match components.as_deref() {
+                Ok([red, green, blue]) => colors.push(Color::new(*red, *green, *blue)),
+                _ => {
+                    return Err(Error::invalid_data(
+                        offset,
+                        BLOCK,
+                        format!("invalid color {:?}", line),
+                    ))
+                }
+            }
+        }
+
+        Ok(Gpl {
+            palette: Palette::new(colors),
+        })
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        let mut lines = vec![];
+        lines.push("GIMP Palette".to_string());
+        lines.push(format!("Columns: {}", Palette::BANK_SIZE));
+        lines.push("#".to_string());
+        for color in self.palette.colors() {
+            lines.push(format!(
+                "{:3} {:3} {:3}",
+                color.red, color.green, color.blue
+            ));
+        }
+        lines.push("".to_string());
+
+        Ok(lines.join("\n").as_bytes().to_vec())
+    }
+}
+
+/// Name used to refer to the file in errors, in place of a block name.
+const BLOCK: &str = "GPL";
@@ -1,6 +1,8 @@
 use crate::{
+    error::{Error, Result},
     format::FileFormat,
-    palette::{Color, Palette},
+    palette::{Color, Palette, PaletteFormat},
+    read_write_ext::LineReader,
 };
 
 pub struct Jasc {
@@ -17,35 +19,81 @@ impl Jasc {
     }
 }
 
+impl PaletteFormat for Jasc {
+    fn from_palette(palette: Palette) -> Self {
+        Self::from_palette(palette)
+    }
+
+    fn to_palette(self) -> Palette {
+        self.to_palette()
+    }
+}
+
 impl FileFormat for Jasc {
     fn extension() -> String {
         "pal".to_string()
     }
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
-        let mut colors = vec![];
-        let s = std::str::from_utf8(data).unwrap();
-        let mut lines = s.lines();
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        let mut lines = LineReader::new(BLOCK, data);
 
-        assert!(lines.next().unwrap() == "JASC-PAL");
-        assert!(lines.next().unwrap() == "0100");
-        let num_colors = lines.next().unwrap().parse::<usize>().unwrap();
+        let (offset, magic) = lines.next_line()?;
+        if magic != "JASC-PAL" {
+            return Err(Error::BadMagic {
+                offset,
+                block: BLOCK.to_string(),
+                expected: "JASC-PAL".to_string(),
+                found: magic.to_string(),
+            });
+        }
+        let (offset, version) = lines.next_line()?;
+        if version != "0100" {
+            return Err(Error::invalid_data(
+                offset,
+                BLOCK,
+                format!("unsupported version {:?}", version),
+            ));
+        }
+        let (offset, num_colors) = lines.next_line()?;
+        let num_colors = num_colors.parse::<usize>().map_err(|_| {
+            Error::invalid_data(
+                offset,
+                BLOCK,
+                format!("invalid color count {:?}", num_colors),
+            )
+        })?;
+        let mut colors = vec![];
         for _ in 0..num_colors {
-            let mut components = lines.next().unwrap().split_ascii_whitespace();
-            let red = components.next().unwrap().parse::<u8>().unwrap();
-            let green = components.next().unwrap().parse::<u8>().unwrap();
-            let blue = components.next().unwrap().parse::<u8>().unwrap();
-            colors.push(Color::new(red, green, blue));
-            assert!(components.next().is_none());
+            let (offset, line) = lines.next_line()?;
+            let components = line
+                .split_ascii_whitespace()
+                .map(|component| component.parse::<u8>())
+                .collect::<std::result::Result<Vec<_>, _>>();
+            match components.as_deref() {
+                Ok([red, green, blue]) => colors.push(Color::new(*red, *green, *blue)),
+                _ => {
+                    return Err(Error::invalid_data(
+                        offset,
+                        BLOCK,
+                        format!("invalid color {:?}", line),
+                    ))
+                }
+            }
+        }
+        if let Some((offset, _)) = lines.next() {
+            return Err(Error::invalid_data(
+                offset,
+                BLOCK,
+                "unexpected data after the last color",
+            ));
         }
-        assert!(lines.next().is_none());
 
         Ok(Jasc {
             palette: Palette::new(colors),
         })
     }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
+    fn write_to_data(&self) -> Result<Vec<u8>> {
         let mut lines = vec![];
         lines.push("JASC-PAL".to_string());
         lines.push("0100".to_string());
@@ -58,3 +106,6 @@ impl FileFormat for Jasc {
         Ok(lines.join("\r\n").as_bytes().to_vec())
     }
 }
+
+/// Name used to refer to the file in errors, in place of a block name.
+const BLOCK: &str = "JASC-PAL";
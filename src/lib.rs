@@ -1,35 +1,81 @@
 #![doc(html_no_source)]
 
+mod aco;
+mod act;
+mod compression;
 mod enums;
+mod error;
 mod format;
+mod gpl;
 mod image;
 mod jasc;
+mod nanr;
+mod narc;
 mod ncer;
 mod ncgr;
 mod nclr;
 mod nscr;
 mod ntr;
+mod paint_net;
 mod palette;
 mod png;
+mod quantize;
+mod raw_palette;
 mod read_write_ext;
+mod riff_pal;
 
 pub use crate::image::Image;
+pub use crate::palette::Color;
+pub use crate::palette::ColorScaling;
 pub use crate::palette::Palette;
+pub use crate::palette::PaletteFormat;
 
+pub use crate::aco::Aco;
+pub use crate::act::Act;
+pub use crate::gpl::Gpl;
 pub use crate::jasc::Jasc;
+pub use crate::paint_net::PaintNet;
 pub use crate::png::Png;
+pub use crate::quantize::Dithering;
+pub use crate::quantize::Quantization;
+pub use crate::quantize::TruecolorImage;
+pub use crate::raw_palette::RawPalette;
+pub use crate::raw_palette::RawPaletteOptions;
+pub use crate::riff_pal::RiffPal;
 
+pub use crate::nanr::Nanr;
+pub use crate::narc::Narc;
+pub use crate::narc::NarcFile;
+pub use crate::ncer::AffineMatrix;
+pub use crate::ncer::BoundingRectangle;
+pub use crate::ncer::Cell;
+pub use crate::ncer::CellAttribute;
+pub use crate::ncer::CellVramTransferData;
 pub use crate::ncer::Ncer;
+pub use crate::ncer::OamData;
+pub use crate::ncer::VramData;
 pub use crate::ncgr::Ncgr;
 pub use crate::nclr::Nclr;
 pub use crate::nscr::Nscr;
 
 pub use crate::ncgr::NcgrMetadata;
 pub use crate::nclr::NclrMetadata;
+pub use crate::nscr::NscrMetadata;
 
+pub use crate::enums::AnimationElement;
+pub use crate::enums::AnimationType;
+pub use crate::enums::NtrBgType;
 pub use crate::enums::NtrCharacterFormat;
 pub use crate::enums::NtrFileVersion;
 pub use crate::enums::NtrMappingType;
 pub use crate::enums::NtrTextureFormat;
+pub use crate::enums::OamSize;
+pub use crate::enums::ObjMode;
+pub use crate::enums::PlaybackMode;
 
+pub use crate::error::Error;
+pub use crate::error::InvalidEnumValue;
+pub use crate::error::Result;
+
+pub use crate::compression::Compression;
 pub use crate::format::FileFormat;
@@ -0,0 +1,386 @@
+use std::{collections::BTreeMap, io::Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enums::{AnimationElement, AnimationType, NtrFileVersion, PlaybackMode},
+    error::{Error, Result},
+    ntr::{default_user_extended_data, Labels, NtrFile, NtrFileBlock, NtrFormat},
+    read_write_ext::{DataReader, WriteExt},
+    FileFormat,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+enum AnimationResult {
+    Index {
+        index: u16,
+    },
+    IndexSrt {
+        index: u16,
+        rotation: u16,
+        scale_x: i32,
+        scale_y: i32,
+        position_x: i16,
+        position_y: i16,
+    },
+    IndexT {
+        index: u16,
+        position_x: i16,
+        position_y: i16,
+    },
+    /// Bytes between or after the results that no frame refers to, e.g.
+    /// unused results, which are kept so that files are written back exactly.
+    /// No padding is added right after them.
+    Raw {
+        data: Vec<u8>,
+    },
+}
+
+impl AnimationResult {
+    fn read(data: &mut DataReader, element: AnimationElement) -> Result<Self> {
+        Ok(match element {
+            AnimationElement::Index => Self::Index {
+                index: data.read_u16()?,
+            },
+            AnimationElement::IndexSrt => Self::IndexSrt {
+                index: data.read_u16()?,
+                rotation: data.read_u16()?,
+                scale_x: data.read_i32()?,
+                scale_y: data.read_i32()?,
+                position_x: data.read_i16()?,
+                position_y: data.read_i16()?,
+            },
+            AnimationElement::IndexT => {
+                let index = data.read_u16()?;
+                data.expect_u16(0xBEEF)?;
+                Self::IndexT {
+                    index,
+                    position_x: data.read_i16()?,
+                    position_y: data.read_i16()?,
+                }
+            }
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) -> Result<()> {
+        match self {
+            AnimationResult::Index { index } => data.write_u16(*index)?,
+            AnimationResult::IndexSrt {
+                index,
+                rotation,
+                scale_x,
+                scale_y,
+                position_x,
+                position_y,
+            } => {
+                data.write_u16(*index)?;
+                data.write_u16(*rotation)?;
+                data.write_u32(*scale_x as u32)?;
+                data.write_u32(*scale_y as u32)?;
+                data.write_i16(*position_x)?;
+                data.write_i16(*position_y)?;
+            }
+            AnimationResult::IndexT {
+                index,
+                position_x,
+                position_y,
+            } => {
+                data.write_u16(*index)?;
+                data.write_u16(0xBEEF)?;
+                data.write_i16(*position_x)?;
+                data.write_i16(*position_y)?;
+            }
+            AnimationResult::Raw { data: raw } => data.write_all(raw)?,
+        }
+        Ok(())
+    }
+
+    /// Results containing more than just the index are 4-byte aligned.
+    fn alignment(&self) -> usize {
+        match self {
+            AnimationResult::Raw { .. } => 1,
+            AnimationResult::Index { .. } => 2,
+            AnimationResult::IndexSrt { .. } | AnimationResult::IndexT { .. } => 4,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Frame {
+    /// Index into the list of animation results.
+    result_id: usize,
+    frame_delay: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Sequence {
+    loop_start_frame: u16,
+    animation_element: AnimationElement,
+    animation_type: AnimationType,
+    playback_mode: PlaybackMode,
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Nanr {
+    version: NtrFileVersion,
+    sequences: Vec<Sequence>,
+    results: Vec<AnimationResult>,
+
+    /// Contents of the UEXT block.
+    #[serde(default = "default_user_extended_data")]
+    user_extended_data: Vec<u8>,
+
+    #[serde(flatten)]
+    labels: Labels,
+}
+
+impl NtrFormat for Nanr {
+    fn read_from_ntr_file(file: &NtrFile) -> Result<Self> {
+        file.expect_id("RNAN")?;
+
+        file.expect_num_blocks(3)?;
+
+        let abnk_block = file.block(0, "KNBA")?;
+        let labl_block = file.block(1, "LBAL")?;
+        let uext_block = file.block(2, "TXEU")?;
+
+        let (sequences, results) = Self::from_abnk_block(abnk_block)?;
+        let labels = NtrFile::read_labl_block(labl_block, sequences.len())?;
+
+        Ok(Self {
+            version: file.version(),
+            sequences,
+            results,
+            user_extended_data: uext_block.contents().to_vec(),
+            labels,
+        })
+    }
+
+    fn write_to_ntr_file(&self) -> Result<NtrFile> {
+        Ok(NtrFile::new(
+            "RNAN",
+            self.version,
+            vec![
+                self.to_abnk_block()?,
+                NtrFile::write_labl_block(&self.labels)?,
+                self.to_uext_block()?,
+            ],
+        ))
+    }
+}
+
+impl Nanr {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str::<Self>(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self)?)
+    }
+
+    fn from_abnk_block(block: &NtrFileBlock) -> Result<(Vec<Sequence>, Vec<AnimationResult>)> {
+        let mut abnk = block.reader();
+        let num_sequences = abnk.read_u16()?;
+        let num_frames_offset = abnk.offset();
+        let num_frames = abnk.read_u16()?;
+        abnk.expect_u32(0x00000018)?;
+        let frames_offset: usize = abnk.read_u32()?.try_into().unwrap();
+        let results_offset: usize = abnk.read_u32()?.try_into().unwrap();
+        abnk.expect_u32(0)?;
+        abnk.expect_u32(0)?;
+
+        // Frames only store the offset of their result, so the results have to
+        // be gathered first before they can be referred to by index.
+        let mut raw_sequences = vec![];
+        let mut result_offsets = BTreeMap::new();
+        for _ in 0..num_sequences {
+            let num_sequence_frames = abnk.read_u16()?;
+            let loop_start_frame = abnk.read_u16()?;
+            let animation_element: AnimationElement = abnk.read_u16_as()?;
+            let animation_type: AnimationType = abnk.read_u16_as()?;
+            let playback_mode: PlaybackMode = abnk.read_u32_as()?;
+            let frame_offset: usize = abnk.read_u32()?.try_into().unwrap();
+
+            let mut frame_data = block.reader();
+            frame_data.seek(frames_offset + frame_offset)?;
+            let mut frames = vec![];
+            for _ in 0..num_sequence_frames {
+                let result_offset: usize = frame_data.read_u32()?.try_into().unwrap();
+                let frame_delay = frame_data.read_u16()?;
+                frame_data.expect_u16(0xBEEF)?;
+                result_offsets
+                    .entry(result_offset)
+                    .or_insert(animation_element);
+                frames.push((result_offset, frame_delay));
+            }
+            raw_sequences.push((
+                loop_start_frame,
+                animation_element,
+                animation_type,
+                playback_mode,
+                frames,
+            ));
+        }
+        let total_frames = raw_sequences
+            .iter()
+            .map(|(_, _, _, _, frames)| frames.len())
+            .sum::<usize>();
+        if total_frames != usize::from(num_frames) {
+            return Err(abnk.error_at(
+                num_frames_offset,
+                format!(
+                    "frame count {} does not match the {} frames in the sequences",
+                    num_frames, total_frames
+                ),
+            ));
+        }
+
+        // Results are kept in the order of their offsets. Any bytes around
+        // them other than the padding that is written anyway are kept as raw
+        // data, so that the offsets stay the same.
+        let contents = block.contents();
+        let results_data = contents.get(results_offset..).ok_or(Error::TruncatedData {
+            offset: block.offset() + 8 + contents.len(),
+            block: block.id().to_string(),
+        })?;
+        let mut results = vec![];
+        let mut result_ids = BTreeMap::new();
+        let mut end = 0;
+        for (result_offset, animation_element) in result_offsets {
+            let mut result_data = block.reader();
+            result_data.seek(results_offset + result_offset)?;
+            if result_offset < end {
+                return Err(result_data.error(format!(
+                    "result at {:#x} overlaps the previous result",
+                    result_offset
+                )));
+            }
+            let result = AnimationResult::read(&mut result_data, animation_element)?;
+            let gap = &results_data[end..result_offset];
+            if result_offset != end.next_multiple_of(result.alignment())
+                || gap.iter().any(|byte| *byte != 0)
+            {
+                results.push(AnimationResult::Raw { data: gap.to_vec() });
+            }
+            end = result_data.position() - results_offset;
+            result_ids.insert(result_offset, results.len());
+            results.push(result);
+        }
+        let trailing = &results_data[end..];
+        if trailing.len() != end.next_multiple_of(4) - end || trailing.iter().any(|byte| *byte != 0)
+        {
+            results.push(AnimationResult::Raw {
+                data: trailing.to_vec(),
+            });
+        }
+
+        let sequences = raw_sequences
+            .into_iter()
+            .map(
+                |(loop_start_frame, animation_element, animation_type, playback_mode, frames)| {
+                    Sequence {
+                        loop_start_frame,
+                        animation_element,
+                        animation_type,
+                        playback_mode,
+                        frames: frames
+                            .into_iter()
+                            .map(|(result_offset, frame_delay)| Frame {
+                                result_id: result_ids[&result_offset],
+                                frame_delay,
+                            })
+                            .collect(),
+                    }
+                },
+            )
+            .collect();
+
+        Ok((sequences, results))
+    }
+
+    fn to_abnk_block(&self) -> Result<NtrFileBlock> {
+        let mut result_data = vec![];
+        let mut result_offsets = vec![];
+        let mut after_raw = false;
+        for result in &self.results {
+            while !after_raw && result_data.len() % result.alignment() != 0 {
+                result_data.write_u8(0)?;
+            }
+            result_offsets.push(result_data.len());
+            result.write(&mut result_data)?;
+            after_raw = matches!(result, AnimationResult::Raw { .. });
+        }
+        while !after_raw && result_data.len() % 4 != 0 {
+            result_data.write_u8(0)?;
+        }
+
+        let mut sequence_data = vec![];
+        let mut frame_data = vec![];
+        for (sequence_index, sequence) in self.sequences.iter().enumerate() {
+            sequence_data.write_u16(sequence.frames.len().try_into().unwrap())?;
+            sequence_data.write_u16(sequence.loop_start_frame)?;
+            sequence_data.write_u16(sequence.animation_element.into())?;
+            sequence_data.write_u16(sequence.animation_type.into())?;
+            sequence_data.write_u32(sequence.playback_mode.into())?;
+            sequence_data.write_u32(frame_data.len().try_into().unwrap())?;
+            for (frame_index, frame) in sequence.frames.iter().enumerate() {
+                let result_offset = result_offsets.get(frame.result_id).ok_or_else(|| {
+                    Error::invalid_input(format!(
+                        "frame {} of sequence {} refers to result {}, but there are only {} results",
+                        frame_index,
+                        sequence_index,
+                        frame.result_id,
+                        self.results.len()
+                    ))
+                })?;
+                if let AnimationResult::Raw { .. } = self.results[frame.result_id] {
+                    return Err(Error::invalid_input(format!(
+                        "frame {} of sequence {} refers to result {}, which is raw data",
+                        frame_index, sequence_index, frame.result_id
+                    )));
+                }
+                frame_data.write_u32((*result_offset).try_into().unwrap())?;
+                frame_data.write_u16(frame.frame_delay)?;
+                frame_data.write_u16(0xBEEF)?;
+            }
+        }
+
+        let num_frames: usize = self.sequences.iter().map(|s| s.frames.len()).sum();
+        let sequences_offset = 0x00000018;
+        let frames_offset = sequences_offset + sequence_data.len();
+        let results_offset = frames_offset + frame_data.len();
+
+        let mut abnk = vec![];
+        abnk.write_u16(self.sequences.len().try_into().unwrap())?;
+        abnk.write_u16(num_frames.try_into().unwrap())?;
+        abnk.write_u32(sequences_offset.try_into().unwrap())?;
+        abnk.write_u32(frames_offset.try_into().unwrap())?;
+        abnk.write_u32(results_offset.try_into().unwrap())?;
+        abnk.write_u32(0x00000000)?;
+        abnk.write_u32(0x00000000)?;
+        abnk.write_all(&sequence_data)?;
+        abnk.write_all(&frame_data)?;
+        abnk.write_all(&result_data)?;
+
+        Ok(NtrFileBlock::new("KNBA", abnk))
+    }
+
+    fn to_uext_block(&self) -> Result<NtrFileBlock> {
+        Ok(NtrFileBlock::new("TXEU", self.user_extended_data.clone()))
+    }
+}
+
+impl FileFormat for Nanr {
+    fn extension() -> String {
+        "NANR".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        NtrFormat::read_from_data(data)
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        NtrFormat::write_to_data(self)
+    }
+}
@@ -0,0 +1,450 @@
+use std::io::Write;
+
+use crate::{
+    enums::NtrFileVersion,
+    error::{Error, Result},
+    ntr::{NtrFile, NtrFileBlock, NtrFormat},
+    read_write_ext::WriteExt,
+    FileFormat,
+};
+
+/// Files in the GMIF block are aligned to this many bytes.
+const FILE_ALIGNMENT: usize = 4;
+
+/// Directory ids in the BTNF block are offset by this value.
+const DIRECTORY_ID_BASE: u16 = 0xF000;
+
+pub struct NarcFile {
+    /// Full path of the file within the archive, using `/` as the separator.
+    /// Only present in archives with file names.
+    name: Option<String>,
+    data: Vec<u8>,
+}
+
+impl NarcFile {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+enum DirectoryEntry {
+    File(String),
+
+    /// Name of the subdirectory along with its index.
+    Directory(String, usize),
+}
+
+struct Directory {
+    entries: Vec<DirectoryEntry>,
+    first_file_id: u16,
+}
+
+pub struct Narc {
+    version: NtrFileVersion,
+    files: Vec<NarcFile>,
+
+    /// The directory tree, with the root directory first. Empty if the archive
+    /// does not have file names.
+    directories: Vec<Directory>,
+
+    /// The value of the bytes used to pad each file to 4-byte alignment.
+    padding: u8,
+}
+
+impl NtrFormat for Narc {
+    fn read_from_ntr_file(file: &NtrFile) -> Result<Self> {
+        file.expect_id("NARC")?;
+
+        file.expect_num_blocks(3)?;
+
+        let fatb_block = file.block(0, "BTAF")?;
+        let fntb_block = file.block(1, "BTNF")?;
+        let fimg_block = file.block(2, "GMIF")?;
+
+        let ranges = Self::from_fatb_block(fatb_block)?;
+        let directories = Self::from_fntb_block(fntb_block)?;
+
+        let fimg = fimg_block.contents();
+        let mut files = vec![];
+        let mut padding = None;
+        let mut end_of_previous_file = 0;
+        for (start, end) in &ranges {
+            if *start > *end || *end > fimg.len() {
+                return Err(Error::invalid_data(
+                    fatb_block.offset(),
+                    fatb_block.id(),
+                    format!("file range {:#x}..{:#x} is out of bounds", start, end),
+                ));
+            }
+            if end_of_previous_file < *start {
+                padding = padding.or(Some(fimg[end_of_previous_file]));
+            }
+            end_of_previous_file = *end;
+            files.push(NarcFile {
+                name: None,
+                data: fimg[*start..*end].to_vec(),
+            });
+        }
+        if end_of_previous_file < fimg.len() {
+            padding = padding.or(Some(fimg[end_of_previous_file]));
+        }
+
+        let mut narc = Self {
+            version: file.version(),
+            files,
+            directories,
+            padding: padding.unwrap_or(0xFF),
+        };
+        for (file_id, name) in narc.file_names() {
+            let file = narc.files.get_mut(file_id).ok_or_else(|| {
+                Error::invalid_data(
+                    fntb_block.offset(),
+                    fntb_block.id(),
+                    format!("file id {} is out of bounds", file_id),
+                )
+            })?;
+            file.name = Some(name);
+        }
+
+        Ok(narc)
+    }
+
+    fn write_to_ntr_file(&self) -> Result<NtrFile> {
+        let mut fimg = vec![];
+        let mut ranges = vec![];
+        for file in &self.files {
+            let start = fimg.len();
+            fimg.write_all(&file.data)?;
+            ranges.push((start, fimg.len()));
+            while !fimg.len().is_multiple_of(FILE_ALIGNMENT) {
+                fimg.write_u8(self.padding)?;
+            }
+        }
+
+        Ok(NtrFile::new(
+            "NARC",
+            self.version,
+            vec![
+                self.to_fatb_block(&ranges)?,
+                self.to_fntb_block()?,
+                NtrFileBlock::new("GMIF", fimg),
+            ],
+        )
+        .with_byte_order_mark(0xFFFE))
+    }
+}
+
+impl Narc {
+    /// Creates an archive without file names.
+    pub fn from_files(files: Vec<Vec<u8>>) -> Self {
+        Self {
+            version: NtrFileVersion::Version0100,
+            files: files
+                .into_iter()
+                .map(|data| NarcFile { name: None, data })
+                .collect(),
+            directories: vec![],
+            padding: 0xFF,
+        }
+    }
+
+    /// Creates an archive with file names, where each name is the full path of
+    /// the file within the archive using `/` as the separator.
+    ///
+    /// The files of each directory are stored next to each other, so the order
+    /// of [`files`](Narc::files) may differ from the order given here.
+    pub fn from_named_files(files: Vec<(String, Vec<u8>)>) -> Result<Self> {
+        let mut directories = vec![Directory {
+            entries: vec![],
+            first_file_id: 0,
+        }];
+        let mut files_per_directory = vec![vec![]];
+        for (name, data) in files {
+            let components = name.split('/').collect::<Vec<_>>();
+            let (file_name, directory_names) = components.split_last().unwrap();
+            let mut directory_index = 0;
+            for directory_name in directory_names {
+                let existing = directories[directory_index]
+                    .entries
+                    .iter()
+                    .find_map(|entry| match entry {
+                        DirectoryEntry::Directory(n, i) if n == directory_name => Some(*i),
+                        _ => None,
+                    });
+                directory_index = match existing {
+                    Some(i) => i,
+                    None => {
+                        let i = directories.len();
+                        directories.push(Directory {
+                            entries: vec![],
+                            first_file_id: 0,
+                        });
+                        files_per_directory.push(vec![]);
+                        directories[directory_index]
+                            .entries
+                            .push(DirectoryEntry::Directory(directory_name.to_string(), i));
+                        i
+                    }
+                };
+            }
+            let entries = &directories[directory_index].entries;
+            if file_name.is_empty()
+                || entries
+                    .iter()
+                    .any(|entry| matches!(entry, DirectoryEntry::File(n) if n == file_name))
+            {
+                return Err(Error::invalid_input(format!(
+                    "invalid file name {:?}",
+                    name
+                )));
+            }
+            files_per_directory[directory_index].push((name.clone(), data));
+        }
+
+        // Within each directory, files are listed before subdirectories.
+        let mut narc_files = vec![];
+        for (directory, files) in directories.iter_mut().zip(files_per_directory) {
+            directory.first_file_id = narc_files.len().try_into().unwrap();
+            let subdirectories = std::mem::take(&mut directory.entries);
+            for (name, data) in files {
+                let file_name = name.rsplit('/').next().unwrap().to_string();
+                directory.entries.push(DirectoryEntry::File(file_name));
+                narc_files.push(NarcFile {
+                    name: Some(name),
+                    data,
+                });
+            }
+            directory.entries.extend(subdirectories);
+        }
+
+        Ok(Self {
+            version: NtrFileVersion::Version0100,
+            files: narc_files,
+            directories,
+            padding: 0xFF,
+        })
+    }
+
+    pub fn files(&self) -> &[NarcFile] {
+        &self.files
+    }
+
+    pub fn file_by_name(&self, name: &str) -> Option<&NarcFile> {
+        self.files.iter().find(|file| file.name() == Some(name))
+    }
+
+    /// Replaces the contents of the file at `index`, keeping its name.
+    pub fn set_file_data(&mut self, index: usize, data: Vec<u8>) -> Result<()> {
+        let num_files = self.files.len();
+        let file = self.files.get_mut(index).ok_or_else(|| {
+            Error::invalid_input(format!(
+                "file index {} is out of range for an archive with {} files",
+                index, num_files
+            ))
+        })?;
+        file.data = data;
+        Ok(())
+    }
+
+    pub fn has_file_names(&self) -> bool {
+        !self.directories.is_empty()
+    }
+
+    /// Walks the directory tree, returning the id and full path of each file.
+    fn file_names(&self) -> Vec<(usize, String)> {
+        fn walk(
+            directories: &[Directory],
+            index: usize,
+            prefix: &str,
+            names: &mut Vec<(usize, String)>,
+        ) {
+            let directory = &directories[index];
+            let mut file_id = usize::from(directory.first_file_id);
+            for entry in &directory.entries {
+                match entry {
+                    DirectoryEntry::File(name) => {
+                        names.push((file_id, format!("{}{}", prefix, name)));
+                        file_id += 1;
+                    }
+                    DirectoryEntry::Directory(name, i) => {
+                        walk(directories, *i, &format!("{}{}/", prefix, name), names);
+                    }
+                }
+            }
+        }
+
+        let mut names = vec![];
+        if self.has_file_names() {
+            walk(&self.directories, 0, "", &mut names);
+        }
+        names
+    }
+
+    fn from_fatb_block(block: &NtrFileBlock) -> Result<Vec<(usize, usize)>> {
+        let mut fatb = block.reader();
+        let num_files = fatb.read_u16()?;
+        fatb.expect_u16(0x0000)?;
+        let mut ranges = vec![];
+        for _ in 0..num_files {
+            let start = fatb.read_u32()?.try_into().unwrap();
+            let end = fatb.read_u32()?.try_into().unwrap();
+            ranges.push((start, end));
+        }
+        Ok(ranges)
+    }
+
+    fn from_fntb_block(block: &NtrFileBlock) -> Result<Vec<Directory>> {
+        // Archives without file names only have the root entry of the main
+        // table, without any subtables.
+        if block.contents().len() == 8 {
+            return Ok(vec![]);
+        }
+
+        let mut fntb = block.reader();
+        fntb.seek(6)?;
+        let num_directories = fntb.read_u16()?;
+        fntb.seek(0)?;
+
+        let mut main_table = vec![];
+        for _ in 0..num_directories {
+            let subtable_offset: usize = fntb.read_u32()?.try_into().unwrap();
+            let first_file_id = fntb.read_u16()?;
+            let _parent_id = fntb.read_u16()?;
+            main_table.push((subtable_offset, first_file_id));
+        }
+
+        // Each subdirectory must be listed exactly once, so that walking the
+        // tree from the root always terminates.
+        let mut listed = vec![false; main_table.len()];
+        let mut directories = vec![];
+        for (subtable_offset, first_file_id) in main_table {
+            fntb.seek(subtable_offset)?;
+            let mut entries = vec![];
+            loop {
+                let offset = fntb.offset();
+                let entry_type = fntb.read_u8()?;
+                let name_length = usize::from(entry_type & 0x7F);
+                match entry_type {
+                    0x00 => break,
+                    0x80 => return Err(fntb.error_at(offset, "reserved entry type 0x80")),
+                    _ => {}
+                }
+                let name = fntb.read_string(name_length)?;
+                if entry_type < 0x80 {
+                    entries.push(DirectoryEntry::File(name));
+                } else {
+                    let offset = fntb.offset();
+                    let directory_id = fntb.read_u16()?;
+                    let index = usize::from(directory_id.wrapping_sub(DIRECTORY_ID_BASE));
+                    if index == 0 || index >= usize::from(num_directories) {
+                        return Err(fntb.error_at(
+                            offset,
+                            format!("invalid directory id {:#06x}", directory_id),
+                        ));
+                    }
+                    if listed[index] {
+                        return Err(fntb.error_at(
+                            offset,
+                            format!(
+                                "directory id {:#06x} is listed more than once",
+                                directory_id
+                            ),
+                        ));
+                    }
+                    listed[index] = true;
+                    entries.push(DirectoryEntry::Directory(name, index));
+                }
+            }
+            directories.push(Directory {
+                entries,
+                first_file_id,
+            });
+        }
+
+        Ok(directories)
+    }
+
+    fn to_fatb_block(&self, ranges: &[(usize, usize)]) -> Result<NtrFileBlock> {
+        let mut fatb = vec![];
+        fatb.write_u16(self.files.len().try_into().unwrap())?;
+        fatb.write_u16(0x0000)?;
+        for (start, end) in ranges {
+            fatb.write_u32((*start).try_into().unwrap())?;
+            fatb.write_u32((*end).try_into().unwrap())?;
+        }
+        Ok(NtrFileBlock::new("BTAF", fatb))
+    }
+
+    fn to_fntb_block(&self) -> Result<NtrFileBlock> {
+        let mut fntb = vec![];
+        if !self.has_file_names() {
+            fntb.write_u32(0x00000004)?;
+            fntb.write_u16(0x0000)?;
+            fntb.write_u16(0x0001)?;
+            return Ok(NtrFileBlock::new("BTNF", fntb));
+        }
+
+        let num_directories = self.directories.len();
+        let mut parent_ids = vec![u16::try_from(num_directories).unwrap(); num_directories];
+        let mut subtables = vec![];
+        let mut subtable_offsets = vec![];
+        for (i, directory) in self.directories.iter().enumerate() {
+            subtable_offsets.push(num_directories * 8 + subtables.len());
+            for entry in &directory.entries {
+                match entry {
+                    DirectoryEntry::File(name) => {
+                        subtables.write_u8(Self::name_length(name)?)?;
+                        subtables.write_string(name)?;
+                    }
+                    DirectoryEntry::Directory(name, index) => {
+                        subtables.write_u8(0x80 | Self::name_length(name)?)?;
+                        subtables.write_string(name)?;
+                        subtables.write_u16(DIRECTORY_ID_BASE + u16::try_from(*index).unwrap())?;
+                        parent_ids[*index] = DIRECTORY_ID_BASE + u16::try_from(i).unwrap();
+                    }
+                }
+            }
+            subtables.write_u8(0x00)?;
+        }
+
+        for (i, directory) in self.directories.iter().enumerate() {
+            fntb.write_u32(subtable_offsets[i].try_into().unwrap())?;
+            fntb.write_u16(directory.first_file_id)?;
+            fntb.write_u16(parent_ids[i])?;
+        }
+        fntb.write_all(&subtables)?;
+        while !fntb.len().is_multiple_of(FILE_ALIGNMENT) {
+            fntb.write_u8(0xFF)?;
+        }
+
+        Ok(NtrFileBlock::new("BTNF", fntb))
+    }
+
+    fn name_length(name: &str) -> Result<u8> {
+        match name.len() {
+            1..=0x7F => Ok(name.len().try_into().unwrap()),
+            _ => Err(Error::invalid_input(format!(
+                "name {:?} must be between 1 and 127 bytes long",
+                name
+            ))),
+        }
+    }
+}
+
+impl FileFormat for Narc {
+    fn extension() -> String {
+        "narc".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        NtrFormat::read_from_data(data)
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        NtrFormat::write_to_data(self)
+    }
+}
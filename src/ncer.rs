@@ -1,33 +1,136 @@
-use std::{collections::VecDeque, io::Write};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    io::Write,
+    ops::Range,
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    enums::{NtrFileVersion, OamSize, ObjMode},
-    ntr::{NtrFile, NtrFileBlock, NtrFormat},
-    read_write_ext::{ReadExt, WriteExt},
+    enums::{NtrCharacterFormat, NtrFileVersion, NtrTextureFormat, OamSize, ObjMode},
+    error::{Error, InvalidEnumValue, Result},
+    image::{tiles_to_pixels, Image, TILE_LENGTH},
+    ncgr::{Ncgr, NcgrMetadata},
+    nclr::Nclr,
+    ntr::{default_user_extended_data, Labels, NtrFile, NtrFileBlock, NtrFormat},
+    palette::Palette,
+    read_write_ext::WriteExt,
     FileFormat, NtrMappingType,
 };
 
-#[derive(Debug, Deserialize, Serialize)]
-struct BoundingRectangle {
+type Tile = [u8; TILE_LENGTH * TILE_LENGTH];
+
+type UserExtendedAttributes = Option<Vec<Vec<u32>>>;
+
+/// Area covered by a cell, relative to its origin. The maximum coordinates
+/// are exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BoundingRectangle {
     max_x: i16,
     max_y: i16,
     min_x: i16,
     min_y: i16,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct OamData {
+impl BoundingRectangle {
+    pub fn new(min_x: i16, min_y: i16, max_x: i16, max_y: i16) -> Self {
+        Self {
+            max_x,
+            max_y,
+            min_x,
+            min_y,
+        }
+    }
+
+    pub fn min_x(&self) -> i16 {
+        self.min_x
+    }
+
+    pub fn min_y(&self) -> i16 {
+        self.min_y
+    }
+
+    pub fn max_x(&self) -> i16 {
+        self.max_x
+    }
+
+    pub fn max_y(&self) -> i16 {
+        self.max_y
+    }
+}
+
+/// Affine parameters of objects, in 8.8 fixed point. They map from the
+/// screen to the character data, i.e. they are the inverse of the
+/// transformation that is applied to the object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AffineMatrix {
+    pa: i16,
+    pb: i16,
+    pc: i16,
+    pd: i16,
+}
+
+impl AffineMatrix {
+    pub const IDENTITY: Self = Self::new(0x100, 0, 0, 0x100);
+
+    pub const fn new(pa: i16, pb: i16, pc: i16, pd: i16) -> Self {
+        Self { pa, pb, pc, pd }
+    }
+
+    /// Creates the parameters for rotating an object counterclockwise by
+    /// `angle` radians and then scaling it by `(scale_x, scale_y)`.
+    pub fn from_rotation_and_scale(angle: f64, scale_x: f64, scale_y: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let fixed = |value: f64| (value * 256.0).round() as i16;
+        Self::new(
+            fixed(cos / scale_x),
+            fixed(-sin / scale_x),
+            fixed(sin / scale_y),
+            fixed(cos / scale_y),
+        )
+    }
+
+    pub fn pa(&self) -> i16 {
+        self.pa
+    }
+
+    pub fn pb(&self) -> i16 {
+        self.pb
+    }
+
+    pub fn pc(&self) -> i16 {
+        self.pc
+    }
+
+    pub fn pd(&self) -> i16 {
+        self.pd
+    }
+}
+
+/// Attributes of a single object (i.e. hardware sprite) of a cell.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(from = "OamDataJson")]
+pub struct OamData {
     y: i8,
     x: i16,
 
     affine: bool,
 
+    /// Only used by objects that are not affine.
     disable: bool,
     h_flip: bool,
     v_flip: bool,
 
+    /// Only used by affine objects, in place of `disable`, `h_flip` and
+    /// `v_flip`.
+    affine_index: u8,
+    double_size: bool,
+
+    /// Bits 9-11 of the second attribute, which objects that are not affine
+    /// leave unused. Kept so that files are written back unchanged.
+    unused_attr1_bits: u8,
+
     mode: ObjMode,
     mosaic: bool,
 
@@ -39,13 +142,73 @@ struct OamData {
     palette_number: u8,
 }
 
+/// [`OamData`] as read from JSON. JSON written before affine objects had
+/// their own fields lacks `affine_index` and `double_size`, and stores the
+/// double size flag of affine objects in `disable`, and bits 3 and 4 of their
+/// affine index in `h_flip` and `v_flip`.
+#[derive(Deserialize)]
+struct OamDataJson {
+    y: i8,
+    x: i16,
+    affine: bool,
+    disable: bool,
+    h_flip: bool,
+    v_flip: bool,
+    affine_index: Option<u8>,
+    double_size: Option<bool>,
+    #[serde(default)]
+    unused_attr1_bits: u8,
+    mode: ObjMode,
+    mosaic: bool,
+    color_mode: u8,
+    oam_size: OamSize,
+    tile_number: u16,
+    priority: u8,
+    palette_number: u8,
+}
+
+impl From<OamDataJson> for OamData {
+    fn from(json: OamDataJson) -> Self {
+        let legacy_affine_index = json.affine && json.affine_index.is_none();
+        let legacy_double_size = json.affine && json.double_size.is_none();
+        Self {
+            y: json.y,
+            x: json.x,
+            affine: json.affine,
+            disable: json.disable && !legacy_double_size,
+            h_flip: json.h_flip && !legacy_affine_index,
+            v_flip: json.v_flip && !legacy_affine_index,
+            affine_index: match legacy_affine_index {
+                true => (u8::from(json.h_flip) << 3) | (u8::from(json.v_flip) << 4),
+                false => json.affine_index.unwrap_or(0),
+            },
+            double_size: match legacy_double_size {
+                true => json.disable,
+                false => json.double_size.unwrap_or(false),
+            },
+            unused_attr1_bits: json.unused_attr1_bits,
+            mode: json.mode,
+            mosaic: json.mosaic,
+            color_mode: json.color_mode,
+            oam_size: json.oam_size,
+            tile_number: json.tile_number,
+            priority: json.priority,
+            palette_number: json.palette_number,
+        }
+    }
+}
+
 impl Into<(u16, u16, u16)> for &OamData {
     fn into(self) -> (u16, u16, u16) {
         let (shape, size) = self.oam_size.into();
 
         let y = (self.y as u8) as u16;
         let affine = if self.affine { 1 } else { 0 };
-        let disable = if self.disable { 1 } else { 0 };
+        // Affine objects use this bit for the double size flag instead.
+        let disable = match self.affine {
+            true => u16::from(self.double_size),
+            false => u16::from(self.disable),
+        };
         let mode: u16 = self.mode.into();
         let mosaic = if self.mosaic { 1 } else { 0 };
         let color_mode = u16::from(self.color_mode);
@@ -60,11 +223,19 @@ impl Into<(u16, u16, u16)> for &OamData {
             | (shape << 0xe);
 
         let x = if self.x >= 0 { self.x } else { 512 + self.x } as u16;
-        let h_flip = if self.h_flip { 1 } else { 0 };
-        let v_flip = if self.v_flip { 1 } else { 0 };
+        // Affine objects use bits 9-13 for the index of the affine parameters
+        // instead of the flips.
+        let flips_or_affine_index = match self.affine {
+            true => u16::from(self.affine_index) << 0x9,
+            false => {
+                (u16::from(self.unused_attr1_bits) << 0x9)
+                    | (u16::from(self.h_flip) << 0xc)
+                    | (u16::from(self.v_flip) << 0xd)
+            }
+        };
         let size = u16::from(size);
 
-        let attr1 = x | (h_flip << 0xc) | (v_flip << 0xd) | (size << 0xe);
+        let attr1 = x | flips_or_affine_index | (size << 0xe);
 
         let tile_number = self.tile_number;
         let priority = u16::from(self.priority);
@@ -76,14 +247,323 @@ impl Into<(u16, u16, u16)> for &OamData {
     }
 }
 
-impl From<(u16, u16, u16)> for OamData {
-    fn from(value: (u16, u16, u16)) -> Self {
+impl OamData {
+    /// Creates a 4bpp object at the origin of the cell, using palette 0 and
+    /// priority 0.
+    pub fn new(oam_size: OamSize, tile_number: u16) -> Self {
+        Self {
+            y: 0,
+            x: 0,
+            affine: false,
+            disable: false,
+            h_flip: false,
+            v_flip: false,
+            affine_index: 0,
+            double_size: false,
+            unused_attr1_bits: 0,
+            mode: ObjMode::Normal,
+            mosaic: false,
+            color_mode: 0,
+            oam_size,
+            tile_number,
+            priority: 0,
+            palette_number: 0,
+        }
+    }
+
+    /// Sets the position of the top left corner of the object, relative to
+    /// the origin of the cell. `x` must be within -256..=255.
+    pub fn with_position(self, x: i16, y: i8) -> Self {
+        Self { x, y, ..self }
+    }
+
+    pub fn with_affine(self, affine: bool) -> Self {
+        Self { affine, ..self }
+    }
+
+    /// Sets the index of the affine parameters used by an affine object,
+    /// which must be less than 32.
+    pub fn with_affine_index(self, affine_index: u8) -> Self {
+        Self {
+            affine_index,
+            ..self
+        }
+    }
+
+    /// Sets whether an affine object is drawn within an area twice its size,
+    /// so that it is not clipped when rotated or scaled up.
+    pub fn with_double_size(self, double_size: bool) -> Self {
+        Self {
+            double_size,
+            ..self
+        }
+    }
+
+    pub fn with_disable(self, disable: bool) -> Self {
+        Self { disable, ..self }
+    }
+
+    pub fn with_h_flip(self, h_flip: bool) -> Self {
+        Self { h_flip, ..self }
+    }
+
+    pub fn with_v_flip(self, v_flip: bool) -> Self {
+        Self { v_flip, ..self }
+    }
+
+    /// Sets bits 9-11 of the second attribute of an object that is not
+    /// affine, which the hardware ignores. Must be less than 8.
+    pub fn with_unused_attr1_bits(self, unused_attr1_bits: u8) -> Self {
+        Self {
+            unused_attr1_bits,
+            ..self
+        }
+    }
+
+    pub fn with_mode(self, mode: ObjMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    pub fn with_mosaic(self, mosaic: bool) -> Self {
+        Self { mosaic, ..self }
+    }
+
+    /// Sets the color mode, i.e. 0 for 4bpp and 1 for 8bpp.
+    pub fn with_color_mode(self, color_mode: u8) -> Self {
+        Self { color_mode, ..self }
+    }
+
+    pub fn with_oam_size(self, oam_size: OamSize) -> Self {
+        Self { oam_size, ..self }
+    }
+
+    /// Sets the tile number, which must be less than 1024.
+    pub fn with_tile_number(self, tile_number: u16) -> Self {
+        Self {
+            tile_number,
+            ..self
+        }
+    }
+
+    /// Sets the priority, which must be less than 4.
+    pub fn with_priority(self, priority: u8) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// Sets the palette number, which must be less than 16.
+    pub fn with_palette_number(self, palette_number: u8) -> Self {
+        Self {
+            palette_number,
+            ..self
+        }
+    }
+
+    pub fn x(&self) -> i16 {
+        self.x
+    }
+
+    pub fn y(&self) -> i8 {
+        self.y
+    }
+
+    pub fn affine(&self) -> bool {
+        self.affine
+    }
+
+    pub fn affine_index(&self) -> u8 {
+        self.affine_index
+    }
+
+    pub fn double_size(&self) -> bool {
+        self.double_size
+    }
+
+    pub fn disable(&self) -> bool {
+        self.disable
+    }
+
+    pub fn h_flip(&self) -> bool {
+        self.h_flip
+    }
+
+    pub fn v_flip(&self) -> bool {
+        self.v_flip
+    }
+
+    pub fn unused_attr1_bits(&self) -> u8 {
+        self.unused_attr1_bits
+    }
+
+    pub fn mode(&self) -> ObjMode {
+        self.mode
+    }
+
+    pub fn mosaic(&self) -> bool {
+        self.mosaic
+    }
+
+    pub fn color_mode(&self) -> u8 {
+        self.color_mode
+    }
+
+    pub fn oam_size(&self) -> OamSize {
+        self.oam_size
+    }
+
+    pub fn tile_number(&self) -> u16 {
+        self.tile_number
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    pub fn palette_number(&self) -> u8 {
+        self.palette_number
+    }
+
+    /// Checks that every field fits in its bits of the OAM attributes.
+    fn validate(&self) -> std::result::Result<(), String> {
+        if !(-256..=255).contains(&self.x) {
+            return Err(format!("x {} is not within -256..=255", self.x));
+        }
+        if self.color_mode > 1 {
+            return Err(format!("invalid color mode {}", self.color_mode));
+        }
+        if self.tile_number >= 0x400 {
+            return Err(format!("tile number {} is too large", self.tile_number));
+        }
+        if self.priority >= 4 {
+            return Err(format!("priority {} is too large", self.priority));
+        }
+        if self.affine_index >= 32 {
+            return Err(format!("affine index {} is too large", self.affine_index));
+        }
+        if self.unused_attr1_bits >= 8 {
+            return Err(format!(
+                "unused attribute 1 bits {} are too large",
+                self.unused_attr1_bits
+            ));
+        }
+        if self.palette_number >= 16 {
+            return Err(format!(
+                "palette number {} is too large",
+                self.palette_number
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the object is drawn as part of the cell. Objects that are
+    /// disabled or used for the OBJ window are not.
+    fn is_drawn(&self) -> bool {
+        (self.affine || !self.disable)
+            && matches!(self.mode, ObjMode::Normal | ObjMode::Translucent)
+    }
+
+    /// Returns the position and size of the area the object is drawn in, i.e.
+    /// `(x, y, width, height)`.
+    fn rectangle(&self) -> (i32, i32, usize, usize) {
+        let (width, height) = self.oam_size.dimensions();
+        match self.affine && self.double_size {
+            true => (self.x.into(), self.y.into(), width * 2, height * 2),
+            false => (self.x.into(), self.y.into(), width, height),
+        }
+    }
+
+    /// Returns the pixel of the object's character data shown at `(x, y)`
+    /// within its rectangle, if any.
+    fn source_pixel(
+        &self,
+        x: usize,
+        y: usize,
+        matrices: &[AffineMatrix],
+    ) -> Option<(usize, usize)> {
+        let (width, height) = self.oam_size.dimensions();
+        if !self.affine {
+            let x = if self.h_flip { width - 1 - x } else { x };
+            let y = if self.v_flip { height - 1 - y } else { y };
+            return Some((x, y));
+        }
+
+        // The matrix maps from the center of the drawn area to the center of
+        // the object, in 8.8 fixed point.
+        let matrix = matrices
+            .get(usize::from(self.affine_index))
+            .copied()
+            .unwrap_or(AffineMatrix::IDENTITY);
+        let (_, _, area_width, area_height) = self.rectangle();
+        let dx = i32::try_from(x).unwrap() - i32::try_from(area_width / 2).unwrap();
+        let dy = i32::try_from(y).unwrap() - i32::try_from(area_height / 2).unwrap();
+        let source_x = ((i32::from(matrix.pa) * dx + i32::from(matrix.pb) * dy) >> 8)
+            + i32::try_from(width / 2).unwrap();
+        let source_y = ((i32::from(matrix.pc) * dx + i32::from(matrix.pd) * dy) >> 8)
+            + i32::try_from(height / 2).unwrap();
+        let source_x = usize::try_from(source_x).ok().filter(|x| *x < width)?;
+        let source_y = usize::try_from(source_y).ok().filter(|y| *y < height)?;
+        Some((source_x, source_y))
+    }
+
+    /// Returns the range of the character data used by the object in bytes,
+    /// given the size that tile numbers count in with 1D mapping.
+    fn character_range(&self, boundary: usize, tile_size: usize) -> Range<usize> {
+        let (width, height) = self.oam_size.dimensions();
+        let start = usize::from(self.tile_number) * boundary;
+        start..start + width * height / (TILE_LENGTH * TILE_LENGTH) * tile_size
+    }
+
+    /// Returns the index of the tile at `(tile_x, tile_y)` within the object,
+    /// counting in tiles of the given size in bytes.
+    fn tile_index(
+        &self,
+        mapping_type: NtrMappingType,
+        tile_x: usize,
+        tile_y: usize,
+        tile_size: usize,
+    ) -> usize {
+        let tile_number = usize::from(self.tile_number);
+        let (width, _) = self.oam_size.dimensions();
+        let offset = match mapping_type {
+            // Characters are laid out in a grid 32 4bpp tiles wide.
+            NtrMappingType::Mode2D => (tile_number + tile_y * 32) * 32 + tile_x * tile_size,
+            _ => {
+                let boundary = mapping_boundary(mapping_type).unwrap();
+                tile_number * boundary + (tile_y * width / TILE_LENGTH + tile_x) * tile_size
+            }
+        };
+        offset / tile_size
+    }
+}
+
+/// Creates an NCGR storing the tiles one after the other.
+fn ncgr_from_tiles(tiles: &[Tile], metadata: NcgrMetadata) -> Ncgr {
+    Ncgr::from_image(
+        Image::new(TILE_LENGTH, &tiles_to_pixels(tiles, 1), None),
+        metadata,
+    )
+}
+
+/// Returns the size in bytes that tile numbers count in with 1D mapping.
+fn mapping_boundary(mapping_type: NtrMappingType) -> Option<usize> {
+    match mapping_type {
+        NtrMappingType::Mode2D => None,
+        NtrMappingType::Mode1D32K => Some(32),
+        NtrMappingType::Mode1D64K => Some(64),
+        NtrMappingType::Mode1D128K => Some(128),
+        NtrMappingType::Mode1D256K => Some(256),
+    }
+}
+
+impl TryFrom<(u16, u16, u16)> for OamData {
+    type Error = InvalidEnumValue;
+
+    fn try_from(value: (u16, u16, u16)) -> std::result::Result<Self, Self::Error> {
         let (attr0, attr1, attr2) = value;
 
         let y = ((attr0 >> 0) & ((1 << 8) - 1)) as i8;
         let affine = ((attr0 >> 0x8) & 1) != 0;
         let disable = ((attr0 >> 0x9) & 1) != 0;
-        let mode = ((attr0 >> 0xa) & ((1 << 2) - 1)).try_into().unwrap();
+        let mode = ((attr0 >> 0xa) & ((1 << 2) - 1)).try_into()?;
         let mosaic = ((attr0 >> 0xc) & 1) != 0;
         let color_mode: u8 = (((attr0 >> 0xd) & 1) != 0).try_into().unwrap();
         let shape = ((attr0 >> 0xe) & ((1 << 2) - 1)).try_into().unwrap();
@@ -91,23 +571,34 @@ impl From<(u16, u16, u16)> for OamData {
         let x = (attr1 >> 0) & ((1 << 9) - 1);
         let x = x as i16 - (if x < 256 { 0 } else { 512 });
         assert!(-256 <= x && x <= 255);
-        let h_flip = ((attr1 >> 0xc) & 1) != 0;
-        let v_flip = ((attr1 >> 0xd) & 1) != 0;
+        let flips_or_affine_index = (attr1 >> 0x9) & ((1 << 5) - 1);
+        let (h_flip, v_flip, affine_index, unused_attr1_bits) = match affine {
+            true => (false, false, flips_or_affine_index.try_into().unwrap(), 0),
+            false => (
+                ((attr1 >> 0xc) & 1) != 0,
+                ((attr1 >> 0xd) & 1) != 0,
+                0,
+                (flips_or_affine_index & ((1 << 3) - 1)).try_into().unwrap(),
+            ),
+        };
         let size = ((attr1 >> 0xe) & ((1 << 2) - 1)).try_into().unwrap();
 
         let tile_number = (attr2 >> 0) & ((1 << 0xa) - 1);
         let priority = ((attr2 >> 0xa) & ((1 << 2) - 1)).try_into().unwrap();
         let palette_number = ((attr2 >> 0xc) & ((1 << 4) - 1)).try_into().unwrap();
 
-        let oam_size: OamSize = (shape, size).into();
+        let oam_size: OamSize = (shape, size).try_into()?;
 
-        OamData {
+        Ok(OamData {
             y,
             x,
             affine,
-            disable,
+            disable: disable && !affine,
             h_flip,
             v_flip,
+            affine_index,
+            double_size: disable && affine,
+            unused_attr1_bits,
             mode,
             mosaic,
             color_mode,
@@ -115,25 +606,67 @@ impl From<(u16, u16, u16)> for OamData {
             tile_number,
             priority,
             palette_number,
-        }
+        })
     }
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
-struct CellAttribute {
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CellAttribute {
     h_flip: bool,
     v_flip: bool,
     has_bounding_rectangle: bool,
     bounding_sphere_radius: u16,
 }
 
+impl CellAttribute {
+    pub fn with_h_flip(self, h_flip: bool) -> Self {
+        Self { h_flip, ..self }
+    }
+
+    pub fn with_v_flip(self, v_flip: bool) -> Self {
+        Self { v_flip, ..self }
+    }
+
+    fn with_has_bounding_rectangle(self, has_bounding_rectangle: bool) -> Self {
+        Self {
+            has_bounding_rectangle,
+            ..self
+        }
+    }
+
+    /// Sets the radius of the bounding sphere in units of 4 pixels, which must
+    /// be less than 64.
+    pub fn with_bounding_sphere_radius(self, bounding_sphere_radius: u16) -> Self {
+        Self {
+            bounding_sphere_radius,
+            ..self
+        }
+    }
+
+    pub fn h_flip(&self) -> bool {
+        self.h_flip
+    }
+
+    pub fn v_flip(&self) -> bool {
+        self.v_flip
+    }
+
+    /// Whether the cell has a bounding rectangle, which must be the same for
+    /// all cells of the NCER.
+    pub fn has_bounding_rectangle(&self) -> bool {
+        self.has_bounding_rectangle
+    }
+
+    pub fn bounding_sphere_radius(&self) -> u16 {
+        self.bounding_sphere_radius
+    }
+}
+
 impl From<u16> for CellAttribute {
     fn from(value: u16) -> Self {
         let bounding_sphere_radius = value & 0x3f;
         let h_flip = value & (1 << 8) != 0;
         let v_flip = value & (1 << 9) != 0;
-        let h_v_flip = value & (1 << 0xa) != 0;
-        assert!(h_v_flip == (h_flip && v_flip));
         let has_bounding_rectangle = value & (1 << 0xb) != 0;
         CellAttribute {
             h_flip,
@@ -155,61 +688,229 @@ impl Into<u16> for CellAttribute {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Cell {
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Cell {
     attribute: CellAttribute,
     oam_data: Vec<OamData>,
     bounding_rectangle: Option<BoundingRectangle>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct CellVramTransferData {
+impl Cell {
+    /// Creates a cell without a bounding rectangle.
+    pub fn new(oam_data: Vec<OamData>) -> Self {
+        Self {
+            attribute: CellAttribute::default(),
+            oam_data,
+            bounding_rectangle: None,
+        }
+    }
+
+    /// Sets the attribute, except for whether the cell has a bounding
+    /// rectangle, which is kept in sync with the bounding rectangle itself.
+    pub fn with_attribute(self, attribute: CellAttribute) -> Self {
+        Self {
+            attribute: attribute.with_has_bounding_rectangle(self.bounding_rectangle.is_some()),
+            ..self
+        }
+    }
+
+    /// Sets the bounding rectangle, updating the attribute to match.
+    pub fn with_bounding_rectangle(self, bounding_rectangle: Option<BoundingRectangle>) -> Self {
+        Self {
+            attribute: self
+                .attribute
+                .with_has_bounding_rectangle(bounding_rectangle.is_some()),
+            bounding_rectangle,
+            ..self
+        }
+    }
+
+    pub fn attribute(&self) -> CellAttribute {
+        self.attribute
+    }
+
+    pub fn attribute_mut(&mut self) -> &mut CellAttribute {
+        &mut self.attribute
+    }
+
+    pub fn oam_data(&self) -> &[OamData] {
+        &self.oam_data
+    }
+
+    pub fn oam_data_mut(&mut self) -> &mut Vec<OamData> {
+        &mut self.oam_data
+    }
+
+    pub fn bounding_rectangle(&self) -> Option<BoundingRectangle> {
+        self.bounding_rectangle
+    }
+
+    /// Creates a cell whose bounding rectangle and bounding sphere enclose its
+    /// objects.
+    fn from_oam_data(oam_data: Vec<OamData>) -> Self {
+        let mut cell =
+            Self::new(oam_data).with_bounding_rectangle(Some(BoundingRectangle::new(0, 0, 0, 0)));
+        cell.recompute_bounds();
+        cell
+    }
+
+    /// Returns the smallest rectangle enclosing the areas that the objects are
+    /// drawn in, which is empty at the origin if there are no objects.
+    pub fn computed_bounding_rectangle(&self) -> BoundingRectangle {
+        if self.oam_data.is_empty() {
+            return BoundingRectangle::new(0, 0, 0, 0);
+        }
+        let (min_x, min_y, max_x, max_y) = self.oam_data.iter().fold(
+            (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+            |(min_x, min_y, max_x, max_y), oam| {
+                let (x, y, width, height) = oam.rectangle();
+                (
+                    min_x.min(x),
+                    min_y.min(y),
+                    max_x.max(x + i32::try_from(width).unwrap()),
+                    max_y.max(y + i32::try_from(height).unwrap()),
+                )
+            },
+        );
+        BoundingRectangle::new(
+            min_x.try_into().unwrap(),
+            min_y.try_into().unwrap(),
+            max_x.try_into().unwrap(),
+            max_y.try_into().unwrap(),
+        )
+    }
+
+    /// Returns the radius of the smallest sphere around the origin that
+    /// encloses the bounding rectangle, in units of 4 pixels rounded up.
+    pub fn computed_bounding_sphere_radius(&self) -> u16 {
+        let br = self.computed_bounding_rectangle();
+        let x = i32::from(br.min_x.unsigned_abs().max(br.max_x.unsigned_abs()));
+        let y = i32::from(br.min_y.unsigned_abs().max(br.max_y.unsigned_abs()));
+        let radius = f64::from(x * x + y * y).sqrt().ceil() as u16;
+        radius.div_ceil(4).min(0x3f)
+    }
+
+    /// Replaces the bounding sphere radius, and the bounding rectangle if the
+    /// cell has one, with the ones computed from its objects.
+    pub fn recompute_bounds(&mut self) {
+        self.attribute.bounding_sphere_radius = self.computed_bounding_sphere_radius();
+        if self.bounding_rectangle.is_some() {
+            self.bounding_rectangle = Some(self.computed_bounding_rectangle());
+        }
+    }
+
+    /// Whether the stored bounds match the ones computed from the objects.
+    pub fn has_correct_bounds(&self) -> bool {
+        self.attribute.bounding_sphere_radius == self.computed_bounding_sphere_radius()
+            && self
+                .bounding_rectangle
+                .is_none_or(|br| br == self.computed_bounding_rectangle())
+    }
+}
+
+/// Part of the character data to transfer to VRAM when a cell is drawn.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct CellVramTransferData {
     src_offset: u32,
     size: u32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct VramData {
+impl CellVramTransferData {
+    pub fn new(src_offset: u32, size: u32) -> Self {
+        Self { src_offset, size }
+    }
+
+    /// Offset of the data in bytes from the start of the character data.
+    pub fn src_offset(&self) -> u32 {
+        self.src_offset
+    }
+
+    /// Size of the data in bytes.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// VRAM transfer data of an NCER, with one entry per cell.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VramData {
     max_size: u32,
     data: Vec<CellVramTransferData>,
 }
 
+impl VramData {
+    pub fn new(max_size: u32, data: Vec<CellVramTransferData>) -> Self {
+        Self { max_size, data }
+    }
+
+    /// Size in bytes of the VRAM area reserved for transfers, i.e. the
+    /// largest size of any cell.
+    pub fn max_size(&self) -> u32 {
+        self.max_size
+    }
+
+    pub fn data(&self) -> &[CellVramTransferData] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut Vec<CellVramTransferData> {
+        &mut self.data
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Ncer {
     version: NtrFileVersion,
     cells: Vec<Cell>,
     mapping_type: NtrMappingType,
     vram_data: Option<VramData>,
+
+    /// Only present in JSON written before user extended attributes were
+    /// modelled, where it stands for a single zero attribute per cell.
+    #[serde(default, skip_serializing)]
     has_user_extended_attribute_data: bool,
-    labels: Vec<String>,
+
+    /// The user extended attributes (TACU) of each cell, which all cells must
+    /// have the same number of.
+    #[serde(default)]
+    user_extended_attributes: Option<Vec<Vec<u32>>>,
+
+    /// Contents of the UEXT block.
+    #[serde(default = "default_user_extended_data")]
+    user_extended_data: Vec<u8>,
+
+    #[serde(flatten)]
+    labels: Labels,
 }
 
 impl NtrFormat for Ncer {
-    fn read_from_ntr_file(file: &NtrFile) -> std::io::Result<Self> {
-        assert!(file.id() == "RECN");
+    fn read_from_ntr_file(file: &NtrFile) -> Result<Self> {
+        file.expect_id("RECN")?;
 
-        assert!(file.blocks().len() == 3);
+        file.expect_num_blocks(3)?;
 
-        let cebk_block = &file.blocks()[0];
-        let labl_block = &file.blocks()[1];
-        let uext_block = &file.blocks()[2];
+        let cebk_block = file.block(0, "KBEC")?;
+        let labl_block = file.block(1, "LBAL")?;
+        let uext_block = file.block(2, "TXEU")?;
 
-        let (cells, mapping_type, vram_data, has_user_extended_attribute_data) =
+        let (cells, mapping_type, vram_data, user_extended_attributes) =
             Self::from_cebk_block(cebk_block)?;
-        let labels = NtrFile::read_labl_block(labl_block)?;
-        assert!(uext_block.id() == "TXEU" && uext_block.contents() == [0, 0, 0, 0]);
+        let labels = NtrFile::read_labl_block(labl_block, cells.len())?;
 
         Ok(Self {
             version: file.version(),
             cells,
             mapping_type,
             vram_data,
-            has_user_extended_attribute_data,
+            has_user_extended_attribute_data: false,
+            user_extended_attributes,
+            user_extended_data: uext_block.contents().to_vec(),
             labels,
         })
     }
 
-    fn write_to_ntr_file(&self) -> std::io::Result<NtrFile> {
+    fn write_to_ntr_file(&self) -> Result<NtrFile> {
+        self.validate()?;
         Ok(NtrFile::new(
             "RECN",
             self.version,
@@ -223,31 +924,707 @@ impl NtrFormat for Ncer {
 }
 
 impl Ncer {
-    pub fn from_json(json: &str) -> std::io::Result<Self> {
-        Ok(serde_json::from_str::<Self>(json).unwrap())
+    /// Creates an NCER without any cells. At least one cell must be added
+    /// before it can be written.
+    pub fn new(version: NtrFileVersion, mapping_type: NtrMappingType) -> Self {
+        Self {
+            version,
+            cells: vec![],
+            mapping_type,
+            vram_data: None,
+            has_user_extended_attribute_data: false,
+            user_extended_attributes: None,
+            user_extended_data: default_user_extended_data(),
+            labels: Labels::default(),
+        }
+    }
+
+    pub fn version(&self) -> NtrFileVersion {
+        self.version
+    }
+
+    pub fn mapping_type(&self) -> NtrMappingType {
+        self.mapping_type
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [Cell] {
+        &mut self.cells
+    }
+
+    /// Adds a cell, with zeros for its user extended attributes if the other
+    /// cells have any.
+    pub fn push_cell(&mut self, cell: Cell) {
+        if let Some(attributes) = &mut self.user_extended_attributes {
+            let num_attributes = attributes.first().map_or(0, Vec::len);
+            attributes.push(vec![0; num_attributes]);
+        }
+        self.cells.push(cell);
+    }
+
+    /// Removes the cell at `index` along with its label and user extended
+    /// attributes, if it has them.
+    pub fn remove_cell(&mut self, index: usize) -> Cell {
+        if index < self.labels.labels.len() {
+            self.labels.labels.remove(index);
+        }
+        if let Some(attributes) = &mut self.user_extended_attributes {
+            if index < attributes.len() {
+                attributes.remove(index);
+            }
+        }
+        self.cells.remove(index)
+    }
+
+    /// Returns the cell whose label is `label`, as the label at each index
+    /// names the cell at the same index.
+    pub fn cell_by_label(&self, label: &str) -> Option<&Cell> {
+        let index = self.labels.labels.iter().position(|l| l == label)?;
+        self.cells.get(index)
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels.labels
+    }
+
+    /// There may not be more labels than cells.
+    pub fn labels_mut(&mut self) -> &mut Vec<String> {
+        &mut self.labels.labels
+    }
+
+    pub fn vram_data(&self) -> Option<&VramData> {
+        self.vram_data.as_ref()
+    }
+
+    /// Sets the VRAM transfer data, which must have one entry per cell.
+    pub fn with_vram_data(self, vram_data: Option<VramData>) -> Self {
+        Self { vram_data, ..self }
+    }
+
+    pub fn user_extended_attributes(&self) -> Option<&[Vec<u32>]> {
+        self.user_extended_attributes.as_deref()
+    }
+
+    /// Sets the user extended attributes, which must have one entry per cell,
+    /// each with the same number of attributes.
+    pub fn with_user_extended_attributes(
+        self,
+        user_extended_attributes: Option<Vec<Vec<u32>>>,
+    ) -> Self {
+        Self {
+            user_extended_attributes,
+            ..self
+        }
+    }
+
+    /// Returns the contents of the UEXT block, which are `[0, 0, 0, 0]` unless
+    /// a game stores its own data there.
+    pub fn user_extended_data(&self) -> &[u8] {
+        &self.user_extended_data
+    }
+
+    pub fn with_user_extended_data(self, user_extended_data: Vec<u8>) -> Self {
+        Self {
+            user_extended_data,
+            ..self
+        }
+    }
+
+    /// Computes the VRAM transfer data of the cells, for an NCER whose tile
+    /// numbers index the character data of `ncgr` as a whole.
+    ///
+    /// Each cell transfers the character data from the start of the boundary
+    /// containing its first tile up to the end of its last tile, and its tile
+    /// numbers are made relative to the start of that data.
+    ///
+    /// Fails if the NCER already has VRAM transfer data, as its tile numbers
+    /// are relative to it then. The NCER is left unchanged on failure.
+    pub fn compute_vram_transfer_data(&mut self, ncgr: &Ncgr) -> Result<()> {
+        let (boundary, tile_size, tiles) = self.vram_transfer_parameters(ncgr)?;
+        let mut data = vec![];
+        for (i, cell) in self.cells.iter().enumerate() {
+            let ranges = cell
+                .oam_data
+                .iter()
+                .map(|oam| oam.character_range(boundary, tile_size));
+            let start = ranges.clone().map(|range| range.start).min().unwrap_or(0);
+            let start = start - start % boundary;
+            let end = ranges.map(|range| range.end).max().unwrap_or(start);
+            if end > tiles.len() * tile_size {
+                return Err(Error::invalid_input(format!(
+                    "cell {} uses character data up to {:#x}, but there are only {:#x} bytes",
+                    i,
+                    end,
+                    tiles.len() * tile_size
+                )));
+            }
+            data.push(CellVramTransferData::new(
+                start.try_into().unwrap(),
+                (end - start).try_into().unwrap(),
+            ));
+        }
+        for (cell, cell_data) in self.cells.iter_mut().zip(&data) {
+            let start = usize::try_from(cell_data.src_offset).unwrap();
+            let first_tile_number = u16::try_from(start / boundary).unwrap();
+            for oam in &mut cell.oam_data {
+                oam.tile_number -= first_tile_number;
+            }
+        }
+        let max_size = data.iter().map(|d| d.size).max().unwrap_or(0);
+        self.vram_data = Some(VramData::new(max_size, data));
+        Ok(())
+    }
+
+    /// Same as [`Ncer::compute_vram_transfer_data`], but also returns a copy
+    /// of `ncgr` in which the character data of each cell is contiguous, so
+    /// that no more than needed is transferred.
+    ///
+    /// The tiles of each cell are stored in the order of the objects that use
+    /// them. Objects of a cell that start on the same tile share it, but tiles
+    /// used by several cells are stored once for each of them.
+    pub fn compute_vram_transfer_data_with_reordering(&mut self, ncgr: &Ncgr) -> Result<Ncgr> {
+        let (boundary, tile_size, tiles) = self.vram_transfer_parameters(ncgr)?;
+        let mut cells = self.cells.clone();
+        let mut reordered_tiles = vec![];
+        let mut data = vec![];
+        for (i, cell) in cells.iter_mut().enumerate() {
+            let mut cell_tiles = vec![];
+            // Maps the old tile numbers to the new ones and their length.
+            let mut copied = HashMap::<u16, (u16, usize)>::new();
+            for oam in &mut cell.oam_data {
+                let range = oam.character_range(boundary, tile_size);
+                if let Some((tile_number, len)) = copied.get(&oam.tile_number) {
+                    if *len >= range.len() {
+                        oam.tile_number = *tile_number;
+                        continue;
+                    }
+                }
+                let source = tiles
+                    .get(range.start / tile_size..range.end / tile_size)
+                    .ok_or_else(|| {
+                        Error::invalid_input(format!(
+                            "cell {} uses character data up to {:#x}, but there are only {:#x} bytes",
+                            i,
+                            range.end,
+                            tiles.len() * tile_size
+                        ))
+                    })?;
+                let start = (cell_tiles.len() * tile_size).next_multiple_of(boundary);
+                cell_tiles.resize(start / tile_size, [0; TILE_LENGTH * TILE_LENGTH]);
+                cell_tiles.extend_from_slice(source);
+                let tile_number = u16::try_from(start / boundary).unwrap();
+                copied.insert(oam.tile_number, (tile_number, range.len()));
+                oam.tile_number = tile_number;
+            }
+            data.push(CellVramTransferData::new(
+                (reordered_tiles.len() * tile_size).try_into().unwrap(),
+                (cell_tiles.len() * tile_size).try_into().unwrap(),
+            ));
+            reordered_tiles.extend(cell_tiles);
+        }
+        let max_size = data.iter().map(|d| d.size).max().unwrap_or(0);
+        self.cells = cells;
+        self.vram_data = Some(VramData::new(max_size, data));
+        Ok(ncgr_from_tiles(&reordered_tiles, ncgr.metadata()))
+    }
+
+    /// Returns the size that tile numbers count in, the size of the tiles and
+    /// the tiles themselves, checking that VRAM transfer data can be computed.
+    fn vram_transfer_parameters<'a>(&self, ncgr: &'a Ncgr) -> Result<(usize, usize, &'a [Tile])> {
+        if self.vram_data.is_some() {
+            return Err(Error::invalid_input(
+                "the NCER already has VRAM transfer data, which its tile numbers are relative to",
+            ));
+        }
+        let boundary = mapping_boundary(self.mapping_type)
+            .ok_or_else(|| Error::invalid_input("VRAM transfer requires 1D mapping"))?;
+        let tiles = ncgr
+            .tiles()
+            .ok_or_else(|| Error::invalid_input("VRAM transfer requires character data"))?;
+        let tile_size = match ncgr.metadata().texture_format {
+            NtrTextureFormat::Palette16 => 32,
+            NtrTextureFormat::Palette256 => 64,
+            texture_format => {
+                return Err(Error::invalid_input(format!(
+                    "unsupported texture format {:?}",
+                    texture_format
+                )))
+            }
+        };
+        Ok((boundary, tile_size, tiles))
+    }
+
+    /// Recomputes the bounds of every cell from its objects. See
+    /// [`Cell::recompute_bounds`].
+    pub fn recompute_bounds(&mut self) {
+        for cell in &mut self.cells {
+            cell.recompute_bounds();
+        }
     }
 
-    pub fn to_json(&self) -> std::io::Result<String> {
-        Ok(serde_json::to_string_pretty(&self).unwrap())
+    /// Returns the indexes of the cells whose stored bounds do not match the
+    /// ones computed from their objects.
+    pub fn cells_with_incorrect_bounds(&self) -> Vec<usize> {
+        (0..self.cells.len())
+            .filter(|&i| !self.cells[i].has_correct_bounds())
+            .collect()
+    }
+
+    /// Checks the invariants that cannot be enforced while editing, as cells
+    /// may be edited in any order.
+    fn validate(&self) -> Result<()> {
+        let has_bounding_rectangle = self
+            .cells
+            .first()
+            .ok_or_else(|| Error::invalid_input("at least one cell is required"))?
+            .attribute
+            .has_bounding_rectangle;
+        for (i, cell) in self.cells.iter().enumerate() {
+            if cell.attribute.has_bounding_rectangle != cell.bounding_rectangle.is_some() {
+                return Err(Error::invalid_input(format!(
+                    "bounding rectangle flag of cell {} does not match its bounding rectangle",
+                    i
+                )));
+            }
+            if cell.attribute.has_bounding_rectangle != has_bounding_rectangle {
+                return Err(Error::invalid_input(format!(
+                    "cell {} differs from cell 0 in whether it has a bounding rectangle",
+                    i
+                )));
+            }
+            if cell.attribute.bounding_sphere_radius >= 0x40 {
+                return Err(Error::invalid_input(format!(
+                    "bounding sphere radius {} of cell {} is too large",
+                    cell.attribute.bounding_sphere_radius, i
+                )));
+            }
+            for (j, oam) in cell.oam_data.iter().enumerate() {
+                oam.validate().map_err(|reason| {
+                    Error::invalid_input(format!("object {} of cell {}: {}", j, i, reason))
+                })?;
+            }
+        }
+        if self.labels.labels.len() > self.cells.len() {
+            return Err(Error::invalid_input(format!(
+                "{} labels for {} cells",
+                self.labels.labels.len(),
+                self.cells.len()
+            )));
+        }
+        if let Some(attributes) = &self.user_extended_attributes {
+            if attributes.len() != self.cells.len() {
+                return Err(Error::invalid_input(format!(
+                    "user extended attributes for {} cells, but there are {} cells",
+                    attributes.len(),
+                    self.cells.len()
+                )));
+            }
+            if attributes.iter().any(|a| a.len() != attributes[0].len()) {
+                return Err(Error::invalid_input(
+                    "all cells must have the same number of user extended attributes",
+                ));
+            }
+        }
+        if let Some(vram_data) = &self.vram_data {
+            if vram_data.data.len() != self.cells.len() {
+                return Err(Error::invalid_input(format!(
+                    "{} VRAM transfer entries for {} cells",
+                    vram_data.data.len(),
+                    self.cells.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut ncer = serde_json::from_str::<Self>(json)?;
+        if std::mem::take(&mut ncer.has_user_extended_attribute_data)
+            && ncer.user_extended_attributes.is_none()
+        {
+            ncer.user_extended_attributes = Some(vec![vec![0]; ncer.cells.len()]);
+        }
+        Ok(ncer)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self)?)
+    }
+
+    /// Renders the cell at `index` using the tiles of `ncgr` and the colors of
+    /// `nclr`.
+    ///
+    /// Objects with a lower priority value are drawn on top, and among objects
+    /// with the same priority, the ones that come first in the cell are drawn
+    /// on top. The image covers the bounding rectangle of the cell if it has
+    /// one, and the area covered by its objects otherwise. Pixels not covered
+    /// by any object use the first (transparent) color. The first color of
+    /// every bank is given an alpha of 0, so that it is displayed as
+    /// transparent.
+    ///
+    /// If `nclr` is an extended palette, the image of a 256-color cell uses
+    /// the bank selected by the palette number of its objects. The palette
+    /// number of a 16-color object must be one of the banks of `nclr`.
+    ///
+    /// Affine objects are drawn untransformed. Use
+    /// [`Ncer::render_cell_with_affine_matrices`] to transform them.
+    pub fn render_cell(&self, index: usize, ncgr: &Ncgr, nclr: &Nclr) -> Result<Image> {
+        self.render_cell_with_affine_matrices(index, ncgr, nclr, &[])
+    }
+
+    /// Same as [`Ncer::render_cell`], but draws each affine object transformed
+    /// by the matrix at its affine index. Objects whose index is beyond the
+    /// end of `matrices` are drawn untransformed.
+    pub fn render_cell_with_affine_matrices(
+        &self,
+        index: usize,
+        ncgr: &Ncgr,
+        nclr: &Nclr,
+        matrices: &[AffineMatrix],
+    ) -> Result<Image> {
+        let cell = self
+            .cells
+            .get(index)
+            .ok_or_else(|| Error::invalid_input(format!("cell {} does not exist", index)))?;
+        let tiles = ncgr
+            .tiles()
+            .ok_or_else(|| Error::invalid_input("cannot render cells from bitmap data"))?;
+        let texture_format = ncgr.metadata().texture_format;
+        let (color_mode, tile_size) = match texture_format {
+            NtrTextureFormat::Palette16 => (0, 32),
+            NtrTextureFormat::Palette256 => (1, 64),
+            _ => {
+                return Err(Error::invalid_input(format!(
+                    "unsupported texture format {:?}",
+                    texture_format
+                )))
+            }
+        };
+
+        let mut objects = cell
+            .oam_data
+            .iter()
+            .enumerate()
+            .filter(|(_, oam)| oam.is_drawn())
+            .collect::<Vec<_>>();
+
+        let (left, top, right, bottom) = match &cell.bounding_rectangle {
+            Some(br) => (
+                br.min_x.into(),
+                br.min_y.into(),
+                br.max_x.into(),
+                br.max_y.into(),
+            ),
+            None => objects.iter().fold(
+                (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+                |(left, top, right, bottom), (_, oam)| {
+                    let (x, y, width, height) = oam.rectangle();
+                    (
+                        left.min(x),
+                        top.min(y),
+                        right.max(x + i32::try_from(width).unwrap()),
+                        bottom.max(y + i32::try_from(height).unwrap()),
+                    )
+                },
+            ),
+        };
+        if left >= right || top >= bottom {
+            return Err(Error::invalid_input(format!("cell {} is empty", index)));
+        }
+        let width = usize::try_from(right - left).unwrap();
+        let height = usize::try_from(bottom - top).unwrap();
+        let mut pixels = vec![0; width * height];
+
+        // With VRAM transfer, tile numbers are relative to the character data
+        // transferred for the cell.
+        let first_tile_index = self
+            .vram_data
+            .as_ref()
+            .and_then(|vram_data| vram_data.data.get(index))
+            .map_or(0, |data| {
+                usize::try_from(data.src_offset).unwrap() / tile_size
+            });
+
+        // With an extended palette, 256-color objects select one of its banks,
+        // so all the objects of the cell must use the same one.
+        let palette = match (color_mode, nclr.metadata().extended) {
+            (1, true) => {
+                let bank = objects.first().map_or(0, |(_, oam)| oam.palette_number);
+                if let Some((i, _)) = objects.iter().find(|(_, oam)| oam.palette_number != bank) {
+                    return Err(Error::invalid_input(format!(
+                        "object {} of cell {} uses a different extended palette bank",
+                        i, index
+                    )));
+                }
+                nclr.to_palette()
+                    .bank_for_format(bank.into(), texture_format)
+                    .ok_or_else(|| {
+                        Error::invalid_input(format!(
+                            "cell {} uses extended palette bank {}, which does not exist",
+                            index, bank
+                        ))
+                    })?
+            }
+            _ => nclr.to_palette(),
+        };
+
+        // Objects drawn later end up on top.
+        objects.sort_by_key(|(i, oam)| (Reverse(oam.priority), Reverse(*i)));
+        for (i, oam) in objects {
+            if oam.color_mode != color_mode {
+                return Err(Error::invalid_input(format!(
+                    "object {} of cell {} does not match the texture format {:?}",
+                    i, index, texture_format
+                )));
+            }
+            if color_mode == 0 && usize::from(oam.palette_number) >= palette.num_banks() {
+                return Err(Error::invalid_input(format!(
+                    "object {} of cell {} uses palette {}, but there are only {} banks",
+                    i,
+                    index,
+                    oam.palette_number,
+                    palette.num_banks()
+                )));
+            }
+            let (x, y, area_width, area_height) = oam.rectangle();
+            for area_y in 0..area_height {
+                for area_x in 0..area_width {
+                    let canvas_x = x + i32::try_from(area_x).unwrap() - left;
+                    let canvas_y = y + i32::try_from(area_y).unwrap() - top;
+                    if !(0..i32::try_from(width).unwrap()).contains(&canvas_x)
+                        || !(0..i32::try_from(height).unwrap()).contains(&canvas_y)
+                    {
+                        continue;
+                    }
+                    let Some((pixel_x, pixel_y)) = oam.source_pixel(area_x, area_y, matrices)
+                    else {
+                        continue;
+                    };
+                    let tile_index = first_tile_index
+                        + oam.tile_index(
+                            self.mapping_type,
+                            pixel_x / TILE_LENGTH,
+                            pixel_y / TILE_LENGTH,
+                            tile_size,
+                        );
+                    let tile = tiles.get(tile_index).ok_or_else(|| {
+                        Error::invalid_input(format!(
+                            "object {} of cell {} uses tile {}, but there are only {} tiles",
+                            i,
+                            index,
+                            tile_index,
+                            tiles.len()
+                        ))
+                    })?;
+                    let value = tile[(pixel_y % TILE_LENGTH) * TILE_LENGTH + pixel_x % TILE_LENGTH];
+                    if value == 0 {
+                        continue;
+                    }
+                    let canvas_index = usize::try_from(canvas_y).unwrap() * width
+                        + usize::try_from(canvas_x).unwrap();
+                    pixels[canvas_index] = match color_mode {
+                        0 => Palette::color_index(oam.palette_number, value),
+                        _ => value,
+                    };
+                }
+            }
+        }
+
+        let palette = palette.with_transparent_first_colors(texture_format);
+        Ok(Image::new(width, &pixels, Some(palette)))
+    }
+
+    /// Builds an NCER with one cell per image, along with an NCGR containing
+    /// the tiles of the cells, which must use 1D mapping.
+    ///
+    /// Each image is centered on the origin of its cell, and its opaque tiles
+    /// are covered with as few objects as possible. Objects start on tile
+    /// number boundaries of the mapping type, and their tiles are stored
+    /// one after the other in the NCGR. In 4bpp, the upper 4 bits of the
+    /// pixels select the palette, so each object may only use a single one.
+    pub fn from_images(images: &[Image], ncgr_metadata: NcgrMetadata) -> Result<(Self, Ncgr)> {
+        let mapping_type = ncgr_metadata.mapping_type;
+        let boundary = mapping_boundary(mapping_type)
+            .ok_or_else(|| Error::invalid_input("cells can only be built with 1D mapping"))?;
+        let texture_format = ncgr_metadata.texture_format;
+        let (color_mode, tile_size) = match texture_format {
+            NtrTextureFormat::Palette16 => (0, 32),
+            NtrTextureFormat::Palette256 => (1, 64),
+            _ => {
+                return Err(Error::invalid_input(format!(
+                    "unsupported texture format {:?}",
+                    texture_format
+                )))
+            }
+        };
+        if matches!(ncgr_metadata.character_format, NtrCharacterFormat::Bitmap) {
+            return Err(Error::invalid_input("cannot build cells from bitmap data"));
+        }
+
+        let mut tiles = vec![];
+        let mut cells = vec![];
+        for (index, image) in images.iter().enumerate() {
+            let width_in_tiles = image.width().div_ceil(TILE_LENGTH);
+            let height_in_tiles = image.height().div_ceil(TILE_LENGTH);
+            let pixel = |x: usize, y: usize| match x < image.width() && y < image.height() {
+                true => image.pixels()[y * image.width() + x],
+                false => 0,
+            };
+
+            // The palette of each opaque tile, or `None` if it is transparent.
+            let mut tile_palettes = vec![None; width_in_tiles * height_in_tiles];
+            for tile_y in 0..height_in_tiles {
+                for tile_x in 0..width_in_tiles {
+                    let mut palettes = (0..TILE_LENGTH * TILE_LENGTH)
+                        .map(|i| {
+                            pixel(
+                                tile_x * TILE_LENGTH + i % TILE_LENGTH,
+                                tile_y * TILE_LENGTH + i / TILE_LENGTH,
+                            )
+                        })
+                        .filter_map(|value| match color_mode {
+                            0 => match Palette::split_color_index(value) {
+                                (_, 0) => None,
+                                (bank, _) => Some(bank),
+                            },
+                            _ => (value != 0).then_some(0),
+                        });
+                    let Some(palette) = palettes.next() else {
+                        continue;
+                    };
+                    if palettes.any(|p| p != palette) {
+                        return Err(Error::invalid_input(format!(
+                            "tile ({}, {}) of image {} uses more than one palette",
+                            tile_x, tile_y, index
+                        )));
+                    }
+                    tile_palettes[tile_y * width_in_tiles + tile_x] = Some(palette);
+                }
+            }
+
+            let origin_x = i32::try_from(image.width() / 2).unwrap();
+            let origin_y = i32::try_from(image.height() / 2).unwrap();
+            let mut covered = vec![false; tile_palettes.len()];
+            let mut oam_data = vec![];
+            while let Some(first) =
+                (0..covered.len()).find(|&i| tile_palettes[i].is_some() && !covered[i])
+            {
+                let (tile_x, tile_y) = (first % width_in_tiles, first / width_in_tiles);
+                let palette = tile_palettes[first];
+
+                // Take the size that covers the most remaining tiles, and the
+                // smallest one among those. An object may not cover tiles of
+                // another palette, as it would draw them in the wrong colors.
+                let (oam_size, _) = OamSize::ALL
+                    .iter()
+                    .filter_map(|&oam_size| {
+                        let (width, height) = oam_size.dimensions();
+                        let mut num_covered = 0;
+                        for y in tile_y..(tile_y + height / TILE_LENGTH).min(height_in_tiles) {
+                            for x in tile_x..(tile_x + width / TILE_LENGTH).min(width_in_tiles) {
+                                let i = y * width_in_tiles + x;
+                                match tile_palettes[i] {
+                                    None => {}
+                                    Some(_) if tile_palettes[i] != palette => return None,
+                                    Some(_) => num_covered += usize::from(!covered[i]),
+                                }
+                            }
+                        }
+                        Some((oam_size, (num_covered, Reverse(width * height))))
+                    })
+                    .max_by_key(|(_, score)| *score)
+                    .unwrap();
+                let (width, height) = oam_size.dimensions();
+
+                let start = (tiles.len() * tile_size).next_multiple_of(boundary);
+                tiles.resize(start / tile_size, [0; TILE_LENGTH * TILE_LENGTH]);
+                let tile_number = u16::try_from(start / boundary)
+                    .ok()
+                    .filter(|tile_number| *tile_number < 0x400)
+                    .ok_or_else(|| {
+                        Error::invalid_input(format!(
+                            "too many tiles for mapping type {:?}",
+                            mapping_type
+                        ))
+                    })?;
+                for y in tile_y..tile_y + height / TILE_LENGTH {
+                    for x in tile_x..tile_x + width / TILE_LENGTH {
+                        if x < width_in_tiles && y < height_in_tiles {
+                            covered[y * width_in_tiles + x] = true;
+                        }
+                        let mut tile = [0; TILE_LENGTH * TILE_LENGTH];
+                        for (i, value) in tile.iter_mut().enumerate() {
+                            let value_x = x * TILE_LENGTH + i % TILE_LENGTH;
+                            let value_y = y * TILE_LENGTH + i / TILE_LENGTH;
+                            *value = match color_mode {
+                                0 => Palette::split_color_index(pixel(value_x, value_y)).1,
+                                _ => pixel(value_x, value_y),
+                            };
+                        }
+                        tiles.push(tile);
+                    }
+                }
+
+                let x = i32::try_from(tile_x * TILE_LENGTH).unwrap() - origin_x;
+                let y = i32::try_from(tile_y * TILE_LENGTH).unwrap() - origin_y;
+                let y = i8::try_from(y)
+                    .map_err(|_| Error::invalid_input(format!("image {} is too tall", index)))?;
+                let x = i16::try_from(x)
+                    .ok()
+                    .filter(|x| (-256..256).contains(x))
+                    .ok_or_else(|| Error::invalid_input(format!("image {} is too wide", index)))?;
+                oam_data.push(
+                    OamData::new(oam_size, tile_number)
+                        .with_position(x, y)
+                        .with_color_mode(color_mode)
+                        .with_palette_number(palette.unwrap()),
+                );
+            }
+            cells.push(Cell::from_oam_data(oam_data));
+        }
+
+        let ncgr = ncgr_from_tiles(&tiles, ncgr_metadata);
+        let ncer = Self {
+            cells,
+            ..Self::new(ncgr_metadata.version, mapping_type)
+        };
+        Ok((ncer, ncgr))
     }
 
     fn from_cebk_block(
         block: &NtrFileBlock,
-    ) -> std::io::Result<(Vec<Cell>, NtrMappingType, Option<VramData>, bool)> {
-        assert!(block.id() == "KBEC");
-        let mut cebk = block.contents();
+    ) -> Result<(
+        Vec<Cell>,
+        NtrMappingType,
+        Option<VramData>,
+        UserExtendedAttributes,
+    )> {
+        let mut cebk = block.reader();
+        let num_cells_offset = cebk.offset();
         let num_cells = cebk.read_u16()?;
-        assert!(num_cells > 0);
+        if num_cells == 0 {
+            return Err(cebk.error_at(num_cells_offset, "no cells present"));
+        }
+        let cell_bank_attributes_offset = cebk.offset();
         let cell_bank_attributes = cebk.read_u16()?;
-        assert!(cell_bank_attributes == 0 || cell_bank_attributes == 1);
+        if cell_bank_attributes > 1 {
+            return Err(cebk.error_at(
+                cell_bank_attributes_offset,
+                format!("invalid cell bank attributes {:#x}", cell_bank_attributes),
+            ));
+        }
         let has_bounding_rectangle = cell_bank_attributes != 0;
-        assert!(cebk.read_u32()? == 0x00000018);
-        let mapping_type = NtrMappingType::from_u32_ncer(cebk.read_u32()?);
+        cebk.expect_u32(0x00000018)?;
+        let mapping_type_offset = cebk.offset();
+        let mapping_type = NtrMappingType::try_from_u32_ncer(cebk.read_u32()?)
+            .map_err(|e| cebk.enum_error_at(mapping_type_offset, e))?;
         let vram_offset = cebk.read_u32()?;
-        assert!(cebk.read_u32()? == 0);
+        cebk.expect_u32(0)?;
 
         let user_extended_attribute_data_offset = cebk.read_u32()?;
-        let has_user_extended_attribute_data = user_extended_attribute_data_offset != 0;
 
         let mut cells = vec![];
         let mut cell_attributes = VecDeque::new();
@@ -257,8 +1634,23 @@ impl Ncer {
         for _ in 0..num_cells {
             let num_oam_attributes = cebk.read_u16()?;
             list_num_oam_attributes.push_back(num_oam_attributes);
-            let cell_attribute: CellAttribute = cebk.read_u16()?.into();
-            assert!(has_bounding_rectangle == cell_attribute.has_bounding_rectangle);
+            let cell_attribute_offset = cebk.offset();
+            let raw_cell_attribute = cebk.read_u16()?;
+            let cell_attribute: CellAttribute = raw_cell_attribute.into();
+            if (raw_cell_attribute & (1 << 0xa) != 0)
+                != (cell_attribute.h_flip && cell_attribute.v_flip)
+            {
+                return Err(cebk.error_at(
+                    cell_attribute_offset,
+                    "h/v flip bit does not match the h flip and v flip bits",
+                ));
+            }
+            if has_bounding_rectangle != cell_attribute.has_bounding_rectangle {
+                return Err(cebk.error_at(
+                    cell_attribute_offset,
+                    "bounding rectangle flag does not match the cell bank attributes",
+                ));
+            }
             cell_attributes.push_back(cell_attribute);
             let _oam_attrs_offset = cebk.read_u32()?;
             let bounding_rectangle = if has_bounding_rectangle {
@@ -276,10 +1668,15 @@ impl Ncer {
         for _ in 0..num_cells {
             let mut oam_data = vec![];
             for _ in 0..list_num_oam_attributes.pop_front().unwrap() {
+                let offset = cebk.offset();
                 let attr0 = cebk.read_u16()?;
                 let attr1 = cebk.read_u16()?;
                 let attr2 = cebk.read_u16()?;
-                oam_data.push((attr0, attr1, attr2).into());
+                oam_data.push(
+                    (attr0, attr1, attr2)
+                        .try_into()
+                        .map_err(|e| cebk.enum_error_at(offset, e))?,
+                );
             }
             cells.push(Cell {
                 attribute: cell_attributes.pop_front().unwrap(),
@@ -300,7 +1697,7 @@ impl Ncer {
             None
         } else {
             let max_size = cebk.read_u32()?;
-            assert!(cebk.read_u32()? == 0x00000008);
+            cebk.expect_u32(0x00000008)?;
             let mut transfer_data = vec![];
             for _ in 0..num_cells {
                 transfer_data.push(CellVramTransferData {
@@ -314,30 +1711,49 @@ impl Ncer {
             })
         };
 
-        if user_extended_attribute_data_offset != 0 {
-            assert!(cebk.read_string(4)? == "TACU");
-            let user_extended_attribute_data_size = cebk.read_u32()?;
-            assert!(cebk.read_u16()? == num_cells);
-            assert!(user_extended_attribute_data_size == u32::from(16 + num_cells * 8));
-            assert!(cebk.read_u16()? == 0x0001);
-            assert!(cebk.read_u32()? == 0x00000008);
+        let user_extended_attributes = if user_extended_attribute_data_offset != 0 {
+            let magic_offset = cebk.offset();
+            let magic = cebk.read_string(4)?;
+            if magic != "TACU" {
+                return Err(Error::BadMagic {
+                    offset: magic_offset,
+                    block: cebk.block().to_string(),
+                    expected: "TACU".to_string(),
+                    found: magic,
+                });
+            }
+            let size_offset = cebk.offset();
+            let size = cebk.read_u32()?;
+            cebk.expect_u16(num_cells)?;
+            let num_attributes = u32::from(cebk.read_u16()?);
+            let num_cells = u32::from(num_cells);
+            if size != 16 + 4 * num_cells * (1 + num_attributes) {
+                return Err(cebk.error_at(
+                    size_offset,
+                    format!("invalid user extended attribute data size {:#x}", size),
+                ));
+            }
+            cebk.expect_u32(0x00000008)?;
             for i in 0..num_cells {
-                assert!(cebk.read_u32()? == u32::from(8 + 4 * (num_cells + i)));
+                cebk.expect_u32(8 + 4 * (num_cells + i * num_attributes))?;
             }
+            let mut user_extended_attributes = vec![];
             for _ in 0..num_cells {
-                assert!(cebk.read_u32()? == 0x00000000);
+                user_extended_attributes.push(
+                    (0..num_attributes)
+                        .map(|_| cebk.read_u32())
+                        .collect::<Result<Vec<_>>>()?,
+                );
             }
+            Some(user_extended_attributes)
+        } else {
+            None
         };
 
-        Ok((
-            cells,
-            mapping_type,
-            vram_data,
-            has_user_extended_attribute_data,
-        ))
+        Ok((cells, mapping_type, vram_data, user_extended_attributes))
     }
 
-    fn to_cebk_block(&self) -> std::io::Result<NtrFileBlock> {
+    fn to_cebk_block(&self) -> Result<NtrFileBlock> {
         let mut cell_oam_data = vec![];
         let mut oam_attrs_offsets = vec![];
         for cell in &self.cells {
@@ -380,18 +1796,19 @@ impl Ncer {
         let vram_data_len = u32::try_from(vram_data.len()).unwrap();
 
         let mut user_extended_attribute_data = vec![];
-        if self.has_user_extended_attribute_data {
-            let num_cells: u16 = self.cells.len().try_into().unwrap();
+        if let Some(attributes) = &self.user_extended_attributes {
+            let num_cells = u32::try_from(attributes.len()).unwrap();
+            let num_attributes = u32::try_from(attributes[0].len()).unwrap();
             user_extended_attribute_data.write_string("TACU")?;
-            user_extended_attribute_data.write_u32((16 + num_cells * 8).into())?;
-            user_extended_attribute_data.write_u16(num_cells)?;
-            user_extended_attribute_data.write_u16(0x0001)?;
+            user_extended_attribute_data.write_u32(16 + 4 * num_cells * (1 + num_attributes))?;
+            user_extended_attribute_data.write_u16(num_cells.try_into().unwrap())?;
+            user_extended_attribute_data.write_u16(num_attributes.try_into().unwrap())?;
             user_extended_attribute_data.write_u32(0x00000008)?;
             for i in 0..num_cells {
-                user_extended_attribute_data.write_u32(u32::from(8 + 4 * (num_cells + i)))?;
+                user_extended_attribute_data.write_u32(8 + 4 * (num_cells + i * num_attributes))?;
             }
-            for _ in 0..num_cells {
-                user_extended_attribute_data.write_u32(0x00000000)?;
+            for value in attributes.iter().flatten() {
+                user_extended_attribute_data.write_u32(*value)?;
             }
         }
 
@@ -407,9 +1824,9 @@ impl Ncer {
         };
         cebk.write_u32(vram_offset)?;
         cebk.write_u32(0x00000000)?;
-        let user_extended_attribute_data_offset = match self.has_user_extended_attribute_data {
-            true => 0x00000018 + cell_data_len + vram_data_len,
-            false => 0,
+        let user_extended_attribute_data_offset = match self.user_extended_attributes {
+            Some(_) => 0x00000018 + cell_data_len + vram_data_len,
+            None => 0,
         };
         cebk.write_u32(user_extended_attribute_data_offset)?;
         cebk.write_all(&cell_data)?;
@@ -419,8 +1836,8 @@ impl Ncer {
         Ok(NtrFileBlock::new("KBEC", cebk))
     }
 
-    fn to_uext_block(&self) -> std::io::Result<NtrFileBlock> {
-        Ok(NtrFileBlock::new("TXEU", vec![0, 0, 0, 0]))
+    fn to_uext_block(&self) -> Result<NtrFileBlock> {
+        Ok(NtrFileBlock::new("TXEU", self.user_extended_data.clone()))
     }
 }
 
@@ -429,11 +1846,11 @@ impl FileFormat for Ncer {
         "NCER".to_string()
     }
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
+    fn read_from_data(data: &[u8]) -> Result<Self> {
         NtrFormat::read_from_data(data)
     }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
+    fn write_to_data(&self) -> Result<Vec<u8>> {
         NtrFormat::write_to_data(self)
     }
 }
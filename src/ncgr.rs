@@ -1,11 +1,17 @@
-use std::{io::Write, vec};
+use std::{io::Write, path::Path, vec};
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
     enums::{NtrCharacterFormat, NtrFileVersion, NtrMappingType, NtrTextureFormat},
-    format::FileFormat,
+    error::{Error, Result},
+    format::{read_sidecar, write_sidecar, FileFormat},
     image::{pixels_to_tiles, tiles_to_pixels, Image, TILE_LENGTH},
     ntr::{NtrFile, NtrFileBlock, NtrFormat, NtrMetadata},
-    read_write_ext::{ReadExt, WriteExt},
+    palette::Palette,
+    png::Png,
+    quantize::Quantization,
+    read_write_ext::WriteExt,
 };
 
 type Tile = [u8; TILE_LENGTH * TILE_LENGTH];
@@ -18,10 +24,24 @@ enum Mapping1DVariant {
     Vram256,
 }
 
+impl Mapping1DVariant {
+    fn new(mapping_type: NtrMappingType) -> Self {
+        match mapping_type {
+            NtrMappingType::Mode2D => unreachable!(),
+            NtrMappingType::Mode1D32K => Self::Vram32,
+            NtrMappingType::Mode1D64K => Self::Vram64,
+            NtrMappingType::Mode1D128K => Self::Vram128,
+            NtrMappingType::Mode1D256K => Self::Vram256,
+        }
+    }
+}
+
+/// The mapping type, along with the dimensions in tiles. With 1D mapping, the
+/// dimensions are usually not stored, i.e. they are set to 0xFFFF.
 #[derive(Debug)]
 enum MappingData {
     TwoD((usize, usize)),
-    OneD(Mapping1DVariant),
+    OneD(Mapping1DVariant, Option<(usize, usize)>),
 }
 
 #[derive(Debug)]
@@ -30,7 +50,11 @@ enum CharacterData {
     Bitmap(Vec<u8>),
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+/// Can be stored in a `<name>.ncgr.json` sidecar file next to the image, so
+/// that the NCGR can be rebuilt from the image alone. Fields missing from the
+/// sidecar take their default values.
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
 pub struct NcgrMetadata {
     pub version: NtrFileVersion,
     pub texture_format: NtrTextureFormat,
@@ -39,6 +63,10 @@ pub struct NcgrMetadata {
 
     /// Whether the CPOS block is included. Defaults to false.
     pub include_cpos: bool,
+
+    /// Whether the dimensions are stored with 1D mapping instead of being set
+    /// to 0xFFFF. They are always stored with 2D mapping. Defaults to false.
+    pub store_dimensions: bool,
 }
 
 impl Into<NtrMetadata> for NcgrMetadata {
@@ -81,6 +109,39 @@ impl NcgrMetadata {
             ..self
         }
     }
+
+    pub fn store_dimensions(self, store_dimensions: bool) -> Self {
+        Self {
+            store_dimensions,
+            ..self
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str::<Self>(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self)?)
+    }
+
+    /// Reads the `<name>.ncgr.json` sidecar file next to `path`, returning
+    /// `None` if there is no such file.
+    pub fn read_sidecar<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        read_sidecar(path.as_ref(), &Ncgr::extension())
+    }
+
+    /// Writes the metadata to the `<name>.ncgr.json` sidecar file next to
+    /// `path`.
+    pub fn write_sidecar<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        write_sidecar(self, path.as_ref(), &Ncgr::extension())
+    }
 }
 
 #[derive(Debug)]
@@ -93,51 +154,79 @@ pub struct Ncgr {
 }
 
 impl NtrFormat for Ncgr {
-    fn read_from_ntr_file(file: &NtrFile) -> std::io::Result<Self> {
-        assert!(file.id() == "RGCN");
+    fn read_from_ntr_file(file: &NtrFile) -> Result<Self> {
+        file.expect_id("RGCN")?;
 
-        let char_block = &file.blocks()[0];
-        assert!(char_block.id() == "RAHC");
-        let mut char = char_block.contents();
+        let char_block = file.block(0, "RAHC")?;
+        let mut char = char_block.reader();
 
         let height_in_tiles = char.read_u16()?;
         let width_in_tiles = char.read_u16()?;
-        let texture_format: NtrTextureFormat = char.read_u16()?.into();
+        let texture_format_offset = char.offset();
+        let texture_format: NtrTextureFormat = char.read_u16_as()?;
         match texture_format {
             NtrTextureFormat::Palette16 | NtrTextureFormat::Palette256 => {}
-            _ => panic!(),
+            _ => {
+                return Err(Error::UnsupportedTextureFormat {
+                    offset: texture_format_offset,
+                    block: char.block().to_string(),
+                    value: texture_format.into(),
+                })
+            }
         };
         _ = char.read_u16()?;
 
-        let mapping_type = NtrMappingType::from_u32_ncgr(char.read_u32()?);
+        let mapping_type_offset = char.offset();
+        let mapping_type = NtrMappingType::try_from_u32_ncgr(char.read_u32()?)
+            .map_err(|e| char.enum_error_at(mapping_type_offset, e))?;
         let mapping_data = if matches!(mapping_type, NtrMappingType::Mode2D) {
-            assert!(height_in_tiles != 0xFFFF);
-            assert!(width_in_tiles != 0xFFFF);
+            if height_in_tiles == 0xFFFF || width_in_tiles == 0xFFFF {
+                return Err(char.error_at(
+                    char_block.offset() + 8,
+                    "2D mapping requires the dimensions to be set",
+                ));
+            }
             MappingData::TwoD((width_in_tiles.into(), height_in_tiles.into()))
         } else {
-            assert!(height_in_tiles == 0xFFFF);
-            assert!(width_in_tiles == 0xFFFF);
-            match mapping_type {
-                NtrMappingType::Mode2D => unreachable!(),
-                NtrMappingType::Mode1D32K => MappingData::OneD(Mapping1DVariant::Vram32),
-                NtrMappingType::Mode1D64K => MappingData::OneD(Mapping1DVariant::Vram64),
-                NtrMappingType::Mode1D128K => MappingData::OneD(Mapping1DVariant::Vram128),
-                NtrMappingType::Mode1D256K => MappingData::OneD(Mapping1DVariant::Vram256),
-            }
+            let dimensions = match (width_in_tiles, height_in_tiles) {
+                (0xFFFF, 0xFFFF) => None,
+                (0xFFFF, _) | (_, 0xFFFF) => {
+                    return Err(char.error_at(
+                        char_block.offset() + 8,
+                        "1D mapping requires both dimensions to be either set or 0xFFFF",
+                    ))
+                }
+                (width, height) => Some((width.into(), height.into())),
+            };
+            MappingData::OneD(Mapping1DVariant::new(mapping_type), dimensions)
         };
 
-        let character_format: NtrCharacterFormat = char.read_u32()?.into();
+        let character_format: NtrCharacterFormat = char.read_u32_as()?;
         let tiles_size = char.read_u32()?;
-        let tiles_offset = char.read_u32()?;
-        assert!(tiles_offset == 0x00000018);
+        char.expect_u32(0x00000018)?;
 
+        let raw_data_offset = char.offset();
         let raw_data = char.read_sized(tiles_size.try_into().unwrap())?;
+        let bytes_per_tile = match texture_format {
+            NtrTextureFormat::Palette16 => TILE_LENGTH * TILE_LENGTH / 2,
+            _ => TILE_LENGTH * TILE_LENGTH,
+        };
+        if !matches!(character_format, NtrCharacterFormat::Bitmap)
+            && !raw_data.len().is_multiple_of(bytes_per_tile)
+        {
+            return Err(char.error_at(
+                raw_data_offset,
+                format!(
+                    "character data size {:#x} is not a whole number of tiles",
+                    raw_data.len()
+                ),
+            ));
+        }
         let character_data =
-            Self::raw_data_to_character_data(&raw_data, texture_format, character_format);
+            Self::raw_data_to_character_data(raw_data, texture_format, character_format)?;
 
         let include_cpos = if file.blocks().len() > 1 {
-            let cpos_block = &file.blocks()[1];
-            assert!(cpos_block.id() == "SOPC");
+            file.block(1, "SOPC")?;
             true
         } else {
             false
@@ -152,7 +241,7 @@ impl NtrFormat for Ncgr {
         })
     }
 
-    fn write_to_ntr_file(&self) -> std::io::Result<crate::ntr::NtrFile> {
+    fn write_to_ntr_file(&self) -> Result<crate::ntr::NtrFile> {
         let mut blocks = vec![self.to_char_block()?];
         if self.include_cpos {
             blocks.push(self.to_cpos_block()?);
@@ -163,7 +252,18 @@ impl NtrFormat for Ncgr {
 }
 
 impl Ncgr {
+    /// Converts an indexed image to character data. In 16-color mode, the
+    /// bank is selected by the screen entries or objects that use the tiles,
+    /// so only the index of each pixel within its 16-color bank is kept.
     pub fn from_image(image: Image, metadata: NcgrMetadata) -> Self {
+        let pixels = match metadata.texture_format {
+            NtrTextureFormat::Palette16 => image
+                .pixels()
+                .iter()
+                .map(|pixel| Palette::split_color_index(*pixel).1)
+                .collect(),
+            _ => image.pixels().to_vec(),
+        };
         Self {
             version: metadata.version,
             texture_format: metadata.texture_format,
@@ -171,31 +271,55 @@ impl Ncgr {
                 NtrMappingType::Mode2D => {
                     MappingData::TwoD((image.width_in_tiles(), image.height_in_tiles()))
                 }
-                NtrMappingType::Mode1D32K => MappingData::OneD(Mapping1DVariant::Vram32),
-                NtrMappingType::Mode1D64K => MappingData::OneD(Mapping1DVariant::Vram64),
-                NtrMappingType::Mode1D128K => MappingData::OneD(Mapping1DVariant::Vram128),
-                NtrMappingType::Mode1D256K => MappingData::OneD(Mapping1DVariant::Vram256),
+                mapping_type => MappingData::OneD(
+                    Mapping1DVariant::new(mapping_type),
+                    metadata
+                        .store_dimensions
+                        .then(|| (image.width_in_tiles(), image.height_in_tiles())),
+                ),
             },
             character_data: match metadata.character_format {
                 NtrCharacterFormat::Character | NtrCharacterFormat::Character256 => {
                     CharacterData::Character(
-                        pixels_to_tiles(image.pixels(), image.width_in_tiles()),
+                        pixels_to_tiles(&pixels, image.width_in_tiles()),
                         metadata.character_format.into(),
                     )
                 }
-                NtrCharacterFormat::Bitmap => CharacterData::Bitmap(image.pixels().to_vec()),
+                NtrCharacterFormat::Bitmap => CharacterData::Bitmap(pixels),
             },
             include_cpos: metadata.include_cpos,
         }
     }
 
+    /// Reads a PNG, restoring the metadata embedded in it by
+    /// [`Png::with_ncgr_metadata`]. If the PNG has no embedded metadata, it is
+    /// read from the `<name>.ncgr.json` sidecar file instead, and failing
+    /// that, the default metadata is used. Malformed embedded metadata is an
+    /// error rather than a reason to fall back to the sidecar.
+    ///
+    /// Truecolor and grayscale PNGs are quantized to a palette for the
+    /// texture format of the metadata (see [`Quantization::for_texture_format`]).
+    pub fn read_from_png_with_sidecar<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let png = Png::read_from_file(&path)?;
+        let metadata = match png.ncgr_metadata() {
+            Some(metadata) => metadata,
+            None => NcgrMetadata::read_sidecar(&path)?.unwrap_or_default(),
+        };
+        let quantization = Quantization::for_texture_format(metadata.texture_format);
+        let image = png.to_image_with_quantization(&quantization)?;
+        Ok(Self::from_image(image, metadata))
+    }
+
     /// Can be called only when `mapping_type` in [NcgrMetadata] is 2D. Panics otherwise.
     pub fn to_image(&self) -> Image {
         assert!(matches!(self.mapping_type(), NtrMappingType::Mode2D));
 
         let width_in_tiles = match &self.mapping_data {
             MappingData::TwoD((w, _)) => *w,
-            MappingData::OneD(_) => unreachable!(),
+            MappingData::OneD(..) => unreachable!(),
         };
 
         self.to_image_internal(width_in_tiles)
@@ -226,44 +350,89 @@ impl Ncgr {
             mapping_type: self.mapping_type(),
             character_format: self.character_format(),
             include_cpos: self.include_cpos,
+            store_dimensions: matches!(self.mapping_data, MappingData::OneD(_, Some(_))),
         }
     }
 
-    pub fn cipher(self, key: u32) -> Self {
-        let ciphered_data = cipher(&self.character_data_to_raw_data(), key);
+    /// Keeps only the first `num_tiles` tiles, e.g. to leave out unused tiles
+    /// at the end of the image. The stored dimensions are not changed, so
+    /// this is only supported with 1D mapping.
+    pub fn with_num_tiles(self, num_tiles: usize) -> Result<Self> {
+        if matches!(self.mapping_data, MappingData::TwoD(_)) {
+            return Err(Error::invalid_input(
+                "the number of tiles can only be changed with 1D mapping",
+            ));
+        }
+        let tile_count = match &self.character_data {
+            CharacterData::Character(tiles, _) => tiles.len(),
+            CharacterData::Bitmap(pixels) => pixels.len() / (TILE_LENGTH * TILE_LENGTH),
+        };
+        if num_tiles > tile_count {
+            return Err(Error::invalid_input(format!(
+                "cannot keep {} tiles, as there are only {}",
+                num_tiles, tile_count
+            )));
+        }
+        let character_data = match self.character_data {
+            CharacterData::Character(mut tiles, value) => {
+                tiles.truncate(num_tiles);
+                CharacterData::Character(tiles, value)
+            }
+            CharacterData::Bitmap(mut pixels) => {
+                pixels.truncate(num_tiles * TILE_LENGTH * TILE_LENGTH);
+                CharacterData::Bitmap(pixels)
+            }
+        };
+        Ok(Self {
+            character_data,
+            ..self
+        })
+    }
+
+    pub fn cipher(self, key: u32) -> Result<Self> {
+        let ciphered_data = cipher(&self.character_data_to_raw_data()?, key);
         let character_data = Self::raw_data_to_character_data(
             &ciphered_data,
             self.texture_format,
             self.character_format(),
-        );
+        )?;
 
-        Self {
+        Ok(Self {
             character_data,
             ..self
-        }
+        })
     }
 
-    pub fn decipher(self) -> (Self, u32) {
-        let (deciphered_data, key) = decipher(&self.character_data_to_raw_data());
+    pub fn decipher(self) -> Result<(Self, u32)> {
+        let (deciphered_data, key) = decipher(&self.character_data_to_raw_data()?);
         let character_data = Self::raw_data_to_character_data(
             &deciphered_data,
             self.texture_format,
             self.character_format(),
-        );
+        )?;
 
-        (
+        Ok((
             Self {
                 character_data,
                 ..self
             },
             key,
-        )
+        ))
+    }
+
+    /// Returns the tiles in the order they are stored, or `None` if the data
+    /// is in bitmap format.
+    pub(crate) fn tiles(&self) -> Option<&[Tile]> {
+        match &self.character_data {
+            CharacterData::Character(tiles, _) => Some(tiles),
+            CharacterData::Bitmap(_) => None,
+        }
     }
 
     fn mapping_type(&self) -> NtrMappingType {
         match &self.mapping_data {
             MappingData::TwoD(_) => NtrMappingType::Mode2D,
-            MappingData::OneD(mapping1_dvariant) => match mapping1_dvariant {
+            MappingData::OneD(mapping1_dvariant, _) => match mapping1_dvariant {
                 Mapping1DVariant::Vram32 => NtrMappingType::Mode1D32K,
                 Mapping1DVariant::Vram64 => NtrMappingType::Mode1D64K,
                 Mapping1DVariant::Vram128 => NtrMappingType::Mode1D128K,
@@ -274,16 +443,13 @@ impl Ncgr {
 
     fn character_format(&self) -> NtrCharacterFormat {
         match &self.character_data {
-            CharacterData::Character(_, value) => match value {
-                0 => NtrCharacterFormat::Character,
-                256 => NtrCharacterFormat::Character256,
-                _ => panic!(),
-            },
+            CharacterData::Character(_, 256) => NtrCharacterFormat::Character256,
+            CharacterData::Character(_, _) => NtrCharacterFormat::Character,
             CharacterData::Bitmap(_) => NtrCharacterFormat::Bitmap,
         }
     }
 
-    fn character_data_to_raw_data(&self) -> Vec<u8> {
+    fn character_data_to_raw_data(&self) -> Result<Vec<u8>> {
         let raw_data = match &self.character_data {
             CharacterData::Character(tiles, _) => {
                 &tiles.iter().flatten().copied().collect::<Vec<u8>>()
@@ -291,28 +457,28 @@ impl Ncgr {
             CharacterData::Bitmap(pixels) => pixels,
         };
 
-        match &self.texture_format {
+        Ok(match self.texture_format {
             NtrTextureFormat::Palette16 => raw_data
                 .chunks(2)
                 .map(|chunk| chunk[0] | (chunk[1] << 4))
                 .collect::<Vec<u8>>(),
             NtrTextureFormat::Palette256 => raw_data.to_vec(),
-            _ => panic!(),
-        }
+            texture_format => return Err(Self::unsupported_texture_format(texture_format)),
+        })
     }
 
     fn raw_data_to_character_data(
         raw_data: &[u8],
         texture_format: NtrTextureFormat,
         character_format: NtrCharacterFormat,
-    ) -> CharacterData {
+    ) -> Result<CharacterData> {
         let pixels = match texture_format {
             NtrTextureFormat::Palette16 => Image::raw_data_4bpp_to_pixels(raw_data),
             NtrTextureFormat::Palette256 => Image::raw_data_8bpp_to_pixels(raw_data),
-            _ => panic!(),
+            _ => return Err(Self::unsupported_texture_format(texture_format)),
         };
 
-        match character_format {
+        Ok(match character_format {
             NtrCharacterFormat::Character | NtrCharacterFormat::Character256 => {
                 CharacterData::Character(
                     pixels
@@ -323,16 +489,27 @@ impl Ncgr {
                 )
             }
             NtrCharacterFormat::Bitmap => CharacterData::Bitmap(pixels),
+        })
+    }
+
+    /// The error for character data in a texture format other than 16 or 256
+    /// colors, located where the texture format is stored in the RAHC block.
+    fn unsupported_texture_format(texture_format: NtrTextureFormat) -> Error {
+        Error::UnsupportedTextureFormat {
+            offset: 0x1C,
+            block: "RAHC".to_string(),
+            value: texture_format.into(),
         }
     }
 
-    fn to_char_block(&self) -> std::io::Result<NtrFileBlock> {
+    fn to_char_block(&self) -> Result<NtrFileBlock> {
         let mut char = vec![];
         let texture_format = self.texture_format;
+        let raw_data = self.character_data_to_raw_data()?;
 
         let (width_in_tiles, height_in_tiles) = match &self.mapping_data {
-            MappingData::TwoD((w, h)) => (*w, *h),
-            MappingData::OneD(_) => (0xFFFF, 0xFFFF),
+            MappingData::TwoD((w, h)) | MappingData::OneD(_, Some((w, h))) => (*w, *h),
+            MappingData::OneD(_, None) => (0xFFFF, 0xFFFF),
         };
 
         char.write_u16(height_in_tiles.try_into().unwrap())?;
@@ -342,8 +519,6 @@ impl Ncgr {
         char.write_u32(self.mapping_type().into_u32_ncgr())?;
         char.write_u32(self.character_format().into())?;
 
-        let raw_data = self.character_data_to_raw_data();
-
         char.write_u32(raw_data.len().try_into().unwrap())?;
         char.write_u32(0x00000018)?;
         char.write_all(&raw_data)?;
@@ -351,10 +526,14 @@ impl Ncgr {
         Ok(NtrFileBlock::new("RAHC", char))
     }
 
-    fn to_cpos_block(&self) -> std::io::Result<NtrFileBlock> {
+    fn to_cpos_block(&self) -> Result<NtrFileBlock> {
         let (width_in_tiles, height_in_tiles) = match &self.mapping_data {
-            MappingData::TwoD((w, h)) => (*w, *h),
-            MappingData::OneD(_) => unimplemented!(),
+            MappingData::TwoD((w, h)) | MappingData::OneD(_, Some((w, h))) => (*w, *h),
+            MappingData::OneD(_, None) => {
+                return Err(Error::invalid_input(
+                    "the CPOS block can only be included if the dimensions are stored",
+                ))
+            }
         };
         let mut cpos = vec![];
         cpos.write_u16(0x0000)?;
@@ -370,11 +549,11 @@ impl FileFormat for Ncgr {
         "NCGR".to_string()
     }
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
+    fn read_from_data(data: &[u8]) -> Result<Self> {
         NtrFormat::read_from_data(data)
     }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
+    fn write_to_data(&self) -> Result<Vec<u8>> {
         NtrFormat::write_to_data(self)
     }
 }
@@ -382,10 +561,10 @@ impl FileFormat for Ncgr {
 fn cipher(data: &[u8], key: u32) -> Vec<u8> {
     let mut out = vec![];
     let mut internal_key = key;
-    for mut chunk in data.chunks(2).rev() {
+    for chunk in data.chunks(2).rev() {
         internal_key = ((internal_key as i32) - 24691) as u32;
         internal_key = ((internal_key as u64) * 4005161829) as u32;
-        let val = chunk.read_u16().unwrap() ^ (internal_key as u16);
+        let val = u16::from_le_bytes(chunk.try_into().unwrap()) ^ (internal_key as u16);
         out.push((val >> 8) as u8);
         out.push((val & 0xFF) as u8);
     }
@@ -396,8 +575,8 @@ fn cipher(data: &[u8], key: u32) -> Vec<u8> {
 fn decipher(data: &[u8]) -> (Vec<u8>, u32) {
     let mut out = vec![];
     let mut key: u32 = u16::from_le_bytes(data[0..2].try_into().unwrap()).into();
-    for mut chunk in data.chunks(2) {
-        let val = chunk.read_u16().unwrap() ^ (key as u16);
+    for chunk in data.chunks(2) {
+        let val = u16::from_le_bytes(chunk.try_into().unwrap()) ^ (key as u16);
         out.push((val & 0xFF) as u8);
         out.push((val >> 8) as u8);
         key = ((key as u64) * 1103515245) as u32;
@@ -1,12 +1,23 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
 use crate::{
     enums::{NtrFileVersion, NtrTextureFormat},
+    error::{Error, Result},
+    format::{read_sidecar, write_sidecar},
+    jasc::Jasc,
     ntr::{NtrFile, NtrFileBlock, NtrFormat, NtrMetadata},
-    palette::Palette,
-    read_write_ext::{ReadExt, WriteExt},
+    palette::{Color, ColorScaling, Palette, PaletteFormat},
+    read_write_ext::WriteExt,
     FileFormat,
 };
 
-#[derive(Debug, Default, Clone)]
+/// Can be stored in a `<name>.nclr.json` sidecar file next to the palette, so
+/// that the NCLR can be rebuilt from the palette alone. Fields missing from
+/// the sidecar take their default values.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
 pub struct NclrMetadata {
     pub version: NtrFileVersion,
     pub texture_format: NtrTextureFormat,
@@ -14,19 +25,37 @@ pub struct NclrMetadata {
     /// The value to write to offset 0x0002 of the PLTT block. Defaults to `0`.
     pub pltt_0002: u16,
 
-    /// Whether the extended palette is used. Defaults to false.
+    /// Whether the extended palette is used, in which case the palette holds
+    /// up to 16 banks of 256 colors. Defaults to false.
     pub extended: bool,
 
     /// Whether the size of data is stored as `(0x200 - size)` instead.
     /// Defaults to false.
     pub invert_size: bool,
 
-    /// Whether the unused high bit for each color is set. Defaults to false.
+    /// Whether the unused high bit is set for any color. When building an
+    /// NCLR from a palette whose colors have no high bit set, it is set for
+    /// every color. Defaults to false.
     pub high_color_bit: bool,
 
+    /// Whether the high bit of each color maps to its alpha, i.e. colors with
+    /// the high bit set are opaque and the others transparent. When building
+    /// an NCLR from a palette, colors with an alpha of at least 128 then get
+    /// the high bit, and `high_color_bit` is ignored. Defaults to false.
+    pub high_bit_as_alpha: bool,
+
+    /// How the colors are converted to and from 8-bit RGB. Defaults to
+    /// [`ColorScaling::Multiply8`].
+    pub color_scaling: ColorScaling,
+
     /// The palette indexes stored in the PCMP block. If empty, no PCMP block
     /// is included. Defaults to an empty vector.
     pub palette_indexes: Vec<u16>,
+
+    /// Whether the file is an NCPR, which has the id `RPCN` and stores the
+    /// byte order mark as `0xFFFE`, but is otherwise identical to an NCLR.
+    /// Defaults to false.
+    pub ncpr: bool,
 }
 
 impl Into<NtrMetadata> for NclrMetadata {
@@ -74,48 +103,115 @@ impl NclrMetadata {
         }
     }
 
+    pub fn high_bit_as_alpha(self, high_bit_as_alpha: bool) -> Self {
+        Self {
+            high_bit_as_alpha,
+            ..self
+        }
+    }
+
+    pub fn with_color_scaling(self, color_scaling: ColorScaling) -> Self {
+        Self {
+            color_scaling,
+            ..self
+        }
+    }
+
     pub fn with_palette_indexes(self, palette_indexes: Vec<u16>) -> Self {
         Self {
             palette_indexes,
             ..self
         }
     }
+
+    pub fn ncpr(self, ncpr: bool) -> Self {
+        Self { ncpr, ..self }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str::<Self>(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self)?)
+    }
+
+    /// Reads the `<name>.nclr.json` sidecar file next to `path`, returning
+    /// `None` if there is no such file.
+    pub fn read_sidecar<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        read_sidecar(path.as_ref(), &Nclr::extension())
+    }
+
+    /// Writes the metadata to the `<name>.nclr.json` sidecar file next to
+    /// `path`.
+    pub fn write_sidecar<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        write_sidecar(self, path.as_ref(), &Nclr::extension())
+    }
 }
 
 pub struct Nclr {
     metadata: NclrMetadata,
-    palette: Palette,
+
+    /// The BGR555 values of the colors, including their high bits.
+    colors: Vec<u16>,
 }
 
 impl NtrFormat for Nclr {
-    fn read_from_ntr_file(file: &NtrFile) -> std::io::Result<Self> {
-        assert!(file.id() == "RLCN");
+    fn read_from_ntr_file(file: &NtrFile) -> Result<Self> {
+        let ncpr = file.id() == "RPCN";
+        if !ncpr {
+            file.expect_id("RLCN")?;
+        }
 
-        let pltt_block = &file.blocks()[0];
-        assert!(pltt_block.id() == "TTLP");
-        let mut pltt = pltt_block.contents();
-        let texture_format = pltt.read_u16()?.into();
+        let pltt_block = file.block(0, "TTLP")?;
+        let mut pltt = pltt_block.reader();
+        let texture_format_offset = pltt.offset();
+        let texture_format: NtrTextureFormat = pltt.read_u16_as()?;
         match texture_format {
             NtrTextureFormat::Palette16 | NtrTextureFormat::Palette256 => {}
-            _ => panic!(),
+            _ => {
+                return Err(Error::UnsupportedTextureFormat {
+                    offset: texture_format_offset,
+                    block: pltt.block().to_string(),
+                    value: texture_format.into(),
+                })
+            }
         };
         let pltt_0002 = pltt.read_u16()?;
+        let extended_offset = pltt.offset();
         let extended = match pltt.read_u32()? {
             0 => false,
             1 => true,
-            _ => panic!(),
+            value => {
+                return Err(pltt.error_at(
+                    extended_offset,
+                    format!("invalid extended palette flag {}", value),
+                ))
+            }
         };
-        let palette_size = u32::try_from(pltt_block.contents().len() - 16).unwrap();
+        let palette_size = u32::try_from(pltt_block.contents().len().saturating_sub(16)).unwrap();
+        let palette_size_offset = pltt.offset();
         let read_palette_size = pltt.read_u32()?;
         let invert_size = if read_palette_size == palette_size {
             false
-        } else if read_palette_size == 0x200 - palette_size {
+        } else if read_palette_size == 0x200u32.wrapping_sub(palette_size) {
             true
         } else {
-            panic!();
+            return Err(pltt.error_at(
+                palette_size_offset,
+                format!(
+                    "palette size {:#x} does not match block size {:#x}",
+                    read_palette_size, palette_size
+                ),
+            ));
         };
-        let palette_offset = pltt.read_u32()?;
-        assert!(palette_offset == 0x00000010);
+        pltt.expect_u32(0x00000010)?;
 
         let mut colors = vec![];
         let mut high_color_bit = false;
@@ -123,16 +219,15 @@ impl NtrFormat for Nclr {
         for _ in 0..num_colors {
             let value = pltt.read_u16()?;
             high_color_bit |= (value >> 0xf) != 0;
-            colors.push(value.into());
+            colors.push(value);
         }
 
         let palette_indexes = if file.blocks().len() > 1 {
-            let pcmp_block = &file.blocks()[1];
-            assert!(pcmp_block.id() == "PMCP");
-            let mut pcmp = pcmp_block.contents();
+            let pcmp_block = file.block(1, "PMCP")?;
+            let mut pcmp = pcmp_block.reader();
             let num_palette_indexes = pcmp.read_u16()?;
-            assert!(pcmp.read_u16()? == 0xBEEF);
-            assert!(pcmp.read_u32()? == 0x00000008);
+            pcmp.expect_u16(0xBEEF)?;
+            pcmp.expect_u32(0x00000008)?;
             let mut palette_indexes = vec![];
             for _ in 0..num_palette_indexes {
                 palette_indexes.push(pcmp.read_u16()?);
@@ -149,65 +244,141 @@ impl NtrFormat for Nclr {
             extended,
             invert_size,
             high_color_bit,
+            high_bit_as_alpha: false,
+            color_scaling: ColorScaling::default(),
             palette_indexes,
+            ncpr,
         };
 
-        Ok(Self {
-            metadata,
-            palette: Palette::new(colors),
-        })
+        Ok(Self { metadata, colors })
     }
 
-    fn write_to_ntr_file(&self) -> std::io::Result<NtrFile> {
+    fn write_to_ntr_file(&self) -> Result<NtrFile> {
+        let max_colors = Palette::EXTENDED_BANK_SIZE * Palette::NUM_EXTENDED_BANKS;
+        if self.metadata.extended && self.colors.len() > max_colors {
+            return Err(Error::invalid_input(format!(
+                "extended palette has {} colors, but at most {} are supported",
+                self.colors.len(),
+                max_colors
+            )));
+        }
+
         let mut blocks = vec![self.to_pltt_block(&self.metadata)?];
         if !self.metadata.palette_indexes.is_empty() {
             blocks.push(self.to_pcmp_block(&self.metadata.palette_indexes)?);
         }
 
-        Ok(NtrFile::new("RLCN", self.metadata.version, blocks))
+        Ok(match self.metadata.ncpr {
+            true => {
+                NtrFile::new("RPCN", self.metadata.version, blocks).with_byte_order_mark(0xFFFE)
+            }
+            false => NtrFile::new("RLCN", self.metadata.version, blocks),
+        })
     }
 }
 
 impl Nclr {
+    /// Converts the colors with the color scaling of `metadata`. Colors that
+    /// it cannot represent exactly are reduced, which can be checked for
+    /// beforehand with [`Palette::lossy_colors`].
     pub fn from_palette(palette: Palette, metadata: NclrMetadata) -> Self {
-        Self { metadata, palette }
+        let mut colors = palette
+            .colors()
+            .iter()
+            .map(|color| match metadata.high_bit_as_alpha {
+                true => {
+                    let color = Color {
+                        high_bit: color.alpha >= 0x80,
+                        ..*color
+                    };
+                    color.to_bgr555(metadata.color_scaling)
+                }
+                false => color.to_bgr555(metadata.color_scaling),
+            })
+            .collect::<Vec<_>>();
+        if metadata.high_color_bit
+            && !metadata.high_bit_as_alpha
+            && colors.iter().all(|value| value >> 0xf == 0)
+        {
+            colors.iter_mut().for_each(|value| *value |= 1 << 0xf);
+        }
+        Self { metadata, colors }
+    }
+
+    /// Same as [`Nclr::from_palette`], but fails instead if any color cannot
+    /// be represented exactly.
+    pub fn try_from_palette(palette: Palette, metadata: NclrMetadata) -> Result<Self> {
+        palette.expect_exact(metadata.color_scaling)?;
+        Ok(Self::from_palette(palette, metadata))
     }
 
+    /// Reads a JASC palette along with its `<name>.nclr.json` sidecar file, if
+    /// any. Without a sidecar file, the default metadata is used. Fails if any
+    /// color cannot be represented exactly with its color scaling.
+    pub fn read_from_jasc_with_sidecar<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::read_from_palette_with_sidecar::<Jasc, _>(path)
+    }
+
+    /// Same as [`Nclr::read_from_jasc_with_sidecar`], but for a palette in any
+    /// of the supported palette file formats, e.g. [`Gpl`](crate::Gpl).
+    pub fn read_from_palette_with_sidecar<F, P>(path: P) -> Result<Self>
+    where
+        F: PaletteFormat,
+        P: AsRef<Path>,
+    {
+        let metadata = NclrMetadata::read_sidecar(&path)?.unwrap_or_default();
+        let palette = F::read_from_file(path)?.to_palette();
+        Self::try_from_palette(palette, metadata)
+    }
+
+    /// Converts the colors with the color scaling of the metadata.
     pub fn to_palette(&self) -> Palette {
-        self.palette.clone()
+        self.to_palette_with_scaling(self.metadata.color_scaling)
+    }
+
+    pub fn to_palette_with_scaling(&self, scaling: ColorScaling) -> Palette {
+        Palette::new(
+            self.colors
+                .iter()
+                .map(|value| {
+                    let color = Color::from_bgr555(*value, scaling);
+                    match self.metadata.high_bit_as_alpha && !color.high_bit {
+                        true => color.with_alpha(0),
+                        false => color,
+                    }
+                })
+                .collect(),
+        )
     }
 
     pub fn metadata(&self) -> NclrMetadata {
         self.metadata.clone()
     }
 
-    fn to_pltt_block(&self, metadata: &NclrMetadata) -> std::io::Result<NtrFileBlock> {
+    fn to_pltt_block(&self, metadata: &NclrMetadata) -> Result<NtrFileBlock> {
         let mut pltt = vec![];
         pltt.write_u16(metadata.texture_format.into())?;
         pltt.write_u16(metadata.pltt_0002)?;
         pltt.write_u32(if metadata.extended { 1 } else { 0 })?;
 
-        let mut data: Vec<u16> = vec![];
-        for color in self.palette.colors() {
-            let value: u16 = (*color).into();
-            data.push(value | (if metadata.high_color_bit { 1 << 0xf } else { 0 }));
-        }
-
-        let data_size = (data.len() * 2).try_into().unwrap();
+        let data_size: u32 = (self.colors.len() * 2).try_into().unwrap();
         pltt.write_u32(if metadata.invert_size {
-            0x200 - data_size
+            0x200u32.wrapping_sub(data_size)
         } else {
             data_size
         })?;
         pltt.write_u32(0x00000010)?;
-        for color in data {
-            pltt.write_u16(color)?;
+        for color in &self.colors {
+            pltt.write_u16(*color)?;
         }
 
         Ok(NtrFileBlock::new("TTLP", pltt))
     }
 
-    fn to_pcmp_block(&self, palette_indexes: &[u16]) -> std::io::Result<NtrFileBlock> {
+    fn to_pcmp_block(&self, palette_indexes: &[u16]) -> Result<NtrFileBlock> {
         let mut pcmp = vec![];
         pcmp.write_u16(palette_indexes.len().try_into().unwrap())?;
         pcmp.write_u16(0xBEEF)?;
@@ -226,11 +397,11 @@ impl FileFormat for Nclr {
         "NCLR".to_string()
     }
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
+    fn read_from_data(data: &[u8]) -> Result<Self> {
         NtrFormat::read_from_data(data)
     }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
+    fn write_to_data(&self) -> Result<Vec<u8>> {
         NtrFormat::write_to_data(self)
     }
 }
@@ -1,10 +1,18 @@
+use std::io::Write;
+
 use crate::{
+    enums::{NtrBgType, NtrFileVersion},
+    error::{Error, Result},
     image::{pixels_to_tiles, tiles_to_pixels, TILE_LENGTH},
-    ntr::{NtrFile, NtrFormat},
-    read_write_ext::ReadExt,
-    FileFormat, Image, NtrTextureFormat,
+    ntr::{NtrFile, NtrFileBlock, NtrFormat, NtrMetadata},
+    palette::Palette,
+    read_write_ext::WriteExt,
+    FileFormat, Image, Ncgr, NcgrMetadata, NtrMappingType, NtrTextureFormat,
 };
 
+type Tile = [u8; TILE_LENGTH * TILE_LENGTH];
+
+#[derive(Clone, Copy)]
 struct ScreenEntry {
     tile_index: usize,
     h_flip: bool,
@@ -23,93 +31,383 @@ impl From<u16> for ScreenEntry {
     }
 }
 
+impl TryFrom<ScreenEntry> for u16 {
+    type Error = Error;
+
+    fn try_from(value: ScreenEntry) -> Result<Self> {
+        if value.tile_index >= (1 << 0xa) {
+            return Err(Error::invalid_input(format!(
+                "tile index {} does not fit in a screen entry",
+                value.tile_index
+            )));
+        }
+        if value.palette_index >= (1 << 4) {
+            return Err(Error::invalid_input(format!(
+                "palette index {} does not fit in a screen entry",
+                value.palette_index
+            )));
+        }
+        Ok(u16::try_from(value.tile_index).unwrap()
+            | (u16::from(value.h_flip) << 0xa)
+            | (u16::from(value.v_flip) << 0xb)
+            | (u16::try_from(value.palette_index).unwrap() << 0xc))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NscrMetadata {
+    pub version: NtrFileVersion,
+    pub texture_format: NtrTextureFormat,
+    pub bg_type: NtrBgType,
+
+    /// Whether the color mode is stored as `2` (256 colors using the extended
+    /// palette) instead of `1`. Only applies to 256 colors. Defaults to false.
+    pub extended: bool,
+}
+
+impl From<NscrMetadata> for NtrMetadata {
+    fn from(value: NscrMetadata) -> Self {
+        NtrMetadata {
+            version: value.version,
+        }
+    }
+}
+
+impl NscrMetadata {
+    pub fn with_version(self, version: NtrFileVersion) -> Self {
+        Self { version, ..self }
+    }
+
+    pub fn with_texture_format(self, texture_format: NtrTextureFormat) -> Self {
+        Self {
+            texture_format,
+            ..self
+        }
+    }
+
+    pub fn with_bg_type(self, bg_type: NtrBgType) -> Self {
+        Self { bg_type, ..self }
+    }
+
+    pub fn extended(self, extended: bool) -> Self {
+        Self { extended, ..self }
+    }
+}
+
 pub struct Nscr {
+    version: NtrFileVersion,
     width_in_tiles: usize,
     texture_format: NtrTextureFormat,
+    bg_type: NtrBgType,
+    extended: bool,
     screen_entries: Vec<ScreenEntry>,
 }
 
 impl NtrFormat for Nscr {
-    fn read_from_ntr_file(file: &NtrFile) -> std::io::Result<Self> {
-        assert!(file.id() == "RCSN");
-
-        assert!(file.blocks().len() == 1);
-
-        let scrn_block = &file.blocks()[0];
-        assert!(scrn_block.id() == "NRCS");
-        let mut scrn = scrn_block.contents();
-
-        let width: usize = scrn.read_u16()?.try_into().unwrap();
-        assert!(width % TILE_LENGTH == 0);
-        let height: usize = scrn.read_u16()?.try_into().unwrap();
-        assert!(height % TILE_LENGTH == 0);
-        let texture_format = match scrn.read_u16()? {
-            0 => NtrTextureFormat::Palette16,
-            1 | 2 => NtrTextureFormat::Palette256,
-            _ => panic!(),
+    fn read_from_ntr_file(file: &NtrFile) -> Result<Self> {
+        file.expect_id("RCSN")?;
+
+        file.expect_num_blocks(1)?;
+
+        let scrn_block = file.block(0, "NRCS")?;
+        let mut scrn = scrn_block.reader();
+
+        let width: usize = scrn.read_u16()?.into();
+        let height: usize = scrn.read_u16()?.into();
+        if !width.is_multiple_of(TILE_LENGTH) || !height.is_multiple_of(TILE_LENGTH) {
+            return Err(scrn.error_at(
+                scrn_block.offset() + 8,
+                format!("dimensions {}x{} are not multiples of 8", width, height),
+            ));
+        }
+        let color_mode_offset = scrn.offset();
+        let (texture_format, extended) = match scrn.read_u16()? {
+            0 => (NtrTextureFormat::Palette16, false),
+            1 => (NtrTextureFormat::Palette256, false),
+            2 => (NtrTextureFormat::Palette256, true),
+            value => {
+                return Err(
+                    scrn.error_at(color_mode_offset, format!("invalid color mode {}", value))
+                )
+            }
         };
-        let bg_type = scrn.read_u16()?;
+        let bg_type: NtrBgType = scrn.read_u16_as()?;
 
+        let screen_size_offset = scrn.offset();
         let screen_size = scrn.read_u32()?.try_into().unwrap();
-        match bg_type {
-            0 | 2 => assert!(screen_size * TILE_LENGTH * TILE_LENGTH / 2 == width * height),
-            1 => assert!(screen_size * TILE_LENGTH * TILE_LENGTH == width * height),
-            _ => panic!(),
+        let expected_screen_size = match bg_type {
+            NtrBgType::Text | NtrBgType::AffineExtended => width * height * 2,
+            NtrBgType::Affine => width * height,
+        } / (TILE_LENGTH * TILE_LENGTH);
+        if screen_size != expected_screen_size {
+            return Err(scrn.error_at(
+                screen_size_offset,
+                format!(
+                    "screen size {:#x} does not match dimensions {}x{}",
+                    screen_size, width, height
+                ),
+            ));
         }
 
         let raw_data = scrn.read_sized(screen_size)?;
-        let screen_entries = Self::read_screen_data(&raw_data, bg_type);
+        let screen_entries = Self::read_screen_data(raw_data, bg_type);
 
         Ok(Self {
+            version: file.version(),
             width_in_tiles: width / TILE_LENGTH,
             texture_format,
+            bg_type,
+            extended,
             screen_entries,
         })
     }
 
-    fn write_to_ntr_file(&self) -> std::io::Result<NtrFile> {
-        todo!()
+    fn write_to_ntr_file(&self) -> Result<NtrFile> {
+        Ok(NtrFile::new(
+            "RCSN",
+            self.version,
+            vec![self.to_scrn_block()?],
+        ))
     }
 }
 
 impl Nscr {
-    fn read_screen_data(raw_data: &Vec<u8>, bg_type: u16) -> Vec<ScreenEntry> {
+    /// Generates a tilemap along with its tileset from an indexed image.
+    ///
+    /// Duplicate tiles, including ones that are duplicates only after being
+    /// flipped horizontally and/or vertically, are stored once in the tileset.
+    /// Flipping is not available for affine backgrounds.
+    ///
+    /// For 16 colors, all pixels in each tile must belong to the same 16-color
+    /// palette bank, except for pixels that use the first (transparent) color
+    /// of any bank.
+    ///
+    /// The texture format of `ncgr_metadata` is overridden by that of
+    /// `metadata`.
+    pub fn from_image(
+        image: &Image,
+        metadata: NscrMetadata,
+        ncgr_metadata: NcgrMetadata,
+    ) -> Result<(Self, Ncgr)> {
+        if !image.width().is_multiple_of(TILE_LENGTH) || !image.height().is_multiple_of(TILE_LENGTH)
+        {
+            return Err(Error::invalid_input(format!(
+                "image dimensions {}x{} are not multiples of 8",
+                image.width(),
+                image.height()
+            )));
+        }
+        match (metadata.texture_format, metadata.bg_type) {
+            (NtrTextureFormat::Palette16, NtrBgType::Text) | (NtrTextureFormat::Palette256, _) => {}
+            (NtrTextureFormat::Palette16, bg_type) => {
+                return Err(Error::invalid_input(format!(
+                    "16-color screens are only supported for text backgrounds, not {:?}",
+                    bg_type
+                )))
+            }
+            (texture_format, _) => {
+                return Err(Error::invalid_input(format!(
+                    "unsupported texture format {:?}",
+                    texture_format
+                )))
+            }
+        }
+        let width_in_tiles = image.width_in_tiles();
+        let allow_flip = !matches!(metadata.bg_type, NtrBgType::Affine);
+
+        let mut tiles: Vec<Tile> = vec![];
+        let mut screen_entries = vec![];
+        for (i, tile) in pixels_to_tiles(image.pixels(), width_in_tiles)
+            .into_iter()
+            .enumerate()
+        {
+            let (tile, palette_index) = match metadata.texture_format {
+                NtrTextureFormat::Palette16 => {
+                    let split_tile = tile.map(Palette::split_color_index);
+                    let palette_index = split_tile.iter().map(|(bank, _)| *bank).max().unwrap();
+                    if !split_tile
+                        .iter()
+                        .all(|(bank, index)| *index == 0 || *bank == palette_index)
+                    {
+                        return Err(Error::invalid_input(format!(
+                            "tile at ({}, {}) uses colors from more than one palette bank",
+                            i % width_in_tiles * TILE_LENGTH,
+                            i / width_in_tiles * TILE_LENGTH
+                        )));
+                    }
+                    (split_tile.map(|(_, index)| index), palette_index.into())
+                }
+                _ => (tile, 0),
+            };
+
+            let mut entry = None;
+            for (h_flip, v_flip) in [(false, false), (true, false), (false, true), (true, true)] {
+                if !allow_flip && (h_flip || v_flip) {
+                    break;
+                }
+                let mut flipped = tile;
+                if h_flip {
+                    flip_tile_horizontal(&mut flipped);
+                }
+                if v_flip {
+                    flip_tile_vertical(&mut flipped);
+                }
+                if let Some(tile_index) = tiles.iter().position(|t| *t == flipped) {
+                    entry = Some(ScreenEntry {
+                        tile_index,
+                        h_flip,
+                        v_flip,
+                        palette_index,
+                    });
+                    break;
+                }
+            }
+            screen_entries.push(entry.unwrap_or_else(|| {
+                tiles.push(tile);
+                ScreenEntry {
+                    tile_index: tiles.len() - 1,
+                    h_flip: false,
+                    v_flip: false,
+                    palette_index,
+                }
+            }));
+        }
+
+        let max_tiles = match metadata.bg_type {
+            NtrBgType::Text | NtrBgType::AffineExtended => 1 << 0xa,
+            NtrBgType::Affine => 1 << 8,
+        };
+        if tiles.len() > max_tiles {
+            return Err(Error::invalid_input(format!(
+                "image has {} unique tiles, but at most {} are supported for {:?} backgrounds",
+                tiles.len(),
+                max_tiles,
+                metadata.bg_type
+            )));
+        }
+
+        // In 2D mapping, the tileset has to be rectangular, so it is made as
+        // wide as the screen and padded with empty tiles.
+        let tileset_width_in_tiles = match ncgr_metadata.mapping_type {
+            NtrMappingType::Mode2D => width_in_tiles.min(tiles.len()),
+            _ => 1,
+        };
+        while !tiles.len().is_multiple_of(tileset_width_in_tiles) {
+            tiles.push([0; TILE_LENGTH * TILE_LENGTH]);
+        }
+        let tileset = Image::new(
+            tileset_width_in_tiles * TILE_LENGTH,
+            &tiles_to_pixels(&tiles, tileset_width_in_tiles),
+            image.palette(),
+        );
+        let ncgr = Ncgr::from_image(
+            tileset,
+            ncgr_metadata.with_texture_format(metadata.texture_format),
+        );
+
+        let nscr = Self {
+            version: metadata.version,
+            width_in_tiles,
+            texture_format: metadata.texture_format,
+            bg_type: metadata.bg_type,
+            extended: metadata.extended,
+            screen_entries,
+        };
+
+        Ok((nscr, ncgr))
+    }
+
+    pub fn metadata(&self) -> NscrMetadata {
+        NscrMetadata {
+            version: self.version,
+            texture_format: self.texture_format,
+            bg_type: self.bg_type,
+            extended: self.extended,
+        }
+    }
+
+    fn read_screen_data(raw_data: &[u8], bg_type: NtrBgType) -> Vec<ScreenEntry> {
         match bg_type {
-            0 | 2 => raw_data
+            NtrBgType::Text | NtrBgType::AffineExtended => raw_data
                 .chunks(2)
                 .map(|chunk| u16::from_le_bytes(chunk.try_into().unwrap()).into())
                 .collect(),
-            1 => raw_data
+            NtrBgType::Affine => raw_data
                 .iter()
                 .map(|tile| ScreenEntry {
-                    tile_index: (*tile).try_into().unwrap(),
+                    tile_index: (*tile).into(),
                     h_flip: false,
                     v_flip: false,
                     palette_index: 0,
                 })
                 .collect(),
-            _ => panic!(),
-        }
-    }
-
-    pub fn to_image(&self, tileset: &Image) -> Image {
-        let palette = tileset.palette().unwrap().clone();
-        let tiles = {
-            let tiles = pixels_to_tiles(tileset.pixels(), tileset.width_in_tiles());
-            assert!(palette.colors().len() >= min_colors_in_palette(&self.screen_entries, &tiles));
-            match self.texture_format {
-                NtrTextureFormat::Palette16 => tiles
-                    .iter()
-                    .map(|tile| tile.map(|pixel| pixel % 16))
-                    .collect::<Vec<_>>(),
-                NtrTextureFormat::Palette256 => {
-                    assert!(self.screen_entries.iter().all(|e| e.palette_index == 0));
-                    tiles
+        }
+    }
+
+    /// Arranges the tiles of `tileset` into the screen. The tileset must have
+    /// a palette, which is used as the palette of the image.
+    ///
+    /// For 16 colors, the pixels of the image are indexes within a 16-color
+    /// bank, and the palette bank of each screen entry is not applied; use
+    /// [`Palette::bank`] to select the bank to render with.
+    ///
+    /// For 256 colors, if the palette is an extended palette with more than one
+    /// 256-color bank, the image uses the bank of the screen entries, which
+    /// must all use the same one. Otherwise the palette bank of the screen
+    /// entries is ignored, as it is by the DS.
+    pub fn to_image(&self, tileset: &Image) -> Result<Image> {
+        let mut palette = tileset
+            .palette()
+            .ok_or_else(|| Error::invalid_input("tileset has no palette"))?;
+        let tiles = pixels_to_tiles(tileset.pixels(), tileset.width_in_tiles());
+        let tiles = match self.texture_format {
+            NtrTextureFormat::Palette16 => tiles
+                .iter()
+                .map(|tile| tile.map(|pixel| Palette::split_color_index(pixel).1))
+                .collect::<Vec<_>>(),
+            NtrTextureFormat::Palette256 => {
+                if palette.num_extended_banks() > 1 {
+                    let bank = self.extended_palette_bank()?;
+                    palette = palette.extended_bank(bank).ok_or_else(|| {
+                        Error::invalid_input(format!(
+                            "screen uses 256-color palette bank {}, but the palette has {}",
+                            bank,
+                            palette.num_extended_banks()
+                        ))
+                    })?;
                 }
-                _ => panic!(),
+                tiles
+            }
+            texture_format => {
+                return Err(Error::invalid_input(format!(
+                    "unsupported texture format {:?}",
+                    texture_format
+                )))
             }
         };
 
+        for (i, entry) in self.screen_entries.iter().enumerate() {
+            let tile = tiles.get(entry.tile_index).ok_or_else(|| {
+                Error::invalid_input(format!(
+                    "screen entry {} uses tile {}, but the tileset has {} tiles",
+                    i,
+                    entry.tile_index,
+                    tiles.len()
+                ))
+            })?;
+            if let Some(pixel) = tile
+                .iter()
+                .find(|pixel| usize::from(**pixel) >= palette.colors().len())
+            {
+                return Err(Error::invalid_input(format!(
+                    "tile {} uses color {}, but the palette has {} colors",
+                    entry.tile_index,
+                    pixel,
+                    palette.colors().len()
+                )));
+            }
+        }
+
         let arrangement = self
             .screen_entries
             .iter()
@@ -127,7 +425,80 @@ impl Nscr {
 
         let pixels = tiles_to_pixels(&arrangement, self.width_in_tiles);
 
-        Image::new(self.width_in_tiles * TILE_LENGTH, &pixels, Some(palette))
+        Ok(Image::new(
+            self.width_in_tiles * TILE_LENGTH,
+            &pixels,
+            Some(palette),
+        ))
+    }
+
+    /// Returns the 256-color palette bank that all screen entries use.
+    fn extended_palette_bank(&self) -> Result<usize> {
+        let bank = self.screen_entries.first().map_or(0, |e| e.palette_index);
+        match self
+            .screen_entries
+            .iter()
+            .find(|entry| entry.palette_index != bank)
+        {
+            Some(entry) => Err(Error::invalid_input(format!(
+                "screen uses 256-color palette banks {} and {}, but an image can only use one",
+                bank, entry.palette_index
+            ))),
+            None => Ok(bank),
+        }
+    }
+
+    fn write_screen_data(&self) -> Result<Vec<u8>> {
+        let mut raw_data = vec![];
+        for entry in &self.screen_entries {
+            match self.bg_type {
+                NtrBgType::Text | NtrBgType::AffineExtended => {
+                    raw_data.write_u16((*entry).try_into()?)?;
+                }
+                NtrBgType::Affine => {
+                    if entry.h_flip || entry.v_flip || entry.palette_index != 0 {
+                        return Err(Error::invalid_input(
+                            "affine screen entries cannot have flip or palette bits",
+                        ));
+                    }
+                    let tile_index = u8::try_from(entry.tile_index).map_err(|_| {
+                        Error::invalid_input(format!(
+                            "tile index {} does not fit in an affine screen entry",
+                            entry.tile_index
+                        ))
+                    })?;
+                    raw_data.write_u8(tile_index)?;
+                }
+            }
+        }
+        Ok(raw_data)
+    }
+
+    fn to_scrn_block(&self) -> Result<NtrFileBlock> {
+        let width = self.width_in_tiles * TILE_LENGTH;
+        let height = self.screen_entries.len() / self.width_in_tiles * TILE_LENGTH;
+        let color_mode = match (self.texture_format, self.extended) {
+            (NtrTextureFormat::Palette16, _) => 0,
+            (NtrTextureFormat::Palette256, false) => 1,
+            (NtrTextureFormat::Palette256, true) => 2,
+            (texture_format, _) => {
+                return Err(Error::invalid_input(format!(
+                    "unsupported texture format {:?}",
+                    texture_format
+                )))
+            }
+        };
+        let raw_data = self.write_screen_data()?;
+
+        let mut scrn = vec![];
+        scrn.write_u16(width.try_into().unwrap())?;
+        scrn.write_u16(height.try_into().unwrap())?;
+        scrn.write_u16(color_mode)?;
+        scrn.write_u16(self.bg_type.into())?;
+        scrn.write_u32(raw_data.len().try_into().unwrap())?;
+        scrn.write_all(&raw_data)?;
+
+        Ok(NtrFileBlock::new("NRCS", scrn))
     }
 }
 
@@ -136,16 +507,16 @@ impl FileFormat for Nscr {
         "NSCR".to_string()
     }
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
+    fn read_from_data(data: &[u8]) -> Result<Self> {
         NtrFormat::read_from_data(data)
     }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
+    fn write_to_data(&self) -> Result<Vec<u8>> {
         NtrFormat::write_to_data(self)
     }
 }
 
-fn flip_tile_horizontal(tile: &mut [u8; TILE_LENGTH * TILE_LENGTH]) {
+fn flip_tile_horizontal(tile: &mut Tile) {
     for y in 0..TILE_LENGTH {
         for x in 0..(TILE_LENGTH / 2) {
             let left = y * TILE_LENGTH + x;
@@ -155,7 +526,7 @@ fn flip_tile_horizontal(tile: &mut [u8; TILE_LENGTH * TILE_LENGTH]) {
     }
 }
 
-fn flip_tile_vertical(tile: &mut [u8; TILE_LENGTH * TILE_LENGTH]) {
+fn flip_tile_vertical(tile: &mut Tile) {
     for y in 0..(TILE_LENGTH / 2) {
         for x in 0..TILE_LENGTH {
             let top = y * TILE_LENGTH + x;
@@ -164,23 +535,3 @@ fn flip_tile_vertical(tile: &mut [u8; TILE_LENGTH * TILE_LENGTH]) {
         }
     }
 }
-
-fn min_colors_in_palette(
-    screen_entries: &[ScreenEntry],
-    tiles: &[[u8; TILE_LENGTH * TILE_LENGTH]],
-) -> usize {
-    let biggest_palette_index = screen_entries
-        .iter()
-        .map(|entry| entry.palette_index)
-        .max()
-        .unwrap();
-    usize::from(
-        screen_entries
-            .iter()
-            .filter(|entry| entry.palette_index == biggest_palette_index)
-            .map(|entry| tiles[entry.tile_index])
-            .flatten()
-            .max()
-            .unwrap(),
-    ) + biggest_palette_index * 16
-}
@@ -1,13 +1,21 @@
 use std::io::Write;
 
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_8};
+use serde::{Deserialize, Serialize};
+
 use crate::{
     enums::NtrFileVersion,
-    read_write_ext::{ReadExt, WriteExt},
+    error::{Error, Result},
+    read_write_ext::{DataReader, WriteExt},
 };
 
 pub struct NtrFileBlock {
     id: String,
     contents: Vec<u8>,
+
+    /// Offset of the block from the start of the file. Only meaningful for
+    /// blocks that have been read from a file.
+    offset: usize,
 }
 
 impl NtrFileBlock {
@@ -15,6 +23,7 @@ impl NtrFileBlock {
         Self {
             id: id.to_string(),
             contents,
+            offset: 0,
         }
     }
 
@@ -23,7 +32,28 @@ impl NtrFileBlock {
     }
 
     pub fn contents(&self) -> &[u8] {
-        &self.contents.as_slice()
+        self.contents.as_slice()
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns a reader over the contents of the block, i.e. excluding the
+    /// 8-byte block header.
+    pub(crate) fn reader(&self) -> DataReader<'_> {
+        DataReader::new(&self.id, &self.contents, self.offset + 8)
+    }
+
+    pub(crate) fn expect_id(&self, id: &str) -> Result<()> {
+        if self.id() != id {
+            return Err(Error::UnexpectedBlockId {
+                offset: self.offset,
+                block: self.id.clone(),
+                expected: id.to_string(),
+            });
+        }
+        Ok(())
     }
 }
 
@@ -33,36 +63,51 @@ pub struct NtrMetadata {
 
 pub struct NtrFile {
     id: String,
+    byte_order_mark: u16,
     version: NtrFileVersion,
     blocks: Vec<NtrFileBlock>,
 }
 
 impl NtrFile {
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
-        assert!(data.len() > 0);
-        let mut data = data;
-        let file_id = data.read_string(4)?;
-        if data.read_u16()? != 0xFEFF {
-            unimplemented!();
-        }
-        let version: NtrFileVersion = data.read_u16()?.into();
-        let _file_size = data.read_u32()?;
-        assert!(data.read_u16()? == 16);
-        let num_blocks = data.read_u16()?;
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        let mut header = DataReader::new("header", data, 0);
+        let file_id = header.read_string(4)?;
+        let mut header = DataReader::new(&file_id, data, 0);
+        header.seek(4)?;
+        let byte_order_mark_offset = header.offset();
+        let byte_order_mark = header.read_u16()?;
+        if byte_order_mark != 0xFEFF && byte_order_mark != 0xFFFE {
+            return Err(header.error_at(
+                byte_order_mark_offset,
+                format!("invalid byte order mark {:#06x}", byte_order_mark),
+            ));
+        }
+        let version: NtrFileVersion = header.read_u16_as()?;
+        let _file_size = header.read_u32()?;
+        header.expect_u16(16)?;
+        let num_blocks = header.read_u16()?;
 
         let mut blocks = vec![];
         for _ in 0..num_blocks {
-            let block_id = data.read_string(4)?;
-            let block_size = data.read_u32()?;
-            let contents = data.read_sized((block_size - 8).try_into().unwrap())?;
+            let offset = header.offset();
+            let block_id = header.read_string(4)?;
+            let block_size: usize = header.read_u32()?.try_into().unwrap();
+            if block_size < 8 {
+                return Err(
+                    header.error_at(offset, format!("block size {} is too small", block_size))
+                );
+            }
+            let contents = header.read_sized(block_size - 8)?.to_vec();
             blocks.push(NtrFileBlock {
                 id: block_id,
                 contents,
+                offset,
             });
         }
 
         Ok(NtrFile {
             id: file_id,
+            byte_order_mark,
             version,
             blocks,
         })
@@ -71,15 +116,25 @@ impl NtrFile {
     pub fn new(id: &str, version: NtrFileVersion, blocks: Vec<NtrFileBlock>) -> Self {
         Self {
             id: id.to_string(),
+            byte_order_mark: 0xFEFF,
             version,
             blocks,
         }
     }
 
-    pub fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
+    /// Most files store the byte order mark as `0xFEFF`, but some (e.g. NARC)
+    /// store it as `0xFFFE` instead.
+    pub fn with_byte_order_mark(self, byte_order_mark: u16) -> Self {
+        Self {
+            byte_order_mark,
+            ..self
+        }
+    }
+
+    pub fn write_to_data(&self) -> Result<Vec<u8>> {
         let mut data = vec![];
         data.write_string(self.id())?;
-        data.write_u16(0xFEFF)?;
+        data.write_u16(self.byte_order_mark)?;
         data.write_u16(self.version().into())?;
 
         let file_size = 16
@@ -114,71 +169,200 @@ impl NtrFile {
         &self.blocks
     }
 
-    pub fn read_labl_block(block: &NtrFileBlock) -> std::io::Result<Vec<String>> {
-        fn peek_u32(array: &[u8]) -> u32 {
-            u32::from_le_bytes(array[0..4].try_into().unwrap())
-        }
-        assert!(block.id() == "LBAL");
-        // We can never be fully sure how many labels are present in the block,
-        // so this is just an estimation at best. An example of when this would
-        // fail would be when the first 4 labels are empty strings.
-        let mut offsets = vec![];
-        let mut labl = block.contents();
-        loop {
-            let possible_offset = peek_u32(labl);
-            if possible_offset > u32::try_from(labl.len()).unwrap() {
-                break;
-            }
-            if !offsets.is_empty() && possible_offset <= *offsets.last().unwrap() {
-                break;
-            }
-            offsets.push(labl.read_u32()?);
+    pub(crate) fn expect_id(&self, id: &str) -> Result<()> {
+        if self.id() != id {
+            return Err(Error::BadMagic {
+                offset: 0,
+                block: "header".to_string(),
+                expected: id.to_string(),
+                found: self.id.clone(),
+            });
         }
+        Ok(())
+    }
+
+    pub(crate) fn expect_num_blocks(&self, num_blocks: usize) -> Result<()> {
+        if self.blocks.len() != num_blocks {
+            return Err(Error::invalid_data(
+                0x0E,
+                &self.id,
+                format!(
+                    "expected {} blocks, found {}",
+                    num_blocks,
+                    self.blocks.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the block at `index`, which must have the given id.
+    pub(crate) fn block(&self, index: usize, id: &str) -> Result<&NtrFileBlock> {
+        let block = self.blocks.get(index).ok_or_else(|| Error::TruncatedData {
+            offset: self
+                .blocks
+                .last()
+                .map_or(16, |b| b.offset + 8 + b.contents.len()),
+            block: id.to_string(),
+        })?;
+        block.expect_id(id)?;
+        Ok(block)
+    }
+
+    /// Reads the labels of a LABL block, of which there are at most
+    /// `expected_count`, e.g. one per cell of an NCER.
+    ///
+    /// Files may also have fewer labels than entries. The number of labels is
+    /// the largest count up to `expected_count` whose labels are stored one
+    /// after the other and followed by fewer than 4 bytes of padding.
+    pub(crate) fn read_labl_block(block: &NtrFileBlock, expected_count: usize) -> Result<Labels> {
+        block.expect_id("LBAL")?;
+        let contents = block.contents();
+        let (count, end) = (0..=expected_count)
+            .rev()
+            .find_map(|count| {
+                Self::contiguous_labels_end(block, count)
+                    .filter(|end| contents.len() - end < 4)
+                    .map(|end| (count, end))
+            })
+            .ok_or_else(|| {
+                Error::invalid_data(
+                    block.offset() + 8,
+                    block.id(),
+                    format!(
+                        "block does not consist of at most {} labels followed by padding",
+                        expected_count
+                    ),
+                )
+            })?;
+
+        let labl = block.reader();
+        let mut raw_labels = vec![];
+        let mut start = count * 4;
+        while start < end {
+            let len = contents[start..].iter().position(|b| *b == 0).unwrap();
+            raw_labels.push((start, &contents[start..start + len]));
+            start += len + 1;
+        }
+
+        let encoding = match raw_labels
+            .iter()
+            .all(|(_, raw)| std::str::from_utf8(raw).is_ok())
+        {
+            true => LabelEncoding::Utf8,
+            false => LabelEncoding::ShiftJis,
+        };
         let mut labels = vec![];
-        for _ in offsets {
-            let mut label = String::new();
-            loop {
-                let value = labl.read_u8()?;
-                assert!(value < 127);
-                let ch = value as char;
-                if ch == '\0' {
-                    break;
-                }
-                label.push(ch);
+        for (start, raw) in raw_labels {
+            let (label, had_errors) = encoding.encoding().decode_without_bom_handling(raw);
+            if had_errors {
+                return Err(labl.error_at(
+                    block.offset() + 8 + start,
+                    format!("label {:?} is not valid Shift-JIS", raw),
+                ));
+            }
+            labels.push(label.into_owned());
+        }
+
+        Ok(Labels {
+            labels,
+            label_encoding: encoding,
+            label_padding: contents[end..].to_vec(),
+        })
+    }
+
+    /// Returns where the labels end if the block starts with `count` offsets
+    /// to labels that are stored one after the other.
+    fn contiguous_labels_end(block: &NtrFileBlock, count: usize) -> Option<usize> {
+        let contents = block.contents();
+        let strings = contents.get(count * 4..)?;
+        let mut offset = 0;
+        for chunk in contents[..count * 4].chunks(4) {
+            let value = u32::from_le_bytes(chunk.try_into().unwrap());
+            if usize::try_from(value).unwrap() != offset {
+                return None;
             }
-            labels.push(label);
+            offset += strings[offset..].iter().position(|b| *b == 0)? + 1;
         }
-        Ok(labels)
+        Some(count * 4 + offset)
     }
 
-    pub fn write_labl_block(labels: &[String]) -> std::io::Result<NtrFileBlock> {
+    pub(crate) fn write_labl_block(labels: &Labels) -> Result<NtrFileBlock> {
+        let encoding = labels.label_encoding.encoding();
+        let mut raw_labels = vec![];
+        for label in &labels.labels {
+            let (raw, _, had_errors) = encoding.encode(label);
+            if had_errors {
+                return Err(Error::invalid_input(format!(
+                    "label {:?} cannot be encoded as {}",
+                    label,
+                    encoding.name()
+                )));
+            }
+            raw_labels.push(raw);
+        }
+
         let mut labl = vec![];
         let mut offset = 0;
-        for label in labels {
+        for raw in &raw_labels {
             labl.write_u32(offset)?;
-            offset += u32::try_from(label.len()).unwrap() + 1;
+            offset += u32::try_from(raw.len()).unwrap() + 1;
         }
-        for label in labels {
-            labl.write_string(label)?;
-            labl.write_string("\0")?;
+        for raw in &raw_labels {
+            labl.write_all(raw)?;
+            labl.write_u8(0)?;
         }
+        labl.write_all(&labels.label_padding)?;
         Ok(NtrFileBlock::new("LBAL", labl))
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub(crate) enum LabelEncoding {
+    #[default]
+    Utf8,
+    ShiftJis,
+}
+
+impl LabelEncoding {
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            Self::Utf8 => UTF_8,
+            Self::ShiftJis => SHIFT_JIS,
+        }
+    }
+}
+
+/// Contents of a LABL block. Labels are decoded as UTF-8 if possible, and as
+/// Shift-JIS otherwise, and are encoded the same way when written. Any bytes
+/// after the last label are kept as padding.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Labels {
+    pub(crate) labels: Vec<String>,
+    #[serde(default)]
+    pub(crate) label_encoding: LabelEncoding,
+    #[serde(default)]
+    pub(crate) label_padding: Vec<u8>,
+}
+
+/// Contents of an empty UEXT block, as written by the official tools.
+pub(crate) fn default_user_extended_data() -> Vec<u8> {
+    vec![0, 0, 0, 0]
+}
+
 pub trait NtrFormat
 where
     Self: Sized,
 {
-    fn read_from_ntr_file(file: &NtrFile) -> std::io::Result<Self>;
+    fn read_from_ntr_file(file: &NtrFile) -> Result<Self>;
 
-    fn write_to_ntr_file(&self) -> std::io::Result<NtrFile>;
+    fn write_to_ntr_file(&self) -> Result<NtrFile>;
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
+    fn read_from_data(data: &[u8]) -> Result<Self> {
         Self::read_from_ntr_file(&NtrFile::read_from_data(data)?)
     }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
+    fn write_to_data(&self) -> Result<Vec<u8>> {
         self.write_to_ntr_file()?.write_to_data()
     }
 }
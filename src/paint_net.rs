@@ -0,0 +1,80 @@
+use crate::{
+    error::{Error, Result},
+    format::FileFormat,
+    palette::{Color, Palette, PaletteFormat},
+    read_write_ext::LineReader,
+};
+
+/// A paint.net palette, which stores each color as `AARRGGBB` in hexadecimal,
+/// including its alpha.
+pub struct PaintNet {
+    palette: Palette,
+}
+
+impl PaintNet {
+    pub fn from_palette(palette: Palette) -> Self {
+        PaintNet { palette }
+    }
+
+    pub fn to_palette(self) -> Palette {
+        self.palette
+    }
+}
+
+impl PaletteFormat for PaintNet {
+    fn from_palette(palette: Palette) -> Self {
+        Self::from_palette(palette)
+    }
+
+    fn to_palette(self) -> Palette {
+        self.to_palette()
+    }
+}
+
+impl FileFormat for PaintNet {
+    fn extension() -> String {
+        "txt".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        let mut colors = vec![];
+        for (offset, line) in LineReader::new(BLOCK, data) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let value = match line.len() {
+                8 => u32::from_str_radix(line, 16).ok(),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                Error::invalid_data(offset, BLOCK, format!("invalid color {:?}", line))
+            })?;
+            let [alpha, red, green, blue] = value.to_be_bytes();
+            colors.push(Color::new(red, green, blue).with_alpha(alpha));
+        }
+
+        Ok(PaintNet {
+            palette: Palette::new(colors),
+        })
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        let mut lines = vec![];
+        lines.push("; paint.net Palette File".to_string());
+        lines.push("; Lines that start with a semicolon are comments".to_string());
+        lines.push("; Colors are written as 8-digit hexadecimal numbers: aarrggbb".to_string());
+        for color in self.palette.colors() {
+            lines.push(format!(
+                "{:02X}{:02X}{:02X}{:02X}",
+                color.alpha, color.red, color.green, color.blue
+            ));
+        }
+        lines.push("".to_string());
+
+        Ok(lines.join("\r\n").as_bytes().to_vec())
+    }
+}
+
+/// Name used to refer to the file in errors, in place of a block name.
+const BLOCK: &str = "paint.net";
@@ -1,40 +1,181 @@
-#[derive(Debug, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enums::NtrTextureFormat,
+    error::{Error, Result},
+    format::FileFormat,
+};
+
+/// How the 5-bit channels of BGR555 colors are expanded to 8 bits, and
+/// reduced back again.
+///
+/// Reducing an 8-bit channel picks the 5-bit value that expands to it, so a
+/// color read from BGR555 is always written back exactly with the same
+/// scaling. Channels that no 5-bit value expands to are lossy, and are
+/// reduced to the largest 5-bit value that expands to less than them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ColorScaling {
+    /// `x * 8`, as used by pret's `nitrogfx`. White is `(248, 248, 248)`.
+    #[default]
+    Multiply8,
+
+    /// `x * 8 + x / 4`, which spans the full 8-bit range, so white is
+    /// `(255, 255, 255)`.
+    FullRange,
+
+    /// The 8-bit value of each 5-bit value. The values should be distinct,
+    /// which [`ColorScaling::table`] checks, otherwise the 5-bit values that
+    /// share an 8-bit value cannot be told apart and are reduced lossily.
+    Table([u8; 32]),
+}
+
+impl ColorScaling {
+    /// Creates a [`ColorScaling::Table`], failing if two 5-bit values expand
+    /// to the same 8-bit value.
+    pub fn table(table: [u8; 32]) -> Result<Self> {
+        for (i, value) in table.iter().enumerate() {
+            if let Some(j) = table[..i].iter().position(|other| other == value) {
+                return Err(Error::invalid_input(format!(
+                    "color scaling table maps both {} and {} to {}",
+                    j, i, value
+                )));
+            }
+        }
+        Ok(Self::Table(table))
+    }
+
+    /// Expands a 5-bit channel to 8 bits.
+    pub fn expand(&self, value: u8) -> u8 {
+        let value = value & 0x1F;
+        match self {
+            Self::Multiply8 => value * 8,
+            Self::FullRange => value * 8 + value / 4,
+            Self::Table(table) => table[usize::from(value)],
+        }
+    }
+
+    /// Reduces an 8-bit channel to 5 bits, along with whether the reduction
+    /// is exact. It is not if several 5-bit values expand to the channel, in
+    /// which case the first of them is picked.
+    pub fn reduce(&self, value: u8) -> (u8, bool) {
+        let mut matches = (0..32).filter(|x| self.expand(*x) == value);
+        if let Some(first) = matches.next() {
+            return (first, matches.next().is_none());
+        }
+        let below = (0..32)
+            .filter(|x| self.expand(*x) < value)
+            .max_by_key(|x| self.expand(*x))
+            .unwrap_or(0);
+        (below, false)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
     pub blue: u8,
+
+    /// The opacity of the color, from `0` (transparent) to `255` (opaque).
+    /// The DS has no per-color alpha, so this only affects how images are
+    /// displayed by other programs.
+    pub alpha: u8,
+
+    /// The unused high bit of the BGR555 value, kept so that it can be
+    /// written back.
+    pub high_bit: bool,
 }
 
 impl From<u16> for Color {
     fn from(value: u16) -> Self {
-        let red = ((value >> 0 & 0x1F) as u8) * 8;
-        let green = ((value >> 5 & 0x1F) as u8) * 8;
-        let blue = ((value >> 10 & 0x1F) as u8) * 8;
-        Self { red, green, blue }
+        Self::from_bgr555(value, ColorScaling::default())
     }
 }
 
-impl Into<u16> for Color {
-    fn into(self) -> u16 {
-        let r = ((self.red / 8) as u16 & 0x1F) << 0;
-        let g = ((self.green / 8) as u16 & 0x1F) << 5;
-        let b = ((self.blue / 8) as u16 & 0x1F) << 10;
-        r + g + b
+impl From<Color> for u16 {
+    fn from(value: Color) -> Self {
+        value.to_bgr555(ColorScaling::default())
     }
 }
 
 impl Color {
+    /// Creates an opaque color.
     pub fn new(red: u8, green: u8, blue: u8) -> Self {
-        Color { red, green, blue }
+        Color {
+            red,
+            green,
+            blue,
+            alpha: u8::MAX,
+            high_bit: false,
+        }
+    }
+
+    pub fn with_alpha(self, alpha: u8) -> Self {
+        Self { alpha, ..self }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.alpha == 0
+    }
+
+    /// Converts a BGR555 value, including its high bit, expanding the channels
+    /// with `scaling`. The color is opaque.
+    pub fn from_bgr555(value: u16, scaling: ColorScaling) -> Self {
+        let channel = |shift: u16| scaling.expand(((value >> shift) & 0x1F) as u8);
+        Self {
+            red: channel(0),
+            green: channel(5),
+            blue: channel(10),
+            alpha: u8::MAX,
+            high_bit: value >> 15 != 0,
+        }
+    }
+
+    /// Converts the color to a BGR555 value, including its high bit, reducing
+    /// the channels with `scaling`. The alpha is ignored. Use
+    /// [`Color::is_exact`] to check whether this loses information.
+    pub fn to_bgr555(&self, scaling: ColorScaling) -> u16 {
+        let channel = |value: u8| u16::from(scaling.reduce(value).0);
+        channel(self.red)
+            | channel(self.green) << 5
+            | channel(self.blue) << 10
+            | u16::from(self.high_bit) << 15
+    }
+
+    /// Whether the color can be converted to BGR555 with `scaling` without
+    /// losing information.
+    pub fn is_exact(&self, scaling: ColorScaling) -> bool {
+        [self.red, self.green, self.blue]
+            .iter()
+            .all(|value| scaling.reduce(*value).1)
     }
 }
 
+/// A file format that stores a palette, which an NCLR can be converted to and
+/// from.
+pub trait PaletteFormat: FileFormat {
+    fn from_palette(palette: Palette) -> Self;
+
+    fn to_palette(self) -> Palette;
+}
+
 #[derive(Clone)]
 pub struct Palette {
     colors: Vec<Color>,
 }
 
 impl Palette {
+    /// Number of colors in each bank, as selected by the palette number of
+    /// screen entries and objects in 16-color mode.
+    pub const BANK_SIZE: usize = 16;
+
+    /// Number of colors in each bank of an extended palette.
+    pub const EXTENDED_BANK_SIZE: usize = 256;
+
+    /// Number of banks in a full extended palette, as used by BG and OBJ
+    /// extended palette mode.
+    pub const NUM_EXTENDED_BANKS: usize = 16;
+
     pub fn new(colors: Vec<Color>) -> Self {
         Palette { colors }
     }
@@ -43,6 +184,159 @@ impl Palette {
         &self.colors
     }
 
+    /// Returns the indexes of the colors that cannot be converted to BGR555
+    /// with `scaling` without losing information.
+    pub fn lossy_colors(&self, scaling: ColorScaling) -> Vec<usize> {
+        (0..self.colors.len())
+            .filter(|i| !self.colors[*i].is_exact(scaling))
+            .collect()
+    }
+
+    /// Fails if any color cannot be converted to BGR555 with `scaling`
+    /// without losing information.
+    pub(crate) fn expect_exact(&self, scaling: ColorScaling) -> Result<()> {
+        let lossy_colors = self.lossy_colors(scaling);
+        if !lossy_colors.is_empty() {
+            return Err(Error::invalid_input(format!(
+                "colors {:?} cannot be represented exactly with {:?}",
+                lossy_colors, scaling
+            )));
+        }
+        Ok(())
+    }
+
+    /// Builds a palette from 16-color banks, padding each of them with black
+    /// to exactly 16 colors.
+    pub fn from_banks(banks: &[Palette]) -> Result<Self> {
+        let mut palette = Self::new(vec![]);
+        for (n, bank) in banks.iter().enumerate() {
+            palette.set_bank(n, bank)?;
+        }
+        Ok(palette)
+    }
+
+    /// Returns the number of 16-color banks in the palette. The last bank may
+    /// be incomplete.
+    pub fn num_banks(&self) -> usize {
+        self.colors.len().div_ceil(Self::BANK_SIZE)
+    }
+
+    /// Returns the `n`th 16-color bank, or `None` if the palette has no such
+    /// bank.
+    pub fn bank(&self, n: usize) -> Option<Palette> {
+        self.slice_bank(n, Self::BANK_SIZE)
+    }
+
+    /// Returns all 16-color banks of the palette, in order.
+    pub fn banks(&self) -> Vec<Palette> {
+        self.colors
+            .chunks(Self::BANK_SIZE)
+            .map(|colors| Self::new(colors.to_vec()))
+            .collect()
+    }
+
+    /// Replaces the `n`th 16-color bank, which may have at most 16 colors.
+    /// The palette is first padded with black up to the end of the bank.
+    pub fn set_bank(&mut self, n: usize, bank: &Palette) -> Result<()> {
+        if bank.colors.len() > Self::BANK_SIZE {
+            return Err(Error::invalid_input(format!(
+                "bank has {} colors, but at most {} are supported",
+                bank.colors.len(),
+                Self::BANK_SIZE
+            )));
+        }
+        let start = n * Self::BANK_SIZE;
+        let end = start + Self::BANK_SIZE;
+        if self.colors.len() < end {
+            self.colors.resize(end, Color::new(0, 0, 0));
+        }
+        self.colors[start..start + bank.colors.len()].copy_from_slice(&bank.colors);
+        Ok(())
+    }
+
+    /// Makes the first color of every bank in the given color mode transparent,
+    /// as the DS does not draw pixels that use it. All other colors are made
+    /// opaque.
+    pub fn with_transparent_first_colors(self, texture_format: NtrTextureFormat) -> Self {
+        let bank_size = match texture_format {
+            NtrTextureFormat::Palette16 => Self::BANK_SIZE,
+            _ => Self::EXTENDED_BANK_SIZE,
+        };
+        let colors = self
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| match i % bank_size {
+                0 => color.with_alpha(0),
+                _ => color.with_alpha(u8::MAX),
+            })
+            .collect();
+        Self { colors }
+    }
+
+    /// Pads the palette with black up to a whole number of 16-color banks.
+    pub fn padded_to_banks(self) -> Self {
+        self.padded_to(Self::BANK_SIZE)
+    }
+
+    /// Returns the number of 256-color banks in the palette. The last bank may
+    /// be incomplete.
+    pub fn num_extended_banks(&self) -> usize {
+        self.colors.len().div_ceil(Self::EXTENDED_BANK_SIZE)
+    }
+
+    /// Returns the `n`th 256-color bank of an extended palette, or `None` if
+    /// the palette has no such bank.
+    pub fn extended_bank(&self, n: usize) -> Option<Palette> {
+        self.slice_bank(n, Self::EXTENDED_BANK_SIZE)
+    }
+
+    /// Pads the palette with black up to a whole number of 256-color banks.
+    pub fn padded_to_extended_banks(self) -> Self {
+        self.padded_to(Self::EXTENDED_BANK_SIZE)
+    }
+
+    /// Returns the `n`th bank in the given color mode, i.e. a 16-color bank for
+    /// [`NtrTextureFormat::Palette16`] and a 256-color bank for
+    /// [`NtrTextureFormat::Palette256`]. Returns `None` if the palette has no
+    /// such bank or the texture format is not paletted by banks.
+    pub fn bank_for_format(&self, n: usize, texture_format: NtrTextureFormat) -> Option<Palette> {
+        match texture_format {
+            NtrTextureFormat::Palette16 => self.bank(n),
+            NtrTextureFormat::Palette256 => self.extended_bank(n),
+            _ => None,
+        }
+    }
+
+    /// Splits a color index into the 16-color bank it belongs to and its
+    /// index within that bank.
+    pub(crate) fn split_color_index(index: u8) -> (u8, u8) {
+        let bank_size = Self::BANK_SIZE as u8;
+        (index / bank_size, index % bank_size)
+    }
+
+    /// The inverse of [`Palette::split_color_index`].
+    pub(crate) fn color_index(bank: u8, index: u8) -> u8 {
+        bank * Self::BANK_SIZE as u8 + index
+    }
+
+    fn slice_bank(&self, n: usize, bank_size: usize) -> Option<Palette> {
+        let start = n.checked_mul(bank_size)?;
+        if start >= self.colors.len() {
+            return None;
+        }
+        let end = self.colors.len().min(start + bank_size);
+        Some(Self::new(self.colors[start..end].to_vec()))
+    }
+
+    fn padded_to(mut self, bank_size: usize) -> Self {
+        self.colors.resize(
+            self.colors.len().next_multiple_of(bank_size),
+            Color::new(0, 0, 0),
+        );
+        self
+    }
+
     pub(crate) fn gen_16_colors() -> Self {
         let mut colors = vec![];
         for i in (0..=u8::MAX).step_by(0x11) {
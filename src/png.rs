@@ -1,13 +1,37 @@
 use png::{Info, Reader};
 
 use crate::{
+    error::{Error, Result},
     format::FileFormat,
     image::Image,
+    ncgr::NcgrMetadata,
     palette::{Color, Palette},
+    quantize::{Quantization, TruecolorImage},
 };
 
+/// Offset of the IHDR chunk, which always directly follows the PNG signature.
+const IHDR_OFFSET: usize = 8;
+
+/// Keyword of the zTXt chunk storing [`NcgrMetadata`] as JSON.
+const NCGR_METADATA_KEYWORD: &str = "nitrogfx:ncgr";
+
+/// Keyword of the tEXt chunk naming the file the image was rendered from.
+const ORIGIN_KEYWORD: &str = "nitrogfx:origin";
+
 pub struct Png {
-    image: Image,
+    pixels: PngPixels,
+
+    /// Metadata of the NCGR the image was exported from.
+    ncgr_metadata: Option<NcgrMetadata>,
+    origin: Option<String>,
+}
+
+enum PngPixels {
+    Indexed(Image),
+
+    /// Pixels of a truecolor or grayscale PNG, which are only turned into
+    /// indexes when the image is requested.
+    Truecolor(TruecolorImage),
 }
 
 impl FileFormat for Png {
@@ -15,115 +39,349 @@ impl FileFormat for Png {
         "png".to_string()
     }
 
-    fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
+    fn read_from_data(data: &[u8]) -> Result<Self> {
         let decoder = png::Decoder::new(data);
         let mut reader = decoder.read_info()?;
-        let image = Self::read_image(&mut reader);
-        Ok(Self { image })
+        let pixels = match reader.info().color_type {
+            png::ColorType::Indexed => PngPixels::Indexed(Self::read_image(&mut reader)?),
+            _ => {
+                // Every other color type is decoded into 8-bit samples.
+                let mut decoder = png::Decoder::new(data);
+                decoder.set_transformations(
+                    png::Transformations::EXPAND | png::Transformations::STRIP_16,
+                );
+                reader = decoder.read_info()?;
+                PngPixels::Truecolor(Self::read_truecolor_image(&mut reader)?)
+            }
+        };
+
+        // Text chunks may also come after the image data.
+        reader.finish()?;
+        let mut png = Self {
+            pixels,
+            ncgr_metadata: None,
+            origin: None,
+        };
+        for (keyword, text) in Self::read_text(reader.info()) {
+            match keyword.as_str() {
+                NCGR_METADATA_KEYWORD => {
+                    let metadata = text
+                        .and_then(|text| NcgrMetadata::from_json(&text))
+                        .map_err(|e| {
+                            Error::invalid_data(
+                                Self::text_chunk_offset(data, &keyword),
+                                "zTXt",
+                                format!("invalid {} chunk: {}", keyword, e),
+                            )
+                        })?;
+                    png.ncgr_metadata = Some(metadata);
+                }
+                ORIGIN_KEYWORD => png.origin = Some(text?),
+                _ => {}
+            }
+        }
+        Ok(png)
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        match &self.pixels {
+            PngPixels::Indexed(image) => self.write_image(image),
+            PngPixels::Truecolor(image) => self.write_truecolor_image(image),
+        }
+    }
+}
+
+impl Png {
+    pub fn from_image(image: Image) -> Self {
+        Self {
+            pixels: PngPixels::Indexed(image),
+            ncgr_metadata: None,
+            origin: None,
+        }
+    }
+
+    /// Creates a PNG that is written as 8-bit RGBA.
+    pub fn from_truecolor_image(image: TruecolorImage) -> Self {
+        Self {
+            pixels: PngPixels::Truecolor(image),
+            ncgr_metadata: None,
+            origin: None,
+        }
     }
 
-    fn write_to_data(&self) -> std::io::Result<Vec<u8>> {
-        let width = self.image.width().try_into().unwrap();
-        let height = self.image.height().try_into().unwrap();
+    /// Returns the indexed image, quantizing the pixels of a truecolor or
+    /// grayscale PNG with [`Quantization::default`], which fails if they have
+    /// more than 255 colors.
+    pub fn to_image(&self) -> Result<Image> {
+        self.to_image_with_quantization(&Quantization::default())
+    }
+
+    /// Returns the indexed image, quantizing the pixels of a truecolor or
+    /// grayscale PNG as described by `quantization`. Indexed PNGs are
+    /// returned unchanged.
+    pub fn to_image_with_quantization(&self, quantization: &Quantization) -> Result<Image> {
+        match &self.pixels {
+            PngPixels::Indexed(image) => Ok(image.clone()),
+            PngPixels::Truecolor(image) => image.quantize(quantization),
+        }
+    }
+
+    /// Returns the pixels of a truecolor or grayscale PNG, or `None` if the
+    /// PNG is indexed.
+    pub fn truecolor_image(&self) -> Option<&TruecolorImage> {
+        match &self.pixels {
+            PngPixels::Indexed(_) => None,
+            PngPixels::Truecolor(image) => Some(image),
+        }
+    }
+
+    /// Embeds the metadata of the NCGR that the image is exported from, so
+    /// that it can be restored when the image is converted back.
+    ///
+    /// The metadata is lost if the PNG is saved by an editor that does not
+    /// preserve ancillary chunks, so it is worth keeping a sidecar file (see
+    /// [`NcgrMetadata::write_sidecar`]) as a fallback.
+    pub fn with_ncgr_metadata(self, metadata: NcgrMetadata) -> Self {
+        Self {
+            ncgr_metadata: Some(metadata),
+            ..self
+        }
+    }
+
+    /// Returns the embedded NCGR metadata, if any. A PNG whose embedded
+    /// metadata cannot be parsed fails to be read in the first place.
+    pub fn ncgr_metadata(&self) -> Option<NcgrMetadata> {
+        self.ncgr_metadata
+    }
+
+    /// Records the file that the image was rendered from, e.g. the NCER or
+    /// NSCR file.
+    pub fn with_origin(self, origin: &str) -> Self {
+        Self {
+            origin: Some(origin.to_string()),
+            ..self
+        }
+    }
+
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    fn read_image(reader: &mut Reader<&[u8]>) -> Result<Image> {
+        let (color_type, bit_depth) = reader.output_color_type();
+        if !matches!(color_type, png::ColorType::Indexed) {
+            return Err(Error::invalid_data(
+                IHDR_OFFSET,
+                "IHDR",
+                format!("unsupported color type {:?}", color_type),
+            ));
+        }
+        let info = reader.info();
+        let width: usize = info.width.try_into().unwrap();
+        let palette = Self::read_palette(info)?;
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let frame_info = reader.next_frame(&mut buf)?;
+        let bytes = &buf[..frame_info.buffer_size()];
 
-        let palette = self.image.palette().unwrap_or_else(|| {
-            match self.image.pixels().iter().max().unwrap() {
-                0..16 => Palette::gen_16_colors(),
-                16..=255 => Palette::gen_256_colors(),
+        let bits_per_pixel = match bit_depth {
+            png::BitDepth::One => 1,
+            png::BitDepth::Two => 2,
+            png::BitDepth::Four => 4,
+            png::BitDepth::Eight => 8,
+            _ => {
+                return Err(Error::invalid_data(
+                    IHDR_OFFSET,
+                    "IHDR",
+                    format!("unsupported bit depth {}", bit_depth as u8),
+                ))
             }
-        });
-        assert!(palette.colors().len() <= 256);
+        };
+
+        // Each row starts on a new byte, so any bits left over at the end of a
+        // row are skipped.
+        let pixels_per_byte = 8 / bits_per_pixel;
+        let mask = ((1u16 << bits_per_pixel) - 1) as u8;
+        let pixels = bytes
+            .chunks(frame_info.line_size)
+            .flat_map(|row| {
+                row.iter()
+                    .flat_map(|byte| {
+                        (0..pixels_per_byte)
+                            .map(move |i| (byte >> (8 - bits_per_pixel * (i + 1))) & mask)
+                    })
+                    .take(width)
+            })
+            .collect::<Vec<u8>>();
+
+        Ok(Image::new(width, &pixels, Some(palette)))
+    }
+
+    /// Reads an image whose samples have been expanded to 8 bits.
+    fn read_truecolor_image(reader: &mut Reader<&[u8]>) -> Result<TruecolorImage> {
+        let (color_type, _) = reader.output_color_type();
+        let width: usize = reader.info().width.try_into().unwrap();
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let frame_info = reader.next_frame(&mut buf)?;
+        let bytes = &buf[..frame_info.buffer_size()];
+
+        let samples = color_type.samples();
+        let pixels = bytes
+            .chunks(frame_info.line_size)
+            .flat_map(|row| row.chunks_exact(samples).take(width))
+            .map(|sample| match color_type {
+                png::ColorType::Grayscale => Color::new(sample[0], sample[0], sample[0]),
+                png::ColorType::GrayscaleAlpha => {
+                    Color::new(sample[0], sample[0], sample[0]).with_alpha(sample[1])
+                }
+                png::ColorType::Rgb => Color::new(sample[0], sample[1], sample[2]),
+                png::ColorType::Rgba => {
+                    Color::new(sample[0], sample[1], sample[2]).with_alpha(sample[3])
+                }
+                png::ColorType::Indexed => unreachable!(),
+            })
+            .collect();
+
+        Ok(TruecolorImage::new(width, pixels))
+    }
+
+    fn write_image(&self, image: &Image) -> Result<Vec<u8>> {
+        let width = image.width().try_into().unwrap();
+        let height = image.height().try_into().unwrap();
+
+        let palette =
+            image
+                .palette()
+                .unwrap_or_else(|| match image.pixels().iter().max().unwrap_or(&0) {
+                    0..16 => Palette::gen_16_colors(),
+                    16..=255 => Palette::gen_256_colors(),
+                });
+        if palette.colors().len() > 256 {
+            return Err(Error::invalid_input(format!(
+                "palette has {} colors, but at most 256 are supported; select a single bank of an extended palette instead",
+                palette.colors().len()
+            )));
+        }
 
         let mut data = vec![];
         let buf_writer = std::io::BufWriter::new(&mut data);
         let mut encoder = png::Encoder::new(buf_writer, width, height);
         encoder.set_color(png::ColorType::Indexed);
         let bit_depth = match palette.colors().len() {
-            0..=8 => unimplemented!(),
-            9..=16 => png::BitDepth::Four,
+            0..=16 => png::BitDepth::Four,
             17..=256 => png::BitDepth::Eight,
             _ => unreachable!(),
         };
         encoder.set_depth(bit_depth);
         encoder.set_palette(Self::write_palette(&palette));
+        if let Some(trns) = Self::write_trns(&palette) {
+            encoder.set_trns(trns);
+        }
+        self.add_text_chunks(&mut encoder)?;
         let mut writer = encoder.write_header()?;
         let pixels = match bit_depth {
-            png::BitDepth::Four => &self
-                .image
+            // Each row starts on a new byte, so rows of odd width are padded.
+            png::BitDepth::Four => &image
                 .pixels()
-                .chunks(2)
-                .map(|chunk| chunk[0] << 4 | chunk[1])
+                .chunks(image.width())
+                .flat_map(|row| row.chunks(2))
+                .map(|chunk| chunk[0] << 4 | chunk.get(1).unwrap_or(&0))
                 .collect::<Vec<u8>>(),
-            png::BitDepth::Eight => self.image.pixels(),
+            png::BitDepth::Eight => image.pixels(),
             _ => unimplemented!(),
         };
 
-        writer.write_image_data(pixels).unwrap();
-        writer.finish().unwrap();
+        writer.write_image_data(pixels)?;
+        writer.finish()?;
         Ok(data)
     }
-}
 
-impl Png {
-    pub fn from_image(image: Image) -> Self {
-        let image = match image.palette() {
-            Some(palette) => {
-                if palette.colors().len() > 256 {
-                    eprintln!("truncating palette to first 256 colors");
-                    image.with_palette(Palette::new(palette.colors()[0..256].to_vec()))
-                } else {
-                    image
-                }
-            }
-            None => image,
-        };
-        Self { image }
-    }
+    fn write_truecolor_image(&self, image: &TruecolorImage) -> Result<Vec<u8>> {
+        let width = image.width().try_into().unwrap();
+        let height = image.height().try_into().unwrap();
 
-    pub fn to_image(&self) -> Image {
-        self.image.clone()
+        let mut data = vec![];
+        let buf_writer = std::io::BufWriter::new(&mut data);
+        let mut encoder = png::Encoder::new(buf_writer, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        self.add_text_chunks(&mut encoder)?;
+        let mut writer = encoder.write_header()?;
+        let pixels = image
+            .pixels()
+            .iter()
+            .flat_map(|color| [color.red, color.green, color.blue, color.alpha])
+            .collect::<Vec<u8>>();
+
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        Ok(data)
     }
 
-    fn read_image(reader: &mut Reader<&[u8]>) -> Image {
-        let (color_type, bit_depth) = reader.output_color_type();
-        assert!(matches!(color_type, png::ColorType::Indexed));
-        let info = reader.info();
-        let width: usize = info.width.try_into().unwrap();
-        let palette = Self::read_palette(info);
+    fn add_text_chunks<W: std::io::Write>(&self, encoder: &mut png::Encoder<W>) -> Result<()> {
+        if let Some(ncgr_metadata) = &self.ncgr_metadata {
+            encoder.add_ztxt_chunk(NCGR_METADATA_KEYWORD.to_string(), ncgr_metadata.to_json()?)?;
+        }
+        if let Some(origin) = &self.origin {
+            encoder.add_text_chunk(ORIGIN_KEYWORD.to_string(), origin.clone())?;
+        }
+        Ok(())
+    }
 
-        let mut buf = vec![0; reader.output_buffer_size()];
-        let frame_info = reader.next_frame(&mut buf).unwrap();
-        let bytes = &buf[..frame_info.buffer_size()];
+    /// Returns the keyword and text of every tEXt, zTXt and iTXt chunk. The
+    /// text of a compressed chunk is an error if it cannot be decompressed,
+    /// which only matters for the chunks that are used.
+    fn read_text(info: &Info) -> Vec<(String, Result<String>)> {
+        let mut text = vec![];
+        for chunk in &info.uncompressed_latin1_text {
+            text.push((chunk.keyword.clone(), Ok(chunk.text.clone())));
+        }
+        for chunk in &info.compressed_latin1_text {
+            text.push((chunk.keyword.clone(), chunk.get_text().map_err(Error::from)));
+        }
+        for chunk in &info.utf8_text {
+            text.push((chunk.keyword.clone(), chunk.get_text().map_err(Error::from)));
+        }
+        text
+    }
 
-        let pixels = match bit_depth {
-            png::BitDepth::Four => {
-                let mut pixels = vec![];
-                for byte in bytes {
-                    pixels.push(byte >> 4);
-                    pixels.push(byte & 0xF);
-                }
-                pixels
+    /// Returns the offset of the first text chunk with the given keyword, or
+    /// that of the IHDR chunk if there is none.
+    fn text_chunk_offset(data: &[u8], keyword: &str) -> usize {
+        let mut offset = IHDR_OFFSET;
+        while let Some(header) = data.get(offset..offset + 8) {
+            let length =
+                usize::try_from(u32::from_be_bytes(header[..4].try_into().unwrap())).unwrap();
+            let contents = data.get(offset + 8..).unwrap_or_default();
+            if matches!(&header[4..], b"tEXt" | b"zTXt" | b"iTXt")
+                && contents.starts_with(keyword.as_bytes())
+                && contents.get(keyword.len()) == Some(&0)
+            {
+                return offset;
             }
-            png::BitDepth::Eight => bytes.to_vec(),
-            _ => unimplemented!(),
-        };
-
-        assert!(pixels.len() % width == 0);
-
-        Image::new(width, &pixels, Some(palette))
+            // Each chunk is followed by its CRC.
+            offset += length + 12;
+        }
+        IHDR_OFFSET
     }
 
-    fn read_palette(info: &Info) -> Palette {
+    fn read_palette(info: &Info) -> Result<Palette> {
         let mut colors = vec![];
-        let raw_palette = info.palette.clone().unwrap();
-        assert!(raw_palette.len() % 3 == 0);
-        for chunk in raw_palette.chunks(3) {
+        let raw_palette = info.palette.clone().ok_or_else(|| {
+            Error::invalid_data(IHDR_OFFSET, "PLTE", "indexed image has no palette")
+        })?;
+        // The tRNS chunk holds the alpha of the first colors, and any colors
+        // past its end are opaque.
+        let alphas = info.trns.as_deref().unwrap_or_default();
+        for (i, chunk) in raw_palette.chunks_exact(3).enumerate() {
             let red = chunk[0];
             let green = chunk[1];
             let blue = chunk[2];
-            colors.push(Color::new(red, green, blue));
+            let alpha = alphas.get(i).copied().unwrap_or(u8::MAX);
+            colors.push(Color::new(red, green, blue).with_alpha(alpha));
         }
-        Palette::new(colors)
+        Ok(Palette::new(colors))
     }
 
     fn write_palette(palette: &Palette) -> Vec<u8> {
@@ -135,4 +393,20 @@ impl Png {
         }
         out
     }
+
+    /// Returns the contents of the tRNS chunk, or `None` if every color is
+    /// opaque.
+    fn write_trns(palette: &Palette) -> Option<Vec<u8>> {
+        let len = palette
+            .colors()
+            .iter()
+            .rposition(|color| color.alpha != u8::MAX)?
+            + 1;
+        Some(
+            palette.colors()[..len]
+                .iter()
+                .map(|color| color.alpha)
+                .collect(),
+        )
+    }
 }
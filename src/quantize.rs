@@ -0,0 +1,538 @@
+use std::collections::HashMap;
+
+use crate::{
+    enums::NtrTextureFormat,
+    error::{Error, Result},
+    image::Image,
+    palette::{Color, ColorScaling, Palette},
+};
+
+/// Pixels with an alpha below this are treated as transparent.
+const ALPHA_THRESHOLD: u8 = 0x80;
+
+/// Roughly the difference between the 8-bit values of neighbouring 5-bit
+/// values.
+const BGR555_STEP: f32 = 8.0;
+
+/// Thresholds of ordered dithering, from 0 to 15.
+const BAYER_MATRIX: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// An image whose pixels are colors rather than indexes into a palette, e.g.
+/// a truecolor or grayscale PNG.
+#[derive(Clone)]
+pub struct TruecolorImage {
+    width: usize,
+    pixels: Vec<Color>,
+}
+
+impl TruecolorImage {
+    pub fn new(width: usize, pixels: Vec<Color>) -> Self {
+        assert!(pixels.len().is_multiple_of(width));
+        Self { width, pixels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.len() / self.width
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Converts the image to an indexed image as described by `quantization`.
+    pub fn quantize(&self, quantization: &Quantization) -> Result<Image> {
+        let scaling = quantization.color_scaling;
+        let dithering = quantization.dithering;
+        let transparent_index = usize::from(quantization.transparent_index);
+        let (palette, pixels) = match &quantization.target {
+            Target::Palette {
+                palette,
+                nearest: true,
+            } if dithering != Dithering::None => (
+                palette.clone(),
+                self.dither_to_palette(palette, dithering, scaling, transparent_index, None)?,
+            ),
+            Target::Palette { palette, nearest } => (
+                palette.clone(),
+                self.match_palette(palette, *nearest, scaling, transparent_index)?,
+            ),
+            Target::Automatic(max_colors) => {
+                // The palette is built from the colors before dithering, which
+                // then only uses colors of the palette, so that it cannot add
+                // more colors than fit.
+                let (palette, pixels) =
+                    self.build_palette(*max_colors, scaling, transparent_index)?;
+                let pixels = match dithering {
+                    Dithering::None => pixels,
+                    _ => self.dither_to_palette(
+                        &palette,
+                        dithering,
+                        scaling,
+                        transparent_index,
+                        Some(BGR555_STEP),
+                    )?,
+                };
+                (palette, pixels)
+            }
+        };
+        Ok(Image::new(self.width, &pixels, Some(palette)))
+    }
+
+    fn match_palette(
+        &self,
+        palette: &Palette,
+        nearest: bool,
+        scaling: ColorScaling,
+        transparent_index: usize,
+    ) -> Result<Vec<u8>> {
+        check_palette_len(palette, transparent_index)?;
+        let targets = palette
+            .colors()
+            .iter()
+            .map(|color| Bgr555::new(color, scaling))
+            .collect::<Vec<_>>();
+        let mut cache = HashMap::new();
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        for (i, color) in self.pixels.iter().enumerate() {
+            if color.alpha < ALPHA_THRESHOLD {
+                pixels.push(u8::try_from(transparent_index).unwrap());
+                continue;
+            }
+            let value = Bgr555::new(color, scaling);
+            let index = match cache.get(&value) {
+                Some(index) => *index,
+                None => {
+                    let index = find_color(&targets, value, nearest, transparent_index)
+                        .ok_or_else(|| {
+                            Error::invalid_input(format!(
+                                "color ({}, {}, {}) at ({}, {}) is not in the palette",
+                                color.red,
+                                color.green,
+                                color.blue,
+                                i % self.width,
+                                i / self.width
+                            ))
+                        })?;
+                    cache.insert(value, index);
+                    index
+                }
+            };
+            pixels.push(index);
+        }
+        Ok(pixels)
+    }
+
+    /// Dithers the image to the colors of `palette`. `spread` is that of
+    /// [`TruecolorImage::dither`], and defaults to the mean spacing of the
+    /// colors.
+    fn dither_to_palette(
+        &self,
+        palette: &Palette,
+        dithering: Dithering,
+        scaling: ColorScaling,
+        transparent_index: usize,
+        spread: Option<f32>,
+    ) -> Result<Vec<u8>> {
+        check_palette_len(palette, transparent_index)?;
+        // The colors as displayed by the DS. The transparent index is never
+        // dithered into unless it is the only color.
+        let targets = palette
+            .colors()
+            .iter()
+            .map(|color| channels(&Color::from_bgr555(color.to_bgr555(scaling), scaling)))
+            .collect::<Vec<_>>();
+        let candidates = opaque_indexes(targets.len(), transparent_index);
+        let spread = spread.unwrap_or_else(|| {
+            mean_spacing(&candidates.iter().map(|i| targets[*i]).collect::<Vec<_>>())
+        });
+        let pixels = self.dither(dithering, spread, |color| {
+            let index = *candidates
+                .iter()
+                .min_by(|a, b| {
+                    distance(targets[**a], color).total_cmp(&distance(targets[**b], color))
+                })
+                .unwrap();
+            (u8::try_from(index).unwrap(), targets[index])
+        });
+        let transparent_index = u8::try_from(transparent_index).unwrap();
+        Ok(pixels
+            .into_iter()
+            .map(|index| index.unwrap_or(transparent_index))
+            .collect())
+    }
+
+    /// Reduces the color of every opaque pixel with `reduce`, which returns
+    /// the reduced value along with the color it is displayed as, and spreads
+    /// the difference as described by `dithering`. Transparent pixels are
+    /// `None`, and neither spread nor take any of the difference.
+    ///
+    /// `spread` is the range of the offsets of ordered dithering, which should
+    /// be about the distance between the colors that pixels are reduced to.
+    fn dither<T>(
+        &self,
+        dithering: Dithering,
+        spread: f32,
+        mut reduce: impl FnMut([f32; 3]) -> (T, [f32; 3]),
+    ) -> Vec<Option<T>> {
+        let height = self.height();
+        let mut errors = vec![[0.0; 3]; self.pixels.len()];
+        let mut values = Vec::with_capacity(self.pixels.len());
+        for (i, color) in self.pixels.iter().enumerate() {
+            if color.alpha < ALPHA_THRESHOLD {
+                values.push(None);
+                continue;
+            }
+            let (x, y) = (i % self.width, i / self.width);
+            let offset = match dithering {
+                Dithering::Bayer => {
+                    ((f32::from(BAYER_MATRIX[y % 4][x % 4]) + 0.5) / 16.0 - 0.5) * spread
+                }
+                _ => 0.0,
+            };
+            let original = channels(color);
+            let wanted: [f32; 3] =
+                std::array::from_fn(|c| (original[c] + errors[i][c] + offset).clamp(0.0, 255.0));
+            let (value, displayed) = reduce(wanted);
+            values.push(Some(value));
+
+            for (dx, dy, share) in dithering.diffusion() {
+                let Some(nx) = x.checked_add_signed(*dx).filter(|nx| *nx < self.width) else {
+                    continue;
+                };
+                if y + dy >= height {
+                    continue;
+                }
+                let error = &mut errors[(y + dy) * self.width + nx];
+                for c in 0..3 {
+                    error[c] += (wanted[c] - displayed[c]) * share;
+                }
+            }
+        }
+        values
+    }
+
+    /// Builds a palette of the colors of the image, reduced with `scaling`.
+    fn build_palette(
+        &self,
+        max_colors: usize,
+        scaling: ColorScaling,
+        transparent_index: usize,
+    ) -> Result<(Palette, Vec<u8>)> {
+        if !(2..=256).contains(&max_colors) {
+            return Err(Error::invalid_input(format!(
+                "cannot quantize to {} colors, as it must be between 2 and 256",
+                max_colors
+            )));
+        }
+        if transparent_index >= max_colors {
+            return Err(Error::invalid_input(format!(
+                "transparent index {} does not fit in a palette of {} colors",
+                transparent_index, max_colors
+            )));
+        }
+
+        let mut opaque_colors = vec![];
+        let mut indexes = HashMap::new();
+        let opaque_pixels = self
+            .pixels
+            .iter()
+            .map(|color| {
+                (color.alpha >= ALPHA_THRESHOLD).then(|| {
+                    let value = color.to_bgr555(scaling) & 0x7FFF;
+                    *indexes.entry(value).or_insert_with(|| {
+                        opaque_colors.push(Color::from_bgr555(value, scaling));
+                        opaque_colors.len() - 1
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        if opaque_colors.len() >= max_colors {
+            return Err(Error::invalid_input(format!(
+                "image has {} colors, but at most {} fit in a palette of {} colors along with the transparent color",
+                opaque_colors.len(),
+                max_colors - 1,
+                max_colors
+            )));
+        }
+
+        // The transparent index keeps the color of the first transparent
+        // pixel so that it can still be told apart in editors, and the other
+        // colors fill the palette around it, padded with black if needed.
+        let transparent = self
+            .pixels
+            .iter()
+            .find(|color| color.alpha < ALPHA_THRESHOLD)
+            .map_or(Color::new(0, 0, 0), |color| {
+                Color::from_bgr555(color.to_bgr555(scaling) & 0x7FFF, scaling)
+            })
+            .with_alpha(0);
+        let mut colors = opaque_colors;
+        if colors.len() < transparent_index {
+            colors.resize(transparent_index, Color::new(0, 0, 0));
+        }
+        colors.insert(transparent_index, transparent);
+        let pixels = opaque_pixels
+            .into_iter()
+            .map(|index| match index {
+                None => transparent_index,
+                Some(index) if index < transparent_index => index,
+                Some(index) => index + 1,
+            })
+            .map(|index| u8::try_from(index).unwrap())
+            .collect();
+        Ok((Palette::new(colors), pixels))
+    }
+}
+
+/// Checks that the palette has at most 256 colors, one of which is at the
+/// transparent index.
+fn check_palette_len(palette: &Palette, transparent_index: usize) -> Result<()> {
+    match palette.colors().len() {
+        len if len > 256 => Err(Error::invalid_input(format!(
+            "palette has {} colors, but at most 256 are supported",
+            len
+        ))),
+        len if transparent_index >= len => Err(Error::invalid_input(format!(
+            "transparent index {} is past the end of the palette of {} colors",
+            transparent_index, len
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the indexes that opaque pixels may take, i.e. all but the
+/// transparent index, unless it is the only one.
+fn opaque_indexes(len: usize, transparent_index: usize) -> Vec<usize> {
+    match len {
+        1 => vec![0],
+        len => (0..len).filter(|i| *i != transparent_index).collect(),
+    }
+}
+
+/// Returns the 8-bit channels of a color as floats, for dithering.
+fn channels(color: &Color) -> [f32; 3] {
+    [color.red, color.green, color.blue].map(f32::from)
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c]).powi(2)).sum()
+}
+
+/// Returns the mean distance from each color to the closest other color, per
+/// channel, as ordered dithering offsets each channel by the spread. Grays
+/// that are `d` apart in each channel are thus `d` apart rather than `d * √3`.
+fn mean_spacing(colors: &[[f32; 3]]) -> f32 {
+    if colors.len() < 2 {
+        return 0.0;
+    }
+    let total: f32 = colors
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            colors
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, b)| distance(*a, *b).sqrt())
+                .fold(f32::INFINITY, f32::min)
+        })
+        .sum();
+    total / colors.len() as f32 / 3f32.sqrt()
+}
+
+/// Finds the index of `value` in `targets`, or of the closest color if
+/// `nearest` is set. The transparent index is reserved for transparent
+/// pixels, so it is only used when it is the only color or no other color
+/// matches exactly.
+fn find_color(
+    targets: &[Bgr555],
+    value: Bgr555,
+    nearest: bool,
+    transparent_index: usize,
+) -> Option<u8> {
+    let candidates = opaque_indexes(targets.len(), transparent_index);
+    let index = candidates
+        .iter()
+        .copied()
+        .chain([transparent_index])
+        .find(|i| targets[*i] == value)
+        .or_else(|| {
+            nearest
+                .then(|| {
+                    candidates
+                        .iter()
+                        .copied()
+                        .min_by_key(|i| targets[*i].distance(value))
+                })
+                .flatten()
+        })?;
+    Some(u8::try_from(index).unwrap())
+}
+
+/// The channels of a color reduced to 5 bits.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Bgr555([u8; 3]);
+
+impl Bgr555 {
+    fn new(color: &Color, scaling: ColorScaling) -> Self {
+        let value = color.to_bgr555(scaling);
+        Self([0, 5, 10].map(|shift| ((value >> shift) & 0x1F) as u8))
+    }
+
+    fn distance(self, other: Self) -> u32 {
+        (0..3)
+            .map(|i| u32::from(self.0[i].abs_diff(other.0[i])).pow(2))
+            .sum()
+    }
+}
+
+/// Describes how the colors of a [`TruecolorImage`] are turned into indexes.
+///
+/// Transparent pixels, i.e. those with an alpha below 128, always take the
+/// transparent index, which is 0 unless set with
+/// [`Quantization::with_transparent_index`]. Colors are compared in the 15-bit color space of the DS, after being
+/// reduced with the color scaling.
+#[derive(Clone, Debug)]
+pub struct Quantization {
+    target: Target,
+    color_scaling: ColorScaling,
+    dithering: Dithering,
+    transparent_index: u8,
+}
+
+/// Ways of hiding the banding left when colors are reduced, by spreading the
+/// difference between the original and reduced colors over the pixels.
+///
+/// Dithering applies when matching the nearest colors of a palette, and when
+/// building a palette, where it hides the reduction to 15-bit colors using
+/// only the colors of the palette built without it. It has no effect when
+/// matching colors exactly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dithering {
+    #[default]
+    None,
+
+    /// Error diffusion to the 4 following neighbours of each pixel.
+    FloydSteinberg,
+
+    /// Ordered dithering with a 4x4 matrix. Unlike error diffusion, each
+    /// pixel only depends on its own color and position, so repeated tiles
+    /// stay identical.
+    Bayer,
+
+    /// Error diffusion of 3/4 of the difference to the 6 following neighbours
+    /// of each pixel, which keeps more contrast than Floyd-Steinberg.
+    Atkinson,
+}
+
+impl Dithering {
+    /// The neighbours that the difference of a pixel is spread to, as offsets
+    /// in x and y along with their share of the difference.
+    fn diffusion(self) -> &'static [(isize, usize, f32)] {
+        match self {
+            Self::None | Self::Bayer => &[],
+            Self::FloydSteinberg => &[
+                (1, 0, 7.0 / 16.0),
+                (-1, 1, 3.0 / 16.0),
+                (0, 1, 5.0 / 16.0),
+                (1, 1, 1.0 / 16.0),
+            ],
+            Self::Atkinson => &[
+                (1, 0, 1.0 / 8.0),
+                (2, 0, 1.0 / 8.0),
+                (-1, 1, 1.0 / 8.0),
+                (0, 1, 1.0 / 8.0),
+                (1, 1, 1.0 / 8.0),
+                (0, 2, 1.0 / 8.0),
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    Palette { palette: Palette, nearest: bool },
+    Automatic(usize),
+}
+
+impl Default for Quantization {
+    fn default() -> Self {
+        Self::automatic(256)
+    }
+}
+
+impl Quantization {
+    /// Uses the index of the color of `palette` that each pixel matches
+    /// exactly. Any other color is an error.
+    pub fn exact(palette: Palette) -> Self {
+        Self {
+            target: Target::Palette {
+                palette,
+                nearest: false,
+            },
+            color_scaling: ColorScaling::default(),
+            dithering: Dithering::default(),
+            transparent_index: 0,
+        }
+    }
+
+    /// Uses the index of the color of `palette` that is closest to each pixel.
+    pub fn nearest(palette: Palette) -> Self {
+        Self {
+            target: Target::Palette {
+                palette,
+                nearest: true,
+            },
+            color_scaling: ColorScaling::default(),
+            dithering: Dithering::default(),
+            transparent_index: 0,
+        }
+    }
+
+    /// Builds a palette of at most `max_colors` colors, e.g. 16 or 256, whose
+    /// color at the transparent index is reserved for transparent pixels. It
+    /// is an error for the image to have more colors than fit.
+    pub fn automatic(max_colors: usize) -> Self {
+        Self {
+            target: Target::Automatic(max_colors),
+            color_scaling: ColorScaling::default(),
+            dithering: Dithering::default(),
+            transparent_index: 0,
+        }
+    }
+
+    /// Builds a palette of 16 colors for [`NtrTextureFormat::Palette16`] and
+    /// of 256 colors otherwise, as with [`Quantization::automatic`].
+    pub fn for_texture_format(texture_format: NtrTextureFormat) -> Self {
+        match texture_format {
+            NtrTextureFormat::Palette16 => Self::automatic(16),
+            _ => Self::automatic(256),
+        }
+    }
+
+    pub fn with_color_scaling(self, color_scaling: ColorScaling) -> Self {
+        Self {
+            color_scaling,
+            ..self
+        }
+    }
+
+    /// Pixels that are transparent, and so take the transparent index, are
+    /// never dithered, and opaque pixels are never dithered into it.
+    pub fn with_dithering(self, dithering: Dithering) -> Self {
+        Self { dithering, ..self }
+    }
+
+    /// Sets the index that transparent pixels take, which opaque pixels only
+    /// take if it is the only color of the palette. Defaults to 0.
+    pub fn with_transparent_index(self, transparent_index: u8) -> Self {
+        Self {
+            transparent_index,
+            ..self
+        }
+    }
+}
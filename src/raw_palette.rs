@@ -0,0 +1,164 @@
+use crate::{
+    error::{Error, Result},
+    format::FileFormat,
+    palette::{Color, ColorScaling, Palette, PaletteFormat},
+    read_write_ext::WriteExt,
+};
+
+/// Options for converting between a [`RawPalette`] and a [`Palette`].
+#[derive(Debug, Clone, Copy)]
+pub struct RawPaletteOptions {
+    /// When writing, pads the palette with black to a multiple of this many
+    /// colors, e.g. 16 or 256. Defaults to no padding.
+    pub padding: Option<usize>,
+
+    /// Whether the unused high bit of each color is kept, rather than
+    /// cleared. Defaults to true.
+    pub preserve_high_bit: bool,
+
+    /// How the colors are converted to and from 8-bit RGB. Defaults to
+    /// [`ColorScaling::Multiply8`].
+    pub color_scaling: ColorScaling,
+}
+
+impl Default for RawPaletteOptions {
+    fn default() -> Self {
+        Self {
+            padding: None,
+            preserve_high_bit: true,
+            color_scaling: ColorScaling::default(),
+        }
+    }
+}
+
+impl RawPaletteOptions {
+    pub fn with_padding(self, padding: usize) -> Self {
+        Self {
+            padding: Some(padding),
+            ..self
+        }
+    }
+
+    pub fn preserve_high_bit(self, preserve_high_bit: bool) -> Self {
+        Self {
+            preserve_high_bit,
+            ..self
+        }
+    }
+
+    pub fn with_color_scaling(self, color_scaling: ColorScaling) -> Self {
+        Self {
+            color_scaling,
+            ..self
+        }
+    }
+}
+
+/// A palette stored as a bare array of little-endian BGR555 colors, without
+/// any header, e.g. `.gbapal` files in pret's GBA projects or `.bin`/`.nbfp`
+/// files in DS games. The colors are converted the same way as those of an
+/// [`Nclr`](crate::Nclr).
+pub struct RawPalette {
+    /// The BGR555 values of the colors, including their high bits.
+    colors: Vec<u16>,
+}
+
+impl RawPalette {
+    pub fn from_palette(palette: Palette) -> Self {
+        Self::from_palette_with_options(palette, RawPaletteOptions::default())
+    }
+
+    /// Converts the colors with the color scaling of `options`. Colors that
+    /// it cannot represent exactly are reduced, which can be checked for
+    /// beforehand with [`Palette::lossy_colors`].
+    pub fn from_palette_with_options(palette: Palette, options: RawPaletteOptions) -> Self {
+        let mut colors = palette
+            .colors()
+            .iter()
+            .map(|color| {
+                let value = color.to_bgr555(options.color_scaling);
+                match options.preserve_high_bit {
+                    true => value,
+                    false => value & 0x7FFF,
+                }
+            })
+            .collect::<Vec<_>>();
+        if let Some(padding) = options.padding.filter(|padding| *padding > 0) {
+            colors.resize(colors.len().next_multiple_of(padding), 0);
+        }
+        Self { colors }
+    }
+
+    /// Same as [`RawPalette::from_palette_with_options`], but fails instead if
+    /// any color cannot be represented exactly.
+    pub fn try_from_palette_with_options(
+        palette: Palette,
+        options: RawPaletteOptions,
+    ) -> Result<Self> {
+        palette.expect_exact(options.color_scaling)?;
+        Ok(Self::from_palette_with_options(palette, options))
+    }
+
+    pub fn to_palette(self) -> Palette {
+        self.to_palette_with_options(RawPaletteOptions::default())
+    }
+
+    /// Converts the colors with the color scaling of `options`. The padding is
+    /// not removed, as it cannot be told apart from black colors.
+    pub fn to_palette_with_options(&self, options: RawPaletteOptions) -> Palette {
+        Palette::new(
+            self.colors
+                .iter()
+                .map(|value| {
+                    let value = match options.preserve_high_bit {
+                        true => *value,
+                        false => value & 0x7FFF,
+                    };
+                    Color::from_bgr555(value, options.color_scaling)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl PaletteFormat for RawPalette {
+    fn from_palette(palette: Palette) -> Self {
+        Self::from_palette(palette)
+    }
+
+    fn to_palette(self) -> Palette {
+        self.to_palette()
+    }
+}
+
+impl FileFormat for RawPalette {
+    fn extension() -> String {
+        "gbapal".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        if !data.len().is_multiple_of(2) {
+            return Err(Error::invalid_data(
+                data.len() - 1,
+                BLOCK,
+                format!("size {} is not a whole number of colors", data.len()),
+            ));
+        }
+        let colors = data
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        Ok(Self { colors })
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        for color in &self.colors {
+            data.write_u16(*color)?;
+        }
+        Ok(data)
+    }
+}
+
+/// Name used to refer to the file in errors, in place of a block name.
+const BLOCK: &str = "raw palette";
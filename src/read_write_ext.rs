@@ -1,46 +1,6 @@
-use std::io::{Read, Write};
-
-pub(crate) trait ReadExt {
-    fn read_sized(&mut self, size: usize) -> std::io::Result<Vec<u8>>;
-    fn read_u8(&mut self) -> std::io::Result<u8>;
-    fn read_u16(&mut self) -> std::io::Result<u16>;
-    fn read_i16(&mut self) -> std::io::Result<i16>;
-    fn read_u32(&mut self) -> std::io::Result<u32>;
-    fn read_string(&mut self, size: usize) -> std::io::Result<String>;
-}
-
-impl<T: ?Sized + Read> ReadExt for T {
-    fn read_sized(&mut self, size: usize) -> std::io::Result<Vec<u8>> {
-        let mut buf = vec![0u8; size];
-        self.read_exact(&mut buf)?;
-        Ok(buf)
-    }
-
-    fn read_u8(&mut self) -> std::io::Result<u8> {
-        let buf = self.read_sized(1)?;
-        Ok(u8::from_le_bytes(buf.try_into().unwrap()))
-    }
-
-    fn read_u16(&mut self) -> std::io::Result<u16> {
-        let buf = self.read_sized(2)?;
-        Ok(u16::from_le_bytes(buf.try_into().unwrap()))
-    }
-
-    fn read_i16(&mut self) -> std::io::Result<i16> {
-        let buf = self.read_sized(2)?;
-        Ok(i16::from_le_bytes(buf.try_into().unwrap()))
-    }
+use std::io::Write;
 
-    fn read_u32(&mut self) -> std::io::Result<u32> {
-        let buf = self.read_sized(4)?;
-        Ok(u32::from_le_bytes(buf.try_into().unwrap()))
-    }
-
-    fn read_string(&mut self, size: usize) -> std::io::Result<String> {
-        let buf = self.read_sized(size)?;
-        Ok(core::str::from_utf8(&buf).unwrap().to_string())
-    }
-}
+use crate::error::{Error, InvalidEnumValue, Result};
 
 pub(crate) trait WriteExt {
     #[allow(dead_code)]
@@ -72,3 +32,200 @@ impl<T: Write> WriteExt for T {
         self.write_all(s.as_bytes())
     }
 }
+
+/// Reads little-endian values from a block of data, keeping track of the
+/// position so that errors can point at the offending offset in the file.
+pub(crate) struct DataReader<'a> {
+    block: &'a str,
+    data: &'a [u8],
+    position: usize,
+
+    /// Offset of `data` from the start of the file.
+    base_offset: usize,
+}
+
+impl<'a> DataReader<'a> {
+    pub(crate) fn new(block: &'a str, data: &'a [u8], base_offset: usize) -> Self {
+        Self {
+            block,
+            data,
+            position: 0,
+            base_offset,
+        }
+    }
+
+    pub(crate) fn block(&self) -> &str {
+        self.block
+    }
+
+    /// Offset of the next value to be read from the start of the file.
+    pub(crate) fn offset(&self) -> usize {
+        self.base_offset + self.position
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub(crate) fn seek(&mut self, position: usize) -> Result<()> {
+        if position > self.data.len() {
+            return Err(Error::TruncatedData {
+                offset: self.base_offset + self.data.len(),
+                block: self.block.to_string(),
+            });
+        }
+        self.position = position;
+        Ok(())
+    }
+
+    pub(crate) fn error(&self, reason: impl Into<String>) -> Error {
+        self.error_at(self.offset(), reason)
+    }
+
+    pub(crate) fn error_at(&self, offset: usize, reason: impl Into<String>) -> Error {
+        Error::invalid_data(offset, self.block, reason)
+    }
+
+    pub(crate) fn enum_error_at(&self, offset: usize, error: InvalidEnumValue) -> Error {
+        Error::InvalidEnumValue {
+            offset,
+            block: self.block.to_string(),
+            name: error.name,
+            value: error.value,
+        }
+    }
+
+    pub(crate) fn read_sized(&mut self, size: usize) -> Result<&'a [u8]> {
+        if size > self.remaining() {
+            return Err(Error::TruncatedData {
+                offset: self.offset(),
+                block: self.block.to_string(),
+            });
+        }
+        let bytes = &self.data[self.position..self.position + size];
+        self.position += size;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_sized(1)?[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_sized(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.read_sized(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_sized(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.read_sized(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_u16_be(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.read_sized(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_u32_be(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.read_sized(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_string(&mut self, size: usize) -> Result<String> {
+        Ok(String::from_utf8_lossy(self.read_sized(size)?).to_string())
+    }
+
+    pub(crate) fn read_u16_as<T>(&mut self) -> Result<T>
+    where
+        T: TryFrom<u16, Error = InvalidEnumValue>,
+    {
+        let offset = self.offset();
+        T::try_from(self.read_u16()?).map_err(|e| self.enum_error_at(offset, e))
+    }
+
+    pub(crate) fn read_u32_as<T>(&mut self) -> Result<T>
+    where
+        T: TryFrom<u32, Error = InvalidEnumValue>,
+    {
+        let offset = self.offset();
+        T::try_from(self.read_u32()?).map_err(|e| self.enum_error_at(offset, e))
+    }
+
+    /// Reads a value and fails if it does not match `expected`.
+    pub(crate) fn expect_u16(&mut self, expected: u16) -> Result<()> {
+        let offset = self.offset();
+        let value = self.read_u16()?;
+        if value != expected {
+            return Err(self.error_at(
+                offset,
+                format!("expected {:#06x}, found {:#06x}", expected, value),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads a value and fails if it does not match `expected`.
+    pub(crate) fn expect_u32(&mut self, expected: u32) -> Result<()> {
+        let offset = self.offset();
+        let value = self.read_u32()?;
+        if value != expected {
+            return Err(self.error_at(
+                offset,
+                format!("expected {:#010x}, found {:#010x}", expected, value),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Iterates over the lines of a text file along with their offsets, without
+/// their line endings.
+pub(crate) struct LineReader<'a> {
+    block: &'a str,
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> LineReader<'a> {
+    pub(crate) fn new(block: &'a str, data: &'a [u8]) -> Self {
+        Self {
+            block,
+            data,
+            offset: 0,
+        }
+    }
+
+    pub(crate) fn next_line(&mut self) -> Result<(usize, &'a str)> {
+        self.next().ok_or(Error::TruncatedData {
+            offset: self.offset,
+            block: self.block.to_string(),
+        })
+    }
+}
+
+impl<'a> Iterator for LineReader<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = &self.data[self.offset..];
+        if remaining.is_empty() {
+            return None;
+        }
+        let offset = self.offset;
+        let length = remaining
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(remaining.len(), |i| i + 1);
+        self.offset += length;
+        let line = std::str::from_utf8(&remaining[..length]).unwrap_or("\u{FFFD}");
+        Some((offset, line.trim_end_matches(['\r', '\n'])))
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    error::{Error, Result},
+    format::FileFormat,
+    palette::{Color, Palette, PaletteFormat},
+    read_write_ext::{DataReader, WriteExt},
+};
+
+/// A Microsoft RIFF palette. Chunks other than the `data` chunk are skipped.
+pub struct RiffPal {
+    palette: Palette,
+}
+
+impl RiffPal {
+    pub fn from_palette(palette: Palette) -> Self {
+        RiffPal { palette }
+    }
+
+    pub fn to_palette(self) -> Palette {
+        self.palette
+    }
+}
+
+impl PaletteFormat for RiffPal {
+    fn from_palette(palette: Palette) -> Self {
+        Self::from_palette(palette)
+    }
+
+    fn to_palette(self) -> Palette {
+        self.to_palette()
+    }
+}
+
+impl FileFormat for RiffPal {
+    fn extension() -> String {
+        "pal".to_string()
+    }
+
+    fn read_from_data(data: &[u8]) -> Result<Self> {
+        let mut reader = DataReader::new(BLOCK, data, 0);
+        for expected in ["RIFF", "PAL "] {
+            let offset = reader.offset();
+            let magic = reader.read_string(4)?;
+            if magic != expected {
+                return Err(Error::BadMagic {
+                    offset,
+                    block: BLOCK.to_string(),
+                    expected: expected.to_string(),
+                    found: magic,
+                });
+            }
+            if expected == "RIFF" {
+                reader.read_u32()?;
+            }
+        }
+
+        loop {
+            let id = reader.read_string(4)?;
+            let size = usize::try_from(reader.read_u32()?).unwrap();
+            if id != "data" {
+                // Chunks are padded to an even size.
+                reader.seek(reader.position() + size + size % 2)?;
+                continue;
+            }
+
+            reader.expect_u16(0x0300)?;
+            let num_colors = reader.read_u16()?;
+            let mut colors = vec![];
+            for _ in 0..num_colors {
+                let [red, green, blue, _flags] = reader.read_sized(4)?.try_into().unwrap();
+                colors.push(Color::new(red, green, blue));
+            }
+            return Ok(RiffPal {
+                palette: Palette::new(colors),
+            });
+        }
+    }
+
+    fn write_to_data(&self) -> Result<Vec<u8>> {
+        let num_colors = u16::try_from(self.palette.colors().len()).map_err(|_| {
+            Error::invalid_input(format!(
+                "palette has {} colors, but at most {} are supported",
+                self.palette.colors().len(),
+                u16::MAX
+            ))
+        })?;
+        let data_size = 4 + 4 * u32::from(num_colors);
+
+        let mut data = vec![];
+        data.write_string("RIFF")?;
+        data.write_u32(4 + 8 + data_size)?;
+        data.write_string("PAL ")?;
+        data.write_string("data")?;
+        data.write_u32(data_size)?;
+        data.write_u16(0x0300)?;
+        data.write_u16(num_colors)?;
+        for color in self.palette.colors() {
+            data.write_u8(color.red)?;
+            data.write_u8(color.green)?;
+            data.write_u8(color.blue)?;
+            data.write_u8(0)?;
+        }
+
+        Ok(data)
+    }
+}
+
+/// Name used to refer to the file in errors, in place of a block name.
+const BLOCK: &str = "RIFF";
@@ -0,0 +1,443 @@
+mod common;
+
+use std::{
+    fs::{create_dir_all, read, read_dir, remove_file, write},
+    process::Command,
+};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::{assert_same_hash, file_has_extension};
+use nitrogfx::{Color, FileFormat, Image, Ncgr, Palette, Png, TruecolorImage};
+
+#[test]
+fn ncer2json_json2ncer() {
+    let file_ext = "ncer";
+    let intermediate_ext = "json";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/cli_{}", file_stem);
+            let intermediate_path = &format!("{}.{}", temp_file_stem, intermediate_ext);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let status = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+                .args(["ncer2json".as_ref(), original_file_path.as_os_str()])
+                .arg(intermediate_path)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            let status = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+                .args(["json2ncer", intermediate_path, created_file_path])
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(intermediate_path).unwrap();
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn unknown_command() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+        .args(["foo2bar", "input", "output"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn pret_unsupported_option() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+        .args(["input.png", "output.NCGR", "-handleempty"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("-handleempty is not supported"));
+}
+
+#[test]
+fn pret_pal_to_ncpr() {
+    create_dir_all("tests/assets/temp/").unwrap();
+    let input_path = "tests/assets/temp/cli_pret.pal";
+    let output_path = "tests/assets/temp/cli_pret.NCLR";
+    write(
+        input_path,
+        "JASC-PAL\r\n0100\r\n2\r\n255 0 0\r\n0 255 0\r\n",
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+        .args([input_path, output_path, "-bitdepth", "4", "-ncpr"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // What pret's nitrogfx writes for the same arguments: the palette is
+    // padded to 16 colors.
+    let mut expected = vec![];
+    expected.extend_from_slice(b"RPCN\xFE\xFF\x00\x01\x48\x00\x00\x00\x10\x00\x01\x00");
+    expected.extend_from_slice(b"TTLP\x38\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00");
+    expected.extend_from_slice(b"\x20\x00\x00\x00\x10\x00\x00\x00\x1F\x00\xE0\x03");
+    expected.extend_from_slice(&[0; 28]);
+    assert_eq!(read(output_path).unwrap(), expected);
+
+    remove_file(input_path).unwrap();
+    remove_file(output_path).unwrap();
+}
+
+/// Runs the arguments of pret's `nitrogfx` on `input`, written to a file with
+/// the given extension, and returns the output, if it succeeds.
+fn run_pret(name: &str, input: (&str, &[u8]), output_ext: &str, args: &[&str]) -> Option<Vec<u8>> {
+    create_dir_all("tests/assets/temp/").unwrap();
+    let input_path = format!("tests/assets/temp/cli_pret_{}.{}", name, input.0);
+    let output_path = format!("tests/assets/temp/cli_pret_{}.{}", name, output_ext);
+    write(&input_path, input.1).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+        .args([&input_path, &output_path])
+        .args(args)
+        .status()
+        .unwrap();
+    let output = status.success().then(|| read(&output_path).unwrap());
+
+    remove_file(&input_path).unwrap();
+    if output.is_some() {
+        remove_file(&output_path).unwrap();
+    }
+    output
+}
+
+/// Creates an NTR file with the given version and blocks, as pret does.
+fn ntr_file(id: &[u8], version: u16, blocks: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+    let file_size = 16 + blocks.iter().map(|(_, b)| b.len() + 8).sum::<usize>();
+    let mut data = id.to_vec();
+    data.extend_from_slice(&[0xFF, 0xFE]);
+    data.extend_from_slice(&version.to_le_bytes());
+    data.extend_from_slice(&(file_size as u32).to_le_bytes());
+    data.extend_from_slice(&[0x10, 0x00]);
+    data.extend_from_slice(&(blocks.len() as u16).to_le_bytes());
+    for (id, contents) in blocks {
+        data.extend_from_slice(id);
+        data.extend_from_slice(&(contents.len() as u32 + 8).to_le_bytes());
+        data.extend_from_slice(contents);
+    }
+    data
+}
+
+/// Creates the RAHC block of an NCGR with the given dimensions in tiles.
+fn rahc_block(
+    (width, height): (u16, u16),
+    bit_depth: u32,
+    mapping_type: u32,
+    scanned: u32,
+    data: &[u8],
+) -> (&'static [u8], Vec<u8>) {
+    let mut rahc = vec![];
+    rahc.extend_from_slice(&height.to_le_bytes());
+    rahc.extend_from_slice(&width.to_le_bytes());
+    for value in [bit_depth, mapping_type, scanned, data.len() as u32, 0x18] {
+        rahc.extend_from_slice(&value.to_le_bytes());
+    }
+    rahc.extend_from_slice(data);
+    (b"RAHC", rahc)
+}
+
+/// Creates 4bpp tiles whose pixels all have the given colors.
+fn tiles_4bpp(colors: &[u8]) -> Vec<u8> {
+    colors.iter().flat_map(|color| [color * 0x11; 32]).collect()
+}
+
+#[test]
+fn pret_png_to_ncgr() {
+    // An image of 4x2 tiles, where the pixels of each tile have its index + 1
+    // as color.
+    let pixels = (0..32 * 16)
+        .map(|i| ((i / 256) * 4 + (i % 32) / 8 + 1) as u8)
+        .collect::<Vec<_>>();
+    let palette = Palette::new((0..16).map(|i| Color::new(i * 8, 0, 0)).collect());
+    let png = Png::from_image(Image::new(32, &pixels, Some(palette)))
+        .write_to_data()
+        .unwrap();
+    let png_to_ncgr = |name, args: &[&str]| run_pret(name, ("png", &png), "NCGR", args);
+
+    let tiles = tiles_4bpp(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    let expected = |blocks: &[(&[u8], Vec<u8>)]| ntr_file(b"RGCN", 0x0100, blocks);
+    assert_eq!(
+        png_to_ncgr("default", &[]).unwrap(),
+        expected(&[rahc_block((4, 2), 3, 0, 0, &tiles)])
+    );
+    assert_eq!(
+        png_to_ncgr("bitdepth", &["-bitdepth", "8"]).unwrap(),
+        expected(&[rahc_block(
+            (4, 2),
+            4,
+            0,
+            0,
+            &(1..=8).flat_map(|color| [color; 64]).collect::<Vec<_>>()
+        )])
+    );
+    assert_eq!(
+        png_to_ncgr("version101", &["-version101"]).unwrap(),
+        ntr_file(b"RGCN", 0x0101, &[rahc_block((4, 2), 3, 0, 0, &tiles)])
+    );
+    assert_eq!(
+        png_to_ncgr("sopc", &["-sopc"]).unwrap(),
+        expected(&[
+            rahc_block((4, 2), 3, 0, 0, &tiles),
+            (b"SOPC", vec![0, 0, 0, 0, 4, 0, 2, 0])
+        ])
+    );
+    // Scanned images are stored row by row rather than tile by tile.
+    let scanned = [[0x11, 0x22, 0x33, 0x44], [0x55, 0x66, 0x77, 0x88]]
+        .iter()
+        .flat_map(|row| row.map(|color| [color; 4]).concat().repeat(8))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        png_to_ncgr("scanned", &["-scanned"]).unwrap(),
+        expected(&[rahc_block((4, 2), 3, 0, 1, &scanned)])
+    );
+
+    // pret stores the dimensions with 1D mapping unless -clobbersize is given.
+    assert_eq!(
+        png_to_ncgr("mappingtype", &["-mappingtype", "64"]).unwrap(),
+        expected(&[rahc_block((4, 2), 3, 0x100010, 0, &tiles)])
+    );
+    assert_eq!(
+        png_to_ncgr("clobbersize", &["-mappingtype", "32", "-clobbersize"]).unwrap(),
+        expected(&[rahc_block((0xFFFF, 0xFFFF), 3, 0x10, 0, &tiles)])
+    );
+    assert_eq!(png_to_ncgr("clobbersize_2d", &["-clobbersize"]), None);
+
+    assert_eq!(
+        png_to_ncgr("num_tiles", &["-mappingtype", "32", "-num_tiles", "3"]).unwrap(),
+        expected(&[rahc_block((4, 2), 3, 0x10, 0, &tiles_4bpp(&[1, 2, 3]))])
+    );
+    assert_eq!(
+        png_to_ncgr("metatiles", &["-mwidth", "2", "-mheight", "2"]).unwrap(),
+        expected(&[rahc_block(
+            (4, 2),
+            3,
+            0,
+            0,
+            &tiles_4bpp(&[1, 2, 5, 6, 3, 4, 7, 8])
+        )])
+    );
+    assert_eq!(png_to_ncgr("metatiles_uneven", &["-mwidth", "3"]), None);
+
+    let mut expected = expected(&[rahc_block((4, 2), 3, 0, 0, &tiles)]);
+    expected[4..6].copy_from_slice(&[0xFE, 0xFF]);
+    expected[8] -= 8;
+    assert_eq!(
+        png_to_ncgr("nobyteorder", &["-nobyteorder", "-wrongsize"]).unwrap(),
+        expected
+    );
+}
+
+/// Creates the TTLP block of an NCLR with the given colors.
+fn ttlp_block(bit_depth: u16, size: u32, colors: &[u16]) -> (&'static [u8], Vec<u8>) {
+    let mut ttlp = vec![];
+    ttlp.extend_from_slice(&bit_depth.to_le_bytes());
+    ttlp.extend_from_slice(&[0; 6]);
+    ttlp.extend_from_slice(&size.to_le_bytes());
+    ttlp.extend_from_slice(&0x10_u32.to_le_bytes());
+    colors
+        .iter()
+        .for_each(|color| ttlp.extend_from_slice(&color.to_le_bytes()));
+    (b"TTLP", ttlp)
+}
+
+#[test]
+fn pret_pal_to_nclr() {
+    let pal = b"JASC-PAL\r\n0100\r\n2\r\n248 0 0\r\n0 248 0\r\n";
+    let pal_to_nclr = |name, args: &[&str]| run_pret(name, ("pal", pal), "NCLR", args);
+
+    let mut colors = vec![0x001F, 0x03E0];
+    colors.resize(16, 0);
+    let expected = |blocks: &[(&[u8], Vec<u8>)]| ntr_file(b"RLCN", 0x0100, blocks);
+    assert_eq!(
+        pal_to_nclr("default", &[]).unwrap(),
+        expected(&[ttlp_block(3, 0x20, &colors)])
+    );
+    assert_eq!(
+        pal_to_nclr("nopad", &["-nopad"]).unwrap(),
+        expected(&[ttlp_block(3, 4, &colors[..2])])
+    );
+    assert_eq!(
+        pal_to_nclr("ir", &["-ir"]).unwrap(),
+        expected(&[ttlp_block(3, 0x1E0, &colors)])
+    );
+    assert_eq!(
+        pal_to_nclr("pcmp", &["-pcmp"]).unwrap(),
+        expected(&[
+            ttlp_block(3, 0x20, &colors),
+            (b"PMCP", vec![1, 0, 0xEF, 0xBE, 8, 0, 0, 0, 0, 0])
+        ])
+    );
+
+    let mut extended_colors = colors.clone();
+    extended_colors.resize(256, 0);
+    assert_eq!(
+        pal_to_nclr("bitdepth", &["-bitdepth", "8", "-version101"]).unwrap(),
+        ntr_file(b"RLCN", 0x0101, &[ttlp_block(4, 0x200, &extended_colors)])
+    );
+
+    let mut expected = expected(&[ttlp_block(3, 0x20, &colors)]);
+    expected[4..6].copy_from_slice(&[0xFE, 0xFF]);
+    assert_eq!(
+        pal_to_nclr("nobyteorder", &["-nobyteorder"]).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn pret_json_round_trip() {
+    let json_round_trip = |name, json: &str, ext| {
+        let data = run_pret(name, ("json", json.as_bytes()), ext, &[]).unwrap();
+        let output = run_pret(name, (ext, &data), "json", &[]).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&output).unwrap(),
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+    };
+
+    let oam = |y, rotation, size_disable, x, rotation_scaling| {
+        format!(
+            r#"{{
+                "Attr0": {{"YCoordinate": {}, "Rotation": {}, "SizeDisable": {}, "Mode": 0,
+                    "Mosaic": false, "Colours": 16, "Shape": 0}},
+                "Attr1": {{"XCoordinate": {}, "RotationScaling": {}, "Size": 1}},
+                "Attr2": {{"CharName": 4, "Priority": 2, "Palette": 1}}
+            }}"#,
+            y, rotation, size_disable, x, rotation_scaling
+        )
+    };
+    let ncer = format!(
+        r#"{{
+            "labelEnabled": true,
+            "extended": true,
+            "vramTransferEnabled": true,
+            "cellCount": 1,
+            "mappingType": 0,
+            "cells": [{{
+                "readOnly": 2048,
+                "maxX": 8, "maxY": 8, "minX": -8, "minY": -8,
+                "oamCount": 2,
+                "OAM0": {},
+                "OAM1": {}
+            }}],
+            "vramTransferMaxSize": 512,
+            "transferData": [{{"offset": 0, "size": 256}}],
+            "labelCount": 1,
+            "labels": ["CellAnime0"]
+        }}"#,
+        oam(248, false, false, 504, 29),
+        oam(8, true, true, 16, 3)
+    );
+    json_round_trip("ncer", &ncer, "NCER");
+
+    let nanr = r#"{
+        "labelEnabled": true,
+        "sequenceCount": 1,
+        "frameCount": 2,
+        "sequences": [{
+            "frameCount": 2,
+            "loopStartFrame": 1,
+            "animationElement": 2,
+            "animationType": 1,
+            "playbackMode": 2,
+            "frameData": [{"frameDelay": 4, "resultId": 0}, {"frameDelay": 6, "resultId": 1}]
+        }],
+        "resultCount": 2,
+        "animationResults": [
+            {"resultType": 2, "index": 0, "positionX": 0, "positionY": 0},
+            {"resultType": 2, "index": 1, "positionX": -4, "positionY": 8}
+        ],
+        "labelCount": 1,
+        "labels": ["NANR_0"]
+    }"#;
+    json_round_trip("nanr", nanr, "NANR");
+}
+
+#[test]
+fn pal_to_nclr_to_pal_color_scaling() {
+    create_dir_all("tests/assets/temp/").unwrap();
+    let input_path = "tests/assets/temp/cli_scaling.pal";
+    let nclr_path = "tests/assets/temp/cli_scaling.NCLR";
+    let output_path = "tests/assets/temp/cli_scaling_out.pal";
+    write(
+        input_path,
+        "JASC-PAL\r\n0100\r\n2\r\n255 255 255\r\n248 248 248\r\n",
+    )
+    .unwrap();
+
+    // Colors are scaled to the full range by default, which cannot represent
+    // the second color exactly.
+    let output = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+        .args(["pal2nclr", input_path, nclr_path])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("warning: colors [1] cannot be represented exactly"));
+
+    let nclr_to_pal = |args: &[&str]| {
+        let status = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+            .args(["nclr2pal", nclr_path, output_path])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        String::from_utf8(read(output_path).unwrap()).unwrap()
+    };
+    assert!(nclr_to_pal(&[]).ends_with("255 255 255\r\n247 247 247\r\n"));
+    assert!(nclr_to_pal(&["--color-scaling", "x8"]).ends_with("248 248 248\r\n240 240 240\r\n"));
+
+    remove_file(input_path).unwrap();
+    remove_file(nclr_path).unwrap();
+    remove_file(output_path).unwrap();
+}
+
+#[test]
+fn png2ncgr_transparent_index() {
+    create_dir_all("tests/assets/temp/").unwrap();
+    let input_path = "tests/assets/temp/cli_transparent_index.png";
+    let output_path = "tests/assets/temp/cli_transparent_index.NCGR";
+    let mut colors = vec![Color::new(248, 0, 0); 64];
+    colors[0] = colors[0].with_alpha(0);
+    Png::from_truecolor_image(TruecolorImage::new(8, colors))
+        .write_to_file(input_path)
+        .unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_nitrogfx"))
+        .args(["png2ncgr", input_path, output_path])
+        .args(["--transparent-index", "2", "--dither", "bayer"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let mut expected = [0; 64];
+    expected[0] = 2;
+    assert_eq!(
+        Ncgr::read_from_file(output_path)
+            .unwrap()
+            .to_image()
+            .pixels(),
+        expected
+    );
+
+    remove_file(input_path).unwrap();
+    remove_file(output_path).unwrap();
+}
@@ -0,0 +1,157 @@
+use nitrogfx::{
+    Color, ColorScaling, Error, FileFormat, Nclr, NclrMetadata, Palette, RawPalette,
+    RawPaletteOptions,
+};
+
+/// A table that spans the full 8-bit range, but differs from
+/// [`ColorScaling::FullRange`].
+fn table() -> [u8; 32] {
+    std::array::from_fn(|x| (x * 255 / 31) as u8)
+}
+
+fn scalings() -> [ColorScaling; 3] {
+    [
+        ColorScaling::Multiply8,
+        ColorScaling::FullRange,
+        ColorScaling::table(table()).unwrap(),
+    ]
+}
+
+#[test]
+fn expand_and_reduce() {
+    for scaling in scalings() {
+        let expanded = (0..32).map(|x| scaling.expand(x)).collect::<Vec<_>>();
+        for x in 0..32 {
+            assert_eq!(
+                scaling.reduce(expanded[usize::from(x)]),
+                (x, true),
+                "{:?}",
+                scaling
+            );
+        }
+        // Channels in between are reduced to the 5-bit value below them.
+        for value in 0..=u8::MAX {
+            if expanded.contains(&value) {
+                continue;
+            }
+            let (x, exact) = scaling.reduce(value);
+            assert!(!exact, "{:?} {}", scaling, value);
+            assert!(scaling.expand(x) < value, "{:?} {}", scaling, value);
+            assert!(
+                x == 31 || scaling.expand(x + 1) > value,
+                "{:?} {}",
+                scaling,
+                value
+            );
+        }
+    }
+
+    assert_eq!(ColorScaling::default(), ColorScaling::Multiply8);
+    assert_eq!(ColorScaling::Multiply8.expand(31), 248);
+    assert_eq!(ColorScaling::Multiply8.reduce(255), (31, false));
+    assert_eq!(ColorScaling::FullRange.expand(31), 255);
+    assert_eq!(ColorScaling::FullRange.expand(16), 132);
+    assert_eq!(ColorScaling::FullRange.reduce(128), (15, false));
+}
+
+#[test]
+fn table_with_duplicates() {
+    let mut duplicates = table();
+    duplicates[2] = duplicates[1];
+    let error = ColorScaling::table(duplicates).err().unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+
+    // The two 5-bit values cannot be told apart, so neither is exact.
+    let scaling = ColorScaling::Table(duplicates);
+    assert_eq!(scaling.reduce(duplicates[1]), (1, false));
+    let palette = Palette::new(vec![
+        Color::from_bgr555(1, scaling),
+        Color::from_bgr555(2, scaling),
+        Color::from_bgr555(3, scaling),
+    ]);
+    assert_eq!(palette.lossy_colors(scaling), vec![0, 1]);
+}
+
+#[test]
+fn bgr555_round_trip() {
+    for scaling in scalings() {
+        for value in 0..=u16::MAX {
+            let color = Color::from_bgr555(value, scaling);
+            assert_eq!(color.high_bit, value >> 15 != 0);
+            assert!(color.is_exact(scaling));
+            assert_eq!(color.to_bgr555(scaling), value, "{:?}", scaling);
+        }
+    }
+    assert_eq!(Color::from(0x7FFF), Color::new(248, 248, 248));
+    assert_eq!(
+        Color::from_bgr555(0x7FFF, ColorScaling::FullRange),
+        Color::new(255, 255, 255)
+    );
+}
+
+#[test]
+fn nclr_high_bit_round_trip() {
+    let values = [0x0000, 0x801F, 0x03E0, 0xFC00, 0x7FFF, 0xFFFF];
+    for scaling in scalings() {
+        let palette = Palette::new(
+            values
+                .iter()
+                .map(|value| Color::from_bgr555(*value, scaling))
+                .collect(),
+        );
+        let metadata = NclrMetadata::default().with_color_scaling(scaling);
+        let data = Nclr::try_from_palette(palette.clone(), metadata)
+            .unwrap()
+            .write_to_data()
+            .unwrap();
+        let nclr = Nclr::read_from_data(&data).unwrap();
+        assert_eq!(
+            nclr.to_palette_with_scaling(scaling).colors(),
+            palette.colors()
+        );
+
+        let raw = RawPalette::try_from_palette_with_options(
+            palette.clone(),
+            RawPaletteOptions::default().with_color_scaling(scaling),
+        )
+        .unwrap();
+        let raw_data = raw.write_to_data().unwrap();
+        let expected = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(raw_data, expected);
+    }
+}
+
+#[test]
+fn lossy_colors() {
+    let palette = Palette::new(vec![
+        Color::new(248, 248, 248),
+        Color::new(255, 255, 255),
+        Color::new(8, 16, 20),
+    ]);
+    assert_eq!(palette.lossy_colors(ColorScaling::Multiply8), vec![1, 2]);
+    assert_eq!(palette.lossy_colors(ColorScaling::FullRange), vec![0, 2]);
+
+    let metadata = NclrMetadata::default();
+    let error = Nclr::try_from_palette(palette.clone(), metadata.clone())
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+    // Without checking, the colors are reduced.
+    let nclr = Nclr::from_palette(palette.clone(), metadata);
+    assert_eq!(
+        nclr.to_palette().colors(),
+        [
+            Color::new(248, 248, 248),
+            Color::new(248, 248, 248),
+            Color::new(8, 16, 16),
+        ]
+    );
+
+    let error = RawPalette::try_from_palette_with_options(palette, RawPaletteOptions::default())
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+}
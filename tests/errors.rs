@@ -0,0 +1,159 @@
+use nitrogfx::{
+    Error, FileFormat, Image, Jasc, Ncgr, NcgrMetadata, Nclr, NtrTextureFormat, OamSize,
+};
+
+/// Builds an NTR file with the given id and blocks.
+fn ntr_file(id: &[u8; 4], version: u16, blocks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let size = 16 + blocks.iter().map(|(_, c)| c.len() + 8).sum::<usize>();
+    let mut data = vec![];
+    data.extend_from_slice(id);
+    data.extend_from_slice(&0xFEFFu16.to_le_bytes());
+    data.extend_from_slice(&version.to_le_bytes());
+    data.extend_from_slice(&(size as u32).to_le_bytes());
+    data.extend_from_slice(&16u16.to_le_bytes());
+    data.extend_from_slice(&(blocks.len() as u16).to_le_bytes());
+    for (block_id, contents) in blocks {
+        data.extend_from_slice(*block_id);
+        data.extend_from_slice(&(contents.len() as u32 + 8).to_le_bytes());
+        data.extend_from_slice(contents);
+    }
+    data
+}
+
+#[test]
+fn truncated_header() {
+    let error = Nclr::read_from_data(b"RLCN\xFF\xFE").err().unwrap();
+    assert!(
+        matches!(&error, Error::TruncatedData { offset: 6, block } if block == "RLCN"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn truncated_block() {
+    // The PLTT block ends right after the texture format.
+    let data = ntr_file(b"RLCN", 0x0100, &[(b"TTLP", &[3, 0])]);
+    let error = Nclr::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(&error, Error::TruncatedData { offset: 0x1A, block } if block == "TTLP"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn bad_magic() {
+    let data = ntr_file(b"RGCN", 0x0100, &[]);
+    let error = Nclr::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(
+            &error,
+            Error::BadMagic { offset: 0, block, expected, found }
+                if block == "header" && expected == "RLCN" && found == "RGCN"
+        ),
+        "{:?}",
+        error
+    );
+
+    let error = Jasc::read_from_data(b"GIMP Palette\n").err().unwrap();
+    assert!(
+        matches!(&error, Error::BadMagic { offset: 0, found, .. } if found == "GIMP Palette"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn unexpected_block_id() {
+    let data = ntr_file(b"RLCN", 0x0100, &[(b"PMCP", &[0; 8])]);
+    let error = Nclr::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(
+            &error,
+            Error::UnexpectedBlockId { offset: 0x10, block, expected }
+                if block == "PMCP" && expected == "TTLP"
+        ),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn bad_enum() {
+    let data = ntr_file(b"RLCN", 0x0200, &[]);
+    let error = Nclr::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(
+            &error,
+            Error::InvalidEnumValue { offset: 6, block, name: "NtrFileVersion", value: 0x200 }
+                if block == "RLCN"
+        ),
+        "{:?}",
+        error
+    );
+
+    let data = ntr_file(b"RLCN", 0x0100, &[(b"TTLP", &[9, 0, 0, 0])]);
+    let error = Nclr::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(
+            &error,
+            Error::InvalidEnumValue { offset: 0x18, block, name: "NtrTextureFormat", value: 9 }
+                if block == "TTLP"
+        ),
+        "{:?}",
+        error
+    );
+
+    // Shape 3 is invalid with any size.
+    let error = OamSize::try_from((3, 2)).err().unwrap();
+    assert_eq!((error.name, error.value), ("OamSize (shape, size)", 0x0302));
+}
+
+#[test]
+fn unsupported_texture_format() {
+    let data = ntr_file(b"RLCN", 0x0100, &[(b"TTLP", &[1, 0, 0, 0])]);
+    let error = Nclr::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(
+            &error,
+            Error::UnsupportedTextureFormat { offset: 0x18, block, value: 1 } if block == "TTLP"
+        ),
+        "{:?}",
+        error
+    );
+
+    let metadata = NcgrMetadata::default().with_texture_format(NtrTextureFormat::A3i5);
+    let ncgr = Ncgr::from_image(Image::new(8, &[0; 64], None), metadata);
+    let error = ncgr.write_to_data().err().unwrap();
+    assert!(
+        matches!(
+            &error,
+            Error::UnsupportedTextureFormat { offset: 0x1C, block, value: 1 } if block == "RAHC"
+        ),
+        "{:?}",
+        error
+    );
+    let error = ncgr.cipher(0x1234).err().unwrap();
+    assert!(
+        matches!(&error, Error::UnsupportedTextureFormat { value: 1, .. }),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn invalid_data() {
+    // 2D mapping with the dimensions left at 0xFFFF.
+    let mut char_block = vec![0; 0x18];
+    char_block[0x00..0x04].copy_from_slice(&[0xFF; 4]);
+    char_block[0x04] = 3;
+    char_block[0x14] = 0x18;
+    let data = ntr_file(b"RGCN", 0x0100, &[(b"RAHC", &char_block)]);
+    let error = Ncgr::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(&error, Error::InvalidData { offset: 0x18, block, .. } if block == "RAHC"),
+        "{:?}",
+        error
+    );
+}
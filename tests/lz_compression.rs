@@ -0,0 +1,126 @@
+mod common;
+
+use std::fs::{create_dir_all, read, read_dir, remove_file, write};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::{assert_same_hash, file_has_extension};
+use nitrogfx::{Compression, Error};
+
+#[test]
+fn lz_to_lz() {
+    let file_ext = "lz";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}", file_stem);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext);
+            let original_data = read(&original_file_path).unwrap();
+            let compression = Compression::detect(&original_data).unwrap();
+            let decompressed_data = Compression::decompress(&original_data).unwrap();
+            write(
+                created_file_path,
+                compression.compress(&decompressed_data).unwrap(),
+            )
+            .unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+/// Pseudo-random bytes from a linear congruential generator.
+fn random_data(length: usize) -> Vec<u8> {
+    let mut state = 0x12345678u32;
+    (0..length)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+fn assert_round_trip(compression: Compression, data: &[u8]) {
+    let compressed = compression.compress(data).unwrap();
+    assert_eq!(Compression::detect(&compressed), Some(compression));
+    assert_eq!(compressed.len() % 4, 0);
+    assert_eq!(Compression::decompress(&compressed).unwrap(), data);
+}
+
+#[test]
+fn compress_to_decompress() {
+    let mut runs = vec![];
+    for (i, length) in [1, 2, 3, 0x12, 0x13, 0x110, 0x111, 0x10110, 0x10111]
+        .into_iter()
+        .enumerate()
+    {
+        runs.extend(std::iter::repeat_n(i as u8, length));
+    }
+    let mut repeated = random_data(0x100);
+    repeated.extend_from_within(..);
+    repeated.extend_from_within(0x80..0x180);
+
+    for compression in [Compression::Lz10, Compression::Lz11] {
+        for data in [
+            vec![],
+            vec![0x42],
+            runs.clone(),
+            random_data(0x2000),
+            repeated.clone(),
+        ] {
+            assert_round_trip(compression, &data);
+        }
+    }
+}
+
+#[test]
+fn compress_empty() {
+    assert_eq!(
+        Compression::Lz10.compress(&[]).unwrap(),
+        [0x10, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(
+        Compression::Lz11.compress(&[]).unwrap(),
+        [0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn compress_large() {
+    let data = vec![0; 0x1000000];
+
+    let error = Compression::Lz10.compress(&data).err().unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+
+    let compressed = Compression::Lz11.compress(&data).unwrap();
+    assert_eq!(
+        compressed[..8],
+        [0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]
+    );
+    assert_eq!(Compression::decompress(&compressed).unwrap(), data);
+}
+
+#[test]
+fn decompress_with_wrong_size() {
+    // The header claims 0xFFFFFF bytes but the data ends after one literal.
+    let error = Compression::decompress(&[0x10, 0xFF, 0xFF, 0xFF, 0x00, 0x42])
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&error, Error::TruncatedData { offset: 6, block } if block == "LZ10"),
+        "{:?}",
+        error
+    );
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::{assert_same_hash, file_has_extension};
+use nitrogfx::{Error, FileFormat, Nanr};
+
+#[test]
+fn nanr_to_json_to_nanr() {
+    let file_ext = "nanr";
+    let intermediate_ext = "json";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}", file_stem);
+            let intermediate_path = &format!("{}.{}", temp_file_stem, intermediate_ext);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let original_file = Nanr::read_from_file(&original_file_path).unwrap();
+            std::fs::write(intermediate_path, original_file.to_json().unwrap()).unwrap();
+
+            let created_file =
+                Nanr::from_json(&read_to_string(intermediate_path).unwrap()).unwrap();
+            created_file.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(intermediate_path).unwrap();
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+const NANR_JSON: &str = r#"{
+  "version": "Version0100",
+  "sequences": [
+    {
+      "loop_start_frame": 0,
+      "animation_element": "Index",
+      "animation_type": "Cell",
+      "playback_mode": "ForwardLoop",
+      "frames": [
+        { "result_id": 0, "frame_delay": 4 },
+        { "result_id": 1, "frame_delay": 4 },
+        { "result_id": 0, "frame_delay": 8 }
+      ]
+    },
+    {
+      "loop_start_frame": 1,
+      "animation_element": "IndexT",
+      "animation_type": "Cell",
+      "playback_mode": "Forward",
+      "frames": [
+        { "result_id": 2, "frame_delay": 2 }
+      ]
+    }
+  ],
+  "results": [
+    { "Index": { "index": 0 } },
+    { "Index": { "index": 1 } },
+    { "IndexT": { "index": 2, "position_x": -8, "position_y": 16 } }
+  ],
+  "user_extended_data": [0, 0, 0, 0],
+  "labels": ["idle", "jump"],
+  "label_encoding": "Utf8",
+  "label_padding": []
+}"#;
+
+#[test]
+fn json_to_nanr_to_json() {
+    let data = Nanr::from_json(NANR_JSON).unwrap().write_to_data().unwrap();
+    assert_eq!(&data[..4], b"RNAN");
+
+    let json = Nanr::read_from_data(&data).unwrap().to_json().unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+        serde_json::from_str::<serde_json::Value>(NANR_JSON).unwrap()
+    );
+}
+
+#[test]
+fn json_to_nanr_with_invalid_result_id() {
+    let json = NANR_JSON.replace(r#""result_id": 2"#, r#""result_id": 3"#);
+    let error = Nanr::from_json(&json).unwrap().write_to_data().unwrap_err();
+    assert!(
+        matches!(&error, Error::InvalidInput { reason } if reason.contains("result 3")),
+        "{:?}",
+        error
+    );
+}
+
+/// Writes the NANR of the JSON, and checks that reading and writing it again
+/// gives the same bytes.
+fn assert_nanr_round_trip(json: &str) -> Nanr {
+    let data = Nanr::from_json(json).unwrap().write_to_data().unwrap();
+    let nanr = Nanr::read_from_data(&data).unwrap();
+    assert_eq!(nanr.write_to_data().unwrap(), data);
+    nanr
+}
+
+#[test]
+fn nanr_with_user_extended_data() {
+    let json = NANR_JSON.replace(
+        r#""user_extended_data": [0, 0, 0, 0]"#,
+        r#""user_extended_data": [1, 2, 3, 4, 5, 6, 7, 8]"#,
+    );
+    let nanr = assert_nanr_round_trip(&json);
+    assert!(nanr
+        .to_json()
+        .unwrap()
+        .contains("\"user_extended_data\": [\n    1,\n    2,"));
+
+    // JSON without UEXT data gets an empty UEXT block.
+    let json = NANR_JSON.replace(r#""user_extended_data": [0, 0, 0, 0],"#, "");
+    let data = Nanr::from_json(&json).unwrap().write_to_data().unwrap();
+    assert_eq!(
+        &data[data.len() - 12..],
+        b"TXEU\x0C\x00\x00\x00\x00\x00\x00\x00"
+    );
+}
+
+#[test]
+fn nanr_with_unreferenced_results() {
+    // The second result is not used by any frame, so it is read back as raw
+    // data at the same offset.
+    let json = NANR_JSON.replace(r#""result_id": 1"#, r#""result_id": 0"#);
+    let nanr = assert_nanr_round_trip(&json);
+    let value = serde_json::from_str::<serde_json::Value>(&nanr.to_json().unwrap()).unwrap();
+    assert_eq!(
+        value["results"],
+        serde_json::json!([
+            { "Index": { "index": 0 } },
+            { "Raw": { "data": [1, 0] } },
+            { "IndexT": { "index": 2, "position_x": -8, "position_y": 16 } },
+        ])
+    );
+    assert_eq!(value["sequences"][1]["frames"][0]["result_id"], 2);
+}
+
+#[test]
+fn nanr_with_raw_results() {
+    // Data that is not padding is kept, and results after it are not aligned.
+    let json = NANR_JSON.replace(
+        r#"{ "IndexT""#,
+        r#"{ "Raw": { "data": [170] } },
+    { "IndexT""#,
+    );
+    let json = json.replace(r#""result_id": 2"#, r#""result_id": 3"#);
+    let nanr = assert_nanr_round_trip(&json);
+    let value = serde_json::from_str::<serde_json::Value>(&nanr.to_json().unwrap()).unwrap();
+    assert_eq!(
+        value["results"][2],
+        serde_json::json!({ "Raw": { "data": [170] } })
+    );
+    assert_eq!(value["results"][3]["IndexT"]["index"], 2);
+
+    let json = NANR_JSON
+        .replace(r#""result_id": 1"#, r#""result_id": 2"#)
+        .replace(
+            r#"{ "Index": { "index": 1 } }"#,
+            r#"{ "Index": { "index": 1 } }, { "Raw": { "data": [] } }"#,
+        );
+    let error = Nanr::from_json(&json).unwrap().write_to_data().unwrap_err();
+    assert!(
+        matches!(&error, Error::InvalidInput { reason } if reason.contains("raw data")),
+        "{:?}",
+        error
+    );
+}
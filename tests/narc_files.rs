@@ -0,0 +1,106 @@
+mod common;
+
+use std::fs::{create_dir_all, read_dir, remove_file};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::{assert_same_hash, file_has_extension};
+use nitrogfx::{Error, FileFormat, Narc};
+
+#[test]
+fn narc_to_files_to_narc() {
+    let file_ext = "narc";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}", file_stem);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let original_file = Narc::read_from_file(&original_file_path).unwrap();
+            let rebuilt_file = if original_file.has_file_names() {
+                Narc::from_named_files(
+                    original_file
+                        .files()
+                        .iter()
+                        .map(|file| (file.name().unwrap().to_string(), file.data().to_vec()))
+                        .collect(),
+                )
+                .unwrap()
+            } else {
+                Narc::from_files(
+                    original_file
+                        .files()
+                        .iter()
+                        .map(|file| file.data().to_vec())
+                        .collect(),
+                )
+            };
+            rebuilt_file.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn named_files_to_narc_to_named_files() {
+    let files = vec![
+        ("a/b/c".to_string(), vec![1, 2, 3]),
+        ("d".to_string(), vec![4]),
+        ("a/e".to_string(), vec![5, 6, 7, 8, 9]),
+    ];
+    let data = Narc::from_named_files(files.clone())
+        .unwrap()
+        .write_to_data()
+        .unwrap();
+    let narc = Narc::read_from_data(&data).unwrap();
+
+    assert!(narc.has_file_names());
+    assert_eq!(narc.files().len(), files.len());
+    for (name, file_data) in &files {
+        assert_eq!(narc.file_by_name(name).unwrap().data(), file_data);
+    }
+    assert_eq!(narc.write_to_data().unwrap(), data);
+
+    let mut narc = narc;
+    narc.set_file_data(1, vec![10, 11]).unwrap();
+    assert_eq!(narc.file_by_name("a/e").unwrap().data(), [10, 11]);
+    assert!(matches!(
+        narc.set_file_data(3, vec![]),
+        Err(Error::InvalidInput { .. })
+    ));
+}
+
+#[test]
+fn narc_with_cyclic_directories() {
+    let mut data = Narc::from_named_files(vec![("a/b/c".to_string(), vec![0])])
+        .unwrap()
+        .write_to_data()
+        .unwrap();
+
+    // Make directory "a" list itself as its subdirectory "b".
+    let entry = [0x81, b'b', 0x02, 0xF0];
+    let position = data
+        .windows(entry.len())
+        .position(|window| window == entry)
+        .unwrap();
+    data[position + 2] = 0x01;
+
+    let error = Narc::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(&error, Error::InvalidData { offset, block, .. } if *offset == position + 2 && block == "BTNF"),
+        "{:?}",
+        error
+    );
+}
@@ -0,0 +1,300 @@
+mod common;
+
+use std::fs::{create_dir_all, read_dir, remove_file};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::{assert_same_hash, file_has_extension};
+use nitrogfx::{
+    BoundingRectangle, Cell, CellAttribute, Error, FileFormat, Ncer, NtrFileVersion,
+    NtrMappingType, OamData, OamSize, ObjMode,
+};
+
+/// Whether the data contains the attributes of an object.
+fn contains_attributes(data: &[u8], (attr0, attr1, attr2): (u16, u16, u16)) -> bool {
+    let bytes = [attr0, attr1, attr2]
+        .iter()
+        .flat_map(|attr| attr.to_le_bytes())
+        .collect::<Vec<_>>();
+    data.windows(bytes.len()).any(|window| window == bytes)
+}
+
+/// Rebuilds every object through the public API, and adds and removes a cell,
+/// which should leave the NCER unchanged.
+#[test]
+fn ncer_edit_cells() {
+    let file_ext = "ncer";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let created_file_path = &format!(
+                "tests/assets/temp/{}_edit.{}",
+                file_stem,
+                file_ext.to_uppercase()
+            );
+            let mut ncer = Ncer::read_from_file(&original_file_path).unwrap();
+
+            for cell in ncer.cells_mut() {
+                let oam_data = cell
+                    .oam_data()
+                    .iter()
+                    .map(|oam| {
+                        OamData::new(oam.oam_size(), oam.tile_number())
+                            .with_position(oam.x(), oam.y())
+                            .with_affine(oam.affine())
+                            .with_disable(oam.disable())
+                            .with_h_flip(oam.h_flip())
+                            .with_v_flip(oam.v_flip())
+                            .with_affine_index(oam.affine_index())
+                            .with_double_size(oam.double_size())
+                            .with_mode(oam.mode())
+                            .with_mosaic(oam.mosaic())
+                            .with_color_mode(oam.color_mode())
+                            .with_priority(oam.priority())
+                            .with_palette_number(oam.palette_number())
+                    })
+                    .collect();
+                *cell = Cell::new(oam_data)
+                    .with_bounding_rectangle(cell.bounding_rectangle())
+                    .with_attribute(cell.attribute());
+            }
+            if let Some(label) = ncer.labels().first() {
+                assert!(ncer.cell_by_label(label).is_some());
+            }
+            let last = ncer.cells().len();
+            ncer.push_cell(ncer.cells()[0].clone());
+            ncer.remove_cell(last);
+            ncer.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn ncer_recompute_bounds() {
+    let file_ext = "ncer";
+
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            // The bounds stored by the original tools match the computed ones,
+            // so recomputing them leaves the file unchanged.
+            let mut ncer = Ncer::read_from_file(&original_file_path).unwrap();
+            assert!(ncer.cells_with_incorrect_bounds().is_empty());
+            ncer.recompute_bounds();
+            assert_eq!(
+                ncer.write_to_data().unwrap(),
+                std::fs::read(&original_file_path).unwrap()
+            );
+        });
+}
+
+#[test]
+fn build_ncer() {
+    let oam = OamData::new(OamSize::Oam32x16, 0x123)
+        .with_position(-3, -5)
+        .with_h_flip(true)
+        .with_mode(ObjMode::Translucent)
+        .with_mosaic(true)
+        .with_color_mode(1)
+        .with_priority(2)
+        .with_palette_number(5);
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(
+        Cell::new(vec![oam])
+            .with_attribute(CellAttribute::default().with_bounding_sphere_radius(7))
+            .with_bounding_rectangle(Some(BoundingRectangle::new(-3, -5, 29, 11))),
+    );
+    ncer.push_cell(
+        Cell::new(vec![OamData::new(OamSize::Oam8x8, 1)])
+            .with_bounding_rectangle(Some(BoundingRectangle::new(0, 0, 8, 8))),
+    );
+    ncer.labels_mut().push("first".to_string());
+    assert!(ncer.cells()[0].attribute().has_bounding_rectangle());
+    assert_eq!(ncer.cells()[0].attribute().bounding_sphere_radius(), 7);
+
+    let data = ncer.write_to_data().unwrap();
+    assert!(contains_attributes(&data, (0x74FB, 0x91FD, 0x5923)));
+    assert!(contains_attributes(&data, (0x0000, 0x0000, 0x0001)));
+
+    let mut ncer = Ncer::read_from_data(&data).unwrap();
+    assert_eq!(ncer.cells().len(), 2);
+    assert_eq!(ncer.labels(), ["first"]);
+    let read_oam = ncer.cell_by_label("first").unwrap().oam_data()[0];
+    assert_eq!((read_oam.x(), read_oam.y()), (-3, -5));
+    assert!(read_oam.h_flip() && !read_oam.v_flip() && read_oam.mosaic());
+    assert!(matches!(read_oam.mode(), ObjMode::Translucent));
+    assert!(matches!(read_oam.oam_size(), OamSize::Oam32x16));
+    assert_eq!(read_oam.color_mode(), 1);
+    assert_eq!(read_oam.tile_number(), 0x123);
+    assert_eq!(read_oam.priority(), 2);
+    assert_eq!(read_oam.palette_number(), 5);
+
+    // Removing the labelled cell removes its label too.
+    ncer.remove_cell(0);
+    assert!(ncer.labels().is_empty());
+    assert!(ncer.cell_by_label("first").is_none());
+    assert_eq!(ncer.cells()[0].oam_data()[0].tile_number(), 1);
+}
+
+#[test]
+fn build_invalid_ncer() {
+    let assert_invalid = |ncer: &Ncer, expected: &str| {
+        let error = ncer.write_to_data().err().unwrap();
+        assert!(
+            matches!(&error, Error::InvalidInput { reason } if reason.contains(expected)),
+            "{:?}",
+            error
+        );
+    };
+    let new_ncer = || Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    let cell = Cell::new(vec![OamData::new(OamSize::Oam8x8, 0)]);
+
+    assert_invalid(&new_ncer(), "at least one cell");
+
+    let mut ncer = new_ncer();
+    ncer.push_cell(cell.clone());
+    ncer.labels_mut().extend(["a".to_string(), "b".to_string()]);
+    assert_invalid(&ncer, "2 labels for 1 cells");
+
+    let mut ncer = new_ncer();
+    ncer.push_cell(cell.clone());
+    ncer.push_cell(
+        cell.clone()
+            .with_bounding_rectangle(Some(BoundingRectangle::new(0, 0, 8, 8))),
+    );
+    assert_invalid(&ncer, "cell 1 differs from cell 0");
+
+    let mut ncer = new_ncer();
+    ncer.push_cell(Cell::new(vec![
+        OamData::new(OamSize::Oam8x8, 0),
+        OamData::new(OamSize::Oam8x8, 0x400),
+    ]));
+    assert_invalid(&ncer, "object 1 of cell 0: tile number 1024 is too large");
+
+    let mut ncer = new_ncer();
+    ncer.push_cell(cell.clone());
+    let mut ncer = ncer.with_user_extended_attributes(Some(vec![vec![1]]));
+    ncer.push_cell(cell.clone());
+    assert_eq!(ncer.user_extended_attributes().unwrap(), [vec![1], vec![0]]);
+    let ncer = ncer.with_user_extended_attributes(Some(vec![vec![1, 2], vec![3]]));
+    assert_invalid(&ncer, "same number of user extended attributes");
+}
+
+#[test]
+fn build_ncer_with_affine_objects() {
+    let affine = OamData::new(OamSize::Oam16x16, 4)
+        .with_position(10, 20)
+        .with_affine(true)
+        .with_affine_index(7)
+        .with_double_size(true);
+    // The flips of an affine object are not stored, as their bits hold the
+    // affine index.
+    let flipped_affine = affine.with_affine_index(0).with_h_flip(true);
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![affine, flipped_affine]));
+
+    let data = ncer.write_to_data().unwrap();
+    assert!(contains_attributes(&data, (0x0314, 0x4E0A, 0x0004)));
+    assert!(contains_attributes(&data, (0x0314, 0x400A, 0x0004)));
+
+    let ncer = Ncer::read_from_data(&data).unwrap();
+    let oam_data = ncer.cells()[0].oam_data();
+    assert!(oam_data[0].affine());
+    assert_eq!(oam_data[0].affine_index(), 7);
+    assert!(oam_data[0].double_size());
+    assert!(!oam_data[0].disable());
+    assert_eq!(oam_data[1].affine_index(), 0);
+    assert!(!oam_data[1].h_flip());
+    assert_eq!(ncer.write_to_data().unwrap(), data);
+
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![affine.with_affine_index(32)]));
+    assert!(ncer.write_to_data().is_err());
+}
+
+#[test]
+fn build_ncer_with_unused_attr1_bits() {
+    // Bits 9-11 of the second attribute are unused unless the object is
+    // affine, but are kept so that files are written back unchanged.
+    let oam = OamData::new(OamSize::Oam16x16, 4)
+        .with_position(10, 20)
+        .with_h_flip(true)
+        .with_unused_attr1_bits(5);
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![oam]));
+
+    let data = ncer.write_to_data().unwrap();
+    assert!(contains_attributes(&data, (0x0014, 0x5A0A, 0x0004)));
+
+    let ncer = Ncer::read_from_data(&data).unwrap();
+    let oam = ncer.cells()[0].oam_data()[0];
+    assert_eq!(oam.unused_attr1_bits(), 5);
+    assert!(oam.h_flip());
+    assert_eq!(ncer.write_to_data().unwrap(), data);
+    let ncer = Ncer::from_json(&ncer.to_json().unwrap()).unwrap();
+    assert_eq!(ncer.write_to_data().unwrap(), data);
+
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![oam.with_unused_attr1_bits(8)]));
+    assert!(ncer.write_to_data().is_err());
+}
+
+#[test]
+fn cell_recompute_bounds() {
+    let oam_data = vec![
+        OamData::new(OamSize::Oam16x8, 0).with_position(-12, -20),
+        OamData::new(OamSize::Oam8x32, 0).with_position(4, 0),
+        // Drawn within a 16x16 area.
+        OamData::new(OamSize::Oam8x8, 0)
+            .with_position(20, -4)
+            .with_affine(true)
+            .with_double_size(true),
+    ];
+    let mut cell = Cell::new(oam_data.clone())
+        .with_bounding_rectangle(Some(BoundingRectangle::new(0, 0, 0, 0)));
+    assert!(!cell.has_correct_bounds());
+
+    // The farthest corner is at (36, 32), which is 48.2 pixels away from the
+    // origin, i.e. 13 units of 4 pixels.
+    let expected = BoundingRectangle::new(-12, -20, 36, 32);
+    assert_eq!(cell.computed_bounding_rectangle(), expected);
+    assert_eq!(cell.computed_bounding_sphere_radius(), 13);
+    cell.recompute_bounds();
+    assert!(cell.has_correct_bounds());
+    assert_eq!(cell.bounding_rectangle(), Some(expected));
+    assert_eq!(cell.attribute().bounding_sphere_radius(), 13);
+
+    // Without a bounding rectangle, only the radius is recomputed.
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(oam_data));
+    ncer.push_cell(Cell::new(vec![]));
+    assert_eq!(ncer.cells_with_incorrect_bounds(), [0]);
+    ncer.recompute_bounds();
+    assert!(ncer.cells_with_incorrect_bounds().is_empty());
+    assert_eq!(ncer.cells()[0].bounding_rectangle(), None);
+    assert_eq!(ncer.cells()[0].attribute().bounding_sphere_radius(), 13);
+    assert_eq!(ncer.cells()[1].attribute().bounding_sphere_radius(), 0);
+}
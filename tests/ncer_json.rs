@@ -5,7 +5,24 @@ use std::fs::{create_dir_all, read_dir, read_to_string, remove_file};
 use rayon::iter::{ParallelBridge, ParallelIterator};
 
 use common::{assert_same_hash, file_has_extension};
-use nitrogfx::{FileFormat, Ncer};
+use nitrogfx::{
+    BoundingRectangle, Cell, FileFormat, Ncer, NtrFileVersion, NtrMappingType, OamData, OamSize,
+};
+
+/// Creates an NCER with two cells that have bounding rectangles.
+fn ncer_with_two_cells() -> Ncer {
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    for tile_number in [0, 1] {
+        ncer.push_cell(
+            Cell::new(vec![
+                OamData::new(OamSize::Oam8x8, tile_number),
+                OamData::new(OamSize::Oam16x8, tile_number).with_position(8, 0),
+            ])
+            .with_bounding_rectangle(Some(BoundingRectangle::new(0, 0, 24, 8))),
+        );
+    }
+    ncer
+}
 
 #[test]
 fn ncer_to_json_to_ncer() {
@@ -41,3 +58,94 @@ fn ncer_to_json_to_ncer() {
             remove_file(created_file_path).unwrap();
         });
 }
+
+#[test]
+fn ncer_with_user_extended_data_to_json_to_ncer() {
+    let ncer = ncer_with_two_cells()
+        .with_user_extended_attributes(Some(vec![vec![1, 0xDEADBEEF], vec![2, 3]]))
+        .with_user_extended_data(vec![0x12, 0x34, 0x56, 0x78]);
+    let data = ncer.write_to_data().unwrap();
+    assert!(data.windows(4).any(|window| window == b"TACU"));
+    assert!(data.ends_with(b"TXEU\x0C\x00\x00\x00\x12\x34\x56\x78"));
+
+    let read_ncer = Ncer::read_from_data(&data).unwrap();
+    assert_eq!(
+        read_ncer.user_extended_attributes().unwrap(),
+        [vec![1, 0xDEADBEEF], vec![2, 3]]
+    );
+    assert_eq!(read_ncer.user_extended_data(), [0x12, 0x34, 0x56, 0x78]);
+
+    let json = read_ncer.to_json().unwrap();
+    let created_ncer = Ncer::from_json(&json).unwrap();
+    assert_eq!(created_ncer.write_to_data().unwrap(), data);
+}
+
+/// Returns the JSON of the NCER in the format written before user extended
+/// attributes were modelled, which only had a flag for them.
+fn legacy_json(ncer: &Ncer, has_user_extended_attribute_data: bool) -> String {
+    let mut json: serde_json::Value = serde_json::from_str(&ncer.to_json().unwrap()).unwrap();
+    let object = json.as_object_mut().unwrap();
+    object.remove("user_extended_attributes");
+    object.remove("user_extended_data");
+    object.insert(
+        "has_user_extended_attribute_data".to_string(),
+        has_user_extended_attribute_data.into(),
+    );
+    json.to_string()
+}
+
+#[test]
+fn legacy_json_to_ncer() {
+    // The flag stands for a single zero attribute per cell.
+    let ncer = Ncer::from_json(&legacy_json(&ncer_with_two_cells(), true)).unwrap();
+    assert_eq!(ncer.user_extended_attributes().unwrap(), [vec![0], vec![0]]);
+    assert_eq!(ncer.user_extended_data(), [0, 0, 0, 0]);
+    assert_eq!(
+        ncer.write_to_data().unwrap(),
+        ncer_with_two_cells()
+            .with_user_extended_attributes(Some(vec![vec![0], vec![0]]))
+            .write_to_data()
+            .unwrap()
+    );
+    assert!(!ncer
+        .to_json()
+        .unwrap()
+        .contains("has_user_extended_attribute_data"));
+
+    let ncer = Ncer::from_json(&legacy_json(&ncer_with_two_cells(), false)).unwrap();
+    assert!(ncer.user_extended_attributes().is_none());
+    assert_eq!(
+        ncer.write_to_data().unwrap(),
+        ncer_with_two_cells().write_to_data().unwrap()
+    );
+}
+
+#[test]
+fn legacy_affine_json_to_ncer() {
+    // JSON written before affine objects had their own fields stores the
+    // double size flag in `disable`, and bits 3 and 4 of the affine index in
+    // the flips. Bits 0-2 of the index were not kept.
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![OamData::new(OamSize::Oam16x16, 0)
+        .with_affine(true)
+        .with_affine_index(0b11000)
+        .with_double_size(true)]));
+    let mut json: serde_json::Value = serde_json::from_str(&ncer.to_json().unwrap()).unwrap();
+    let oam = json["cells"][0]["oam_data"][0].as_object_mut().unwrap();
+    for field in ["affine_index", "double_size", "unused_attr1_bits"] {
+        oam.remove(field);
+    }
+    for field in ["disable", "h_flip", "v_flip"] {
+        oam.insert(field.to_string(), true.into());
+    }
+
+    let legacy_ncer = Ncer::from_json(&json.to_string()).unwrap();
+    let oam = legacy_ncer.cells()[0].oam_data()[0];
+    assert_eq!(oam.affine_index(), 0b11000);
+    assert!(oam.double_size());
+    assert!(!oam.disable() && !oam.h_flip() && !oam.v_flip());
+    assert_eq!(
+        legacy_ncer.write_to_data().unwrap(),
+        ncer.write_to_data().unwrap()
+    );
+}
@@ -0,0 +1,122 @@
+use nitrogfx::{Cell, Error, FileFormat, Ncer, NtrFileVersion, NtrMappingType, OamData, OamSize};
+
+/// Returns the data of an NCER with `num_cells` cells whose LABL block has
+/// the given contents, along with the offset of those contents.
+fn ncer_with_labl_block(num_cells: usize, labl: &[u8]) -> (Vec<u8>, usize) {
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    for tile_number in 0..num_cells {
+        ncer.push_cell(Cell::new(vec![OamData::new(
+            OamSize::Oam8x8,
+            u16::try_from(tile_number).unwrap(),
+        )]));
+    }
+    let original = ncer.write_to_data().unwrap();
+
+    let mut data = original[..16].to_vec();
+    let mut labl_offset = 0;
+    let mut offset = 16;
+    while offset < original.len() {
+        let size = u32::from_le_bytes(original[offset + 4..offset + 8].try_into().unwrap());
+        let next_offset = offset + usize::try_from(size).unwrap();
+        if &original[offset..offset + 4] == b"LBAL" {
+            labl_offset = data.len() + 8;
+            data.extend(b"LBAL");
+            data.extend((u32::try_from(labl.len()).unwrap() + 8).to_le_bytes());
+            data.extend(labl);
+        } else {
+            data.extend(&original[offset..next_offset]);
+        }
+        offset = next_offset;
+    }
+    let file_size = u32::try_from(data.len()).unwrap();
+    data[8..12].copy_from_slice(&file_size.to_le_bytes());
+    (data, labl_offset)
+}
+
+/// Returns the contents of a LABL block with contiguous labels.
+fn labl_block(raw_labels: &[&[u8]], padding: &[u8]) -> Vec<u8> {
+    let mut labl = vec![];
+    let mut offset = 0u32;
+    for raw in raw_labels {
+        labl.extend(offset.to_le_bytes());
+        offset += u32::try_from(raw.len()).unwrap() + 1;
+    }
+    for raw in raw_labels {
+        labl.extend(*raw);
+        labl.push(0);
+    }
+    labl.extend(padding);
+    labl
+}
+
+fn assert_labels(num_cells: usize, labl: &[u8], expected: &[&str]) {
+    let (data, _) = ncer_with_labl_block(num_cells, labl);
+    let ncer = Ncer::read_from_data(&data).unwrap();
+    assert_eq!(ncer.labels(), expected);
+    assert_eq!(ncer.write_to_data().unwrap(), data);
+}
+
+#[test]
+fn read_labels() {
+    assert_labels(
+        2,
+        &labl_block(&[b"CellA", b"CellB"], &[]),
+        &["CellA", "CellB"],
+    );
+    assert_labels(1, &labl_block(&["ラベル".as_bytes()], &[0]), &["ラベル"]);
+}
+
+#[test]
+fn read_empty_first_labels() {
+    let labl = labl_block(&[b"", b"", b"", b"", b"CellE"], &[0, 0]);
+    assert_labels(5, &labl, &["", "", "", "", "CellE"]);
+}
+
+#[test]
+fn read_shift_jis_labels() {
+    // "セル" and "ラベル" encoded as Shift-JIS, which are not valid UTF-8.
+    let labl = labl_block(&[b"\x83\x5A\x83\x8B", b"\x83\x89\x83\x78\x83\x8B"], &[0]);
+    assert_labels(2, &labl, &["セル", "ラベル"]);
+}
+
+#[test]
+fn read_labels_with_padding() {
+    assert_labels(1, &labl_block(&[b"CellA"], &[0xFF, 0xFF]), &["CellA"]);
+    assert_labels(1, &labl_block(&[b"Cell"], &[0x12, 0x34, 0x56]), &["Cell"]);
+}
+
+#[test]
+fn read_fewer_labels_than_cells() {
+    assert_labels(3, &labl_block(&[b"CellA"], &[0, 0]), &["CellA"]);
+    assert_labels(
+        3,
+        &labl_block(&[b"CellA", b"CellB"], &[]),
+        &["CellA", "CellB"],
+    );
+}
+
+#[test]
+fn read_more_labels_than_cells() {
+    let (data, labl_offset) =
+        ncer_with_labl_block(1, &labl_block(&[b"CellA", b"CellB", b"CellC"], &[0, 0]));
+    let error = Ncer::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(&error, Error::InvalidData { offset, block, .. }
+            if *offset == labl_offset && block == "LBAL"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn read_invalid_shift_jis_label() {
+    // 0x82 starts a double-byte character that never ends.
+    let (data, labl_offset) = ncer_with_labl_block(2, &labl_block(&[b"CellA", b"\x82"], &[0]));
+    let error = Ncer::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(&error, Error::InvalidData { offset, block, .. }
+            if *offset == labl_offset + 8 + 6 && block == "LBAL"),
+        "{:?}",
+        error
+    );
+}
@@ -0,0 +1,553 @@
+mod common;
+
+use std::{
+    fs::{create_dir_all, read_dir, remove_file},
+    path::Path,
+};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::file_has_extension;
+use nitrogfx::{
+    AffineMatrix, BoundingRectangle, Cell, Color, Error, FileFormat, Image, Ncer, Ncgr,
+    NcgrMetadata, Nclr, NclrMetadata, NtrFileVersion, NtrMappingType, NtrTextureFormat, OamData,
+    OamSize, Palette, Png,
+};
+
+/// Creates an NCGR storing the tiles one after the other.
+fn ncgr_from_tiles(
+    tiles: &[[u8; 64]],
+    texture_format: NtrTextureFormat,
+    mapping_type: NtrMappingType,
+) -> Ncgr {
+    let pixels = tiles.concat();
+    Ncgr::from_image(
+        Image::new(8, &pixels, None),
+        NcgrMetadata::default()
+            .with_texture_format(texture_format)
+            .with_mapping_type(mapping_type),
+    )
+}
+
+/// Creates an NCLR whose colors are all distinct.
+fn nclr_with_colors(num_colors: usize, texture_format: NtrTextureFormat) -> Nclr {
+    Nclr::from_palette(
+        Palette::new((0..num_colors).map(|i| Color::from(i as u16)).collect()),
+        NclrMetadata::default().with_texture_format(texture_format),
+    )
+}
+
+/// Renders every cell of each NCER that has an NCGR and NCLR of the same name
+/// next to it.
+#[test]
+fn ncer_to_png() {
+    let file_ext = "ncer";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let ncgr_path = original_file_path.with_extension("NCGR");
+            let nclr_path = original_file_path.with_extension("NCLR");
+            if !Path::new(&ncgr_path).exists() || !Path::new(&nclr_path).exists() {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}_cell", file_stem);
+            let ncer = Ncer::read_from_file(&original_file_path).unwrap();
+            let ncgr = Ncgr::read_from_file(ncgr_path).unwrap();
+            let nclr = Nclr::read_from_file(nclr_path).unwrap();
+
+            for index in 0.. {
+                let Ok(image) = ncer.render_cell(index, &ncgr, &nclr) else {
+                    break;
+                };
+                let created_file_path = &format!("{}{}.png", temp_file_stem, index);
+                let palette = image.palette().unwrap();
+                assert!(palette.colors()[0].is_transparent());
+                Png::from_image(image)
+                    .write_to_file(created_file_path)
+                    .unwrap();
+
+                // The transparency is kept in the tRNS chunk.
+                let created_image = Png::read_from_file(created_file_path)
+                    .unwrap()
+                    .to_image()
+                    .unwrap();
+                assert_eq!(created_image.palette().unwrap().colors(), palette.colors());
+                remove_file(created_file_path).unwrap();
+            }
+        });
+}
+
+/// Returns the width and pixels of the smallest area containing all opaque
+/// pixels of the image.
+fn trim(image: &Image) -> (usize, Vec<u8>) {
+    let width = image.width();
+    let opaque = |i: &usize| image.pixels()[*i] != 0;
+    let indexes = (0..image.pixels().len()).filter(opaque).collect::<Vec<_>>();
+    let Some(left) = indexes.iter().map(|i| i % width).min() else {
+        return (0, vec![]);
+    };
+    let right = indexes.iter().map(|i| i % width).max().unwrap() + 1;
+    let top = indexes.first().unwrap() / width;
+    let bottom = indexes.last().unwrap() / width + 1;
+    let pixels = (top..bottom)
+        .flat_map(|y| &image.pixels()[y * width + left..y * width + right])
+        .copied()
+        .collect();
+    (right - left, pixels)
+}
+
+/// Rebuilds every rendered cell from its image and checks that it renders the
+/// same. Cells that mix 4bpp palettes are skipped, as a tile may then contain
+/// pixels of several palettes.
+#[test]
+fn png_to_ncer() {
+    let file_ext = "ncer";
+
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let ncgr_path = original_file_path.with_extension("NCGR");
+            let nclr_path = original_file_path.with_extension("NCLR");
+            if !Path::new(&ncgr_path).exists() || !Path::new(&nclr_path).exists() {
+                return;
+            }
+            let ncer = Ncer::read_from_file(&original_file_path).unwrap();
+            let ncgr = Ncgr::read_from_file(ncgr_path).unwrap();
+            let nclr = Nclr::read_from_file(nclr_path).unwrap();
+            let metadata = ncgr.metadata().with_mapping_type(NtrMappingType::Mode1D32K);
+
+            for index in 0.. {
+                let Ok(image) = ncer.render_cell(index, &ncgr, &nclr) else {
+                    break;
+                };
+                if matches!(metadata.texture_format, NtrTextureFormat::Palette16) {
+                    let mut palettes = image.pixels().iter().filter(|p| **p != 0).map(|p| p / 16);
+                    if let Some(palette) = palettes.next() {
+                        if palettes.any(|p| p != palette) {
+                            continue;
+                        }
+                    }
+                }
+
+                let (built_ncer, built_ncgr) =
+                    Ncer::from_images(std::slice::from_ref(&image), metadata).unwrap();
+                let built_image = built_ncer.render_cell(0, &built_ncgr, &nclr).unwrap();
+                assert_eq!(trim(&image), trim(&built_image));
+            }
+        });
+}
+
+/// Moves the tiles of each cell into their own VRAM transfer area, which
+/// should not change how any cell renders.
+#[test]
+fn ncer_vram_transfer() {
+    let file_ext = "ncer";
+
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let ncgr_path = original_file_path.with_extension("NCGR");
+            let nclr_path = original_file_path.with_extension("NCLR");
+            if !Path::new(&ncgr_path).exists() || !Path::new(&nclr_path).exists() {
+                return;
+            }
+            let ncer = Ncer::read_from_file(&original_file_path).unwrap();
+            let ncgr = Ncgr::read_from_file(ncgr_path).unwrap();
+            let nclr = Nclr::read_from_file(nclr_path).unwrap();
+            if ncer.vram_data().is_some() || matches!(ncer.mapping_type(), NtrMappingType::Mode2D) {
+                return;
+            }
+
+            let mut transferred_ncer = Ncer::read_from_file(&original_file_path).unwrap();
+            let reordered_ncgr = transferred_ncer
+                .compute_vram_transfer_data_with_reordering(&ncgr)
+                .unwrap();
+            for index in 0..ncer.cells().len() {
+                let Ok(image) = ncer.render_cell(index, &ncgr, &nclr) else {
+                    continue;
+                };
+                let transferred_image = transferred_ncer
+                    .render_cell(index, &reordered_ncgr, &nclr)
+                    .unwrap();
+                assert_eq!(image.pixels(), transferred_image.pixels());
+            }
+        });
+}
+
+#[test]
+fn render_cell() {
+    // Tile 0 is filled with 1 except for its top left pixel, and tiles 1 and
+    // 2 are filled with 3 and 4.
+    let mut tile0 = [1; 64];
+    tile0[0] = 2;
+    let ncgr = ncgr_from_tiles(
+        &[tile0, [3; 64], [4; 64]],
+        NtrTextureFormat::Palette16,
+        NtrMappingType::Mode1D32K,
+    );
+    let nclr = nclr_with_colors(32, NtrTextureFormat::Palette16);
+
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![
+        OamData::new(OamSize::Oam16x8, 0)
+            .with_position(-8, -4)
+            .with_h_flip(true)
+            .with_palette_number(1)
+            .with_priority(1),
+        OamData::new(OamSize::Oam8x8, 2).with_position(-4, -4),
+    ]));
+
+    // The first object shows tile 1 then tile 0 flipped, using the second
+    // palette, and the second object is drawn on top of its middle.
+    let expected = (0..8 * 16)
+        .map(|i| match (i % 16, i / 16) {
+            (4..12, _) => 4,
+            (15, 0) => 16 + 2,
+            (0..8, _) => 16 + 3,
+            _ => 16 + 1,
+        })
+        .collect::<Vec<u8>>();
+    let image = ncer.render_cell(0, &ncgr, &nclr).unwrap();
+    assert_eq!(image.width(), 16);
+    assert_eq!(image.pixels(), expected);
+    let palette = image.palette().unwrap();
+    assert_eq!(palette.colors().len(), 32);
+    assert!(palette.colors()[0].is_transparent());
+    assert!(palette.colors()[16].is_transparent());
+    assert!(!palette.colors()[1].is_transparent());
+
+    // The bounding rectangle crops the image to the left half.
+    let cell = ncer.cells()[0]
+        .clone()
+        .with_bounding_rectangle(Some(BoundingRectangle::new(-8, -4, 0, 4)));
+    ncer.cells_mut()[0] = cell;
+    let image = ncer.render_cell(0, &ncgr, &nclr).unwrap();
+    assert_eq!(image.width(), 8);
+    assert_eq!(
+        image.pixels(),
+        expected
+            .chunks(16)
+            .flat_map(|row| &row[..8])
+            .copied()
+            .collect::<Vec<_>>()
+    );
+
+    assert!(ncer.render_cell(1, &ncgr, &nclr).is_err());
+
+    // The second palette is past the end of a 16-color NCLR.
+    let nclr = nclr_with_colors(16, NtrTextureFormat::Palette16);
+    let result = ncer.render_cell(0, &ncgr, &nclr);
+    assert!(
+        matches!(result, Err(Error::InvalidInput { .. })),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn render_cell_with_mapping_types() {
+    // The tiles of an 8x16 256-color object, where each tile number counts in
+    // units of the mapping boundary, i.e. 1 tile for 64K and 2 for 128K.
+    let tiles = (1..=4).map(|i| [i; 64]).collect::<Vec<_>>();
+    let nclr = nclr_with_colors(256, NtrTextureFormat::Palette256);
+    for (mapping_type, expected) in [
+        (NtrMappingType::Mode1D64K, [2, 3]),
+        (NtrMappingType::Mode1D128K, [3, 4]),
+    ] {
+        let ncgr = ncgr_from_tiles(&tiles, NtrTextureFormat::Palette256, mapping_type);
+        let mut ncer = Ncer::new(NtrFileVersion::Version0100, mapping_type);
+        ncer.push_cell(Cell::new(vec![OamData::new(OamSize::Oam8x16, 1)
+            .with_color_mode(1)
+            .with_v_flip(true)]));
+
+        let image = ncer.render_cell(0, &ncgr, &nclr).unwrap();
+        assert_eq!(image.width(), 8);
+        assert_eq!(image.pixels()[..64], [expected[1]; 64]);
+        assert_eq!(image.pixels()[64..], [expected[0]; 64]);
+    }
+}
+
+/// Returns the size, position, tile number and palette number of an object.
+fn describe(oam: &OamData) -> (String, i16, i8, u16, u8) {
+    (
+        format!("{:?}", oam.oam_size()),
+        oam.x(),
+        oam.y(),
+        oam.tile_number(),
+        oam.palette_number(),
+    )
+}
+
+#[test]
+fn images_to_ncer() {
+    // A 16x8 image whose tiles use palettes 0 and 1, so each needs its own
+    // object.
+    let tile = (0..64).map(|i| i % 15 + 1).collect::<Vec<u8>>();
+    let two_palettes = Image::new(
+        16,
+        &(0..16 * 8)
+            .map(|i| match i % 16 {
+                x @ 0..8 => tile[i / 16 * 8 + x],
+                x => 0x10 | tile[i / 16 * 8 + x - 8],
+            })
+            .collect::<Vec<_>>(),
+        None,
+    );
+    // A 24x16 image with opaque tiles at (0, 0), (1, 0), (2, 0) and (0, 1),
+    // which a single 32x16 object covers.
+    let l_shape = Image::new(
+        24,
+        &(0..24 * 16)
+            .map(|i| match (i % 24 / 8, i / 24 / 8) {
+                (_, 0) | (0, 1) => 0x22,
+                _ => 0,
+            })
+            .collect::<Vec<_>>(),
+        None,
+    );
+    let metadata = NcgrMetadata::default()
+        .with_texture_format(NtrTextureFormat::Palette16)
+        .with_mapping_type(NtrMappingType::Mode1D64K);
+
+    let (ncer, ncgr) = Ncer::from_images(&[two_palettes, l_shape], metadata).unwrap();
+
+    assert_eq!(ncer.cells().len(), 2);
+    let oam_data = ncer.cells()[0].oam_data();
+    assert_eq!(
+        oam_data.iter().map(describe).collect::<Vec<_>>(),
+        [
+            ("Oam8x8".to_string(), -8, -4, 0, 0),
+            ("Oam8x8".to_string(), 0, -4, 1, 1),
+        ]
+    );
+    assert_eq!(
+        ncer.cells()[0].bounding_rectangle(),
+        Some(BoundingRectangle::new(-8, -4, 8, 4))
+    );
+    let oam_data = ncer.cells()[1].oam_data();
+    assert_eq!(
+        oam_data.iter().map(describe).collect::<Vec<_>>(),
+        [("Oam32x16".to_string(), -12, -8, 2, 2)]
+    );
+    assert_eq!(
+        ncer.cells()[1].bounding_rectangle(),
+        Some(BoundingRectangle::new(-12, -8, 20, 8))
+    );
+    assert!(ncer.cells_with_incorrect_bounds().is_empty());
+
+    // Each object starts on a boundary of 2 tiles, so the tiles of the first
+    // image are each followed by a padding tile. Then come the 4x2 tiles of
+    // the second image, including those outside of it.
+    let opaque = [2; 64];
+    let transparent = [0; 64];
+    let expected_tiles = [
+        &tile[..],
+        &transparent,
+        &tile,
+        &transparent,
+        &opaque,
+        &opaque,
+        &opaque,
+        &transparent,
+        &opaque,
+        &transparent,
+        &transparent,
+        &transparent,
+    ];
+    assert_eq!(
+        ncgr.to_image_with_width(8).pixels(),
+        expected_tiles.concat()
+    );
+}
+
+#[test]
+fn images_to_ncer_errors() {
+    let metadata = NcgrMetadata::default().with_texture_format(NtrTextureFormat::Palette16);
+    let mixed_palettes = Image::new(8, &[[0x01; 32], [0x11; 32]].concat(), None);
+    assert!(Ncer::from_images(&[mixed_palettes], metadata).is_err());
+
+    let image = Image::new(8, &[1; 64], None);
+    let metadata = metadata.with_mapping_type(NtrMappingType::Mode2D);
+    assert!(Ncer::from_images(&[image], metadata).is_err());
+}
+
+#[test]
+fn render_cell_with_affine_matrices() {
+    // Each pixel holds its column plus one.
+    let tile: [u8; 64] = std::array::from_fn(|i| (i % 8) as u8 + 1);
+    let ncgr = ncgr_from_tiles(
+        &[tile],
+        NtrTextureFormat::Palette16,
+        NtrMappingType::Mode1D32K,
+    );
+    let nclr = nclr_with_colors(16, NtrTextureFormat::Palette16);
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![OamData::new(OamSize::Oam8x8, 0)
+        .with_affine(true)
+        .with_affine_index(1)]));
+    ncer.push_cell(Cell::new(vec![OamData::new(OamSize::Oam8x8, 0)
+        .with_affine(true)
+        .with_double_size(true)]));
+
+    // Without matrices, affine objects are drawn untransformed.
+    let image = ncer.render_cell(0, &ncgr, &nclr).unwrap();
+    assert_eq!(image.pixels(), tile);
+
+    // The matrix maps around the center of the object, so mirroring it moves
+    // the columns by one, leaving the first one transparent.
+    let mirror = AffineMatrix::new(-0x100, 0, 0, 0x100);
+    let image = ncer
+        .render_cell_with_affine_matrices(0, &ncgr, &nclr, &[AffineMatrix::IDENTITY, mirror])
+        .unwrap();
+    assert_eq!(image.pixels()[..8], [0, 8, 7, 6, 5, 4, 3, 2]);
+    assert!(image
+        .pixels()
+        .chunks(8)
+        .all(|row| row == image.pixels()[..8].to_vec()));
+
+    // Double size objects are drawn centered within twice their size.
+    let image = ncer
+        .render_cell_with_affine_matrices(1, &ncgr, &nclr, &[AffineMatrix::IDENTITY])
+        .unwrap();
+    assert_eq!(image.width(), 16);
+    let expected = (0..16 * 16)
+        .map(|i| match (i % 16, i / 16) {
+            (x @ 4..12, 4..12) => (x - 3) as u8,
+            _ => 0,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(image.pixels(), expected);
+}
+
+/// Returns the source offset and size of the VRAM transfer of each cell, and
+/// the maximum size.
+fn vram_transfers(ncer: &Ncer) -> (Vec<(u32, u32)>, u32) {
+    let vram_data = ncer.vram_data().unwrap();
+    let transfers = vram_data
+        .data()
+        .iter()
+        .map(|data| (data.src_offset(), data.size()))
+        .collect();
+    (transfers, vram_data.max_size())
+}
+
+/// Returns the tile number of each object of each cell.
+fn tile_numbers(ncer: &Ncer) -> Vec<Vec<u16>> {
+    ncer.cells()
+        .iter()
+        .map(|cell| cell.oam_data().iter().map(OamData::tile_number).collect())
+        .collect()
+}
+
+#[test]
+fn compute_vram_transfer_data() {
+    let tiles = (1..=6).map(|i| [i; 64]).collect::<Vec<_>>();
+    let ncgr = ncgr_from_tiles(
+        &tiles,
+        NtrTextureFormat::Palette16,
+        NtrMappingType::Mode1D32K,
+    );
+    let nclr = nclr_with_colors(16, NtrTextureFormat::Palette16);
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![
+        OamData::new(OamSize::Oam8x8, 2),
+        OamData::new(OamSize::Oam16x8, 4).with_position(8, 0),
+    ]));
+    ncer.push_cell(Cell::new(vec![
+        OamData::new(OamSize::Oam8x8, 1),
+        OamData::new(OamSize::Oam8x8, 1).with_position(8, 0),
+    ]));
+    let images = (0..2)
+        .map(|i| ncer.render_cell(i, &ncgr, &nclr).unwrap())
+        .collect::<Vec<_>>();
+
+    // Each cell transfers the tiles from its first one to its last one, and
+    // its tile numbers become relative to them.
+    let mut transferred_ncer = Ncer::read_from_data(&ncer.write_to_data().unwrap()).unwrap();
+    transferred_ncer.compute_vram_transfer_data(&ncgr).unwrap();
+    assert_eq!(
+        vram_transfers(&transferred_ncer),
+        (vec![(0x40, 0x80), (0x20, 0x20)], 0x80)
+    );
+    assert_eq!(tile_numbers(&transferred_ncer), [[0, 2], [0, 0]]);
+    for (i, image) in images.iter().enumerate() {
+        let transferred_image = transferred_ncer.render_cell(i, &ncgr, &nclr).unwrap();
+        assert_eq!(transferred_image.pixels(), image.pixels());
+    }
+
+    // The tile numbers are already relative to the transferred data, so
+    // computing it again is an error that leaves the NCER unchanged.
+    let data = transferred_ncer.write_to_data().unwrap();
+    assert!(transferred_ncer.compute_vram_transfer_data(&ncgr).is_err());
+    assert!(transferred_ncer
+        .compute_vram_transfer_data_with_reordering(&ncgr)
+        .is_err());
+    assert_eq!(transferred_ncer.write_to_data().unwrap(), data);
+
+    // With reordering, the unused tile 3 is left out and tile 1, which both
+    // objects of the second cell use, is stored once.
+    let mut reordered_ncer = Ncer::read_from_data(&ncer.write_to_data().unwrap()).unwrap();
+    let reordered_ncgr = reordered_ncer
+        .compute_vram_transfer_data_with_reordering(&ncgr)
+        .unwrap();
+    assert_eq!(
+        vram_transfers(&reordered_ncer),
+        (vec![(0x00, 0x60), (0x60, 0x20)], 0x60)
+    );
+    assert_eq!(tile_numbers(&reordered_ncer), [[0, 1], [0, 0]]);
+    assert_eq!(
+        reordered_ncgr.to_image_with_width(8).pixels(),
+        [[3; 64], [5; 64], [6; 64], [2; 64]].concat()
+    );
+    for (i, image) in images.iter().enumerate() {
+        let reordered_image = reordered_ncer
+            .render_cell(i, &reordered_ncgr, &nclr)
+            .unwrap();
+        assert_eq!(reordered_image.pixels(), image.pixels());
+    }
+
+    // Tiles past the end of the character data and 2D mapping are errors,
+    // which leave the tile numbers of the cells before unchanged.
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode1D32K);
+    ncer.push_cell(Cell::new(vec![OamData::new(OamSize::Oam8x8, 2)]));
+    ncer.push_cell(Cell::new(vec![OamData::new(OamSize::Oam16x8, 5)]));
+    assert!(ncer.compute_vram_transfer_data(&ncgr).is_err());
+    assert!(ncer
+        .compute_vram_transfer_data_with_reordering(&ncgr)
+        .is_err());
+    assert_eq!(tile_numbers(&ncer), [[2], [5]]);
+    assert!(ncer.vram_data().is_none());
+    let mut ncer = Ncer::new(NtrFileVersion::Version0100, NtrMappingType::Mode2D);
+    ncer.push_cell(Cell::new(vec![OamData::new(OamSize::Oam8x8, 0)]));
+    assert!(ncer.compute_vram_transfer_data(&ncgr).is_err());
+}
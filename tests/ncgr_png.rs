@@ -1,11 +1,18 @@
 mod common;
 
-use std::fs::{create_dir_all, read_dir, remove_file};
+use std::{
+    collections::HashSet,
+    fs::{create_dir_all, read_dir, remove_file},
+};
 
 use rayon::iter::{ParallelBridge, ParallelIterator};
 
 use common::{assert_same_hash, file_has_extension};
-use nitrogfx::{FileFormat, Ncgr, Png};
+use nitrogfx::{
+    Color, ColorScaling, Dithering, Error, FileFormat, Image, Ncgr, NcgrMetadata,
+    NtrCharacterFormat, NtrFileVersion, NtrMappingType, NtrTextureFormat, Palette, Png,
+    Quantization, TruecolorImage,
+};
 
 #[test]
 fn ncgr_to_png_to_ncgr() {
@@ -43,7 +50,10 @@ fn ncgr_to_png_to_ncgr() {
                 .unwrap();
 
             let created_file = Ncgr::from_image(
-                Png::read_from_file(intermediate_path).unwrap().to_image(),
+                Png::read_from_file(intermediate_path)
+                    .unwrap()
+                    .to_image()
+                    .unwrap(),
                 original_file.metadata(),
             );
             created_file.write_to_file(created_file_path).unwrap();
@@ -53,3 +63,638 @@ fn ncgr_to_png_to_ncgr() {
             remove_file(created_file_path).unwrap();
         });
 }
+
+#[test]
+fn ncgr_to_png_with_sidecar_to_ncgr() {
+    let file_ext = "ncgr";
+    let intermediate_ext = "png";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}_sidecar", file_stem);
+            let intermediate_path = &format!("{}.{}", temp_file_stem, intermediate_ext);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let original_file = Ncgr::read_from_file(&original_file_path).unwrap();
+            let image = match original_file.metadata().mapping_type {
+                nitrogfx::NtrMappingType::Mode2D => original_file.to_image(),
+                _ => original_file.to_image_with_width(8),
+            };
+            Png::from_image(image)
+                .write_to_file(intermediate_path)
+                .unwrap();
+            original_file
+                .metadata()
+                .write_sidecar(intermediate_path)
+                .unwrap();
+
+            let created_file = Ncgr::read_from_png_with_sidecar(intermediate_path).unwrap();
+            created_file.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(intermediate_path).unwrap();
+            remove_file(format!("{}.ncgr.json", temp_file_stem)).unwrap();
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn ncgr_to_png_with_embedded_metadata_to_ncgr() {
+    let file_ext = "ncgr";
+    let intermediate_ext = "png";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}_embedded", file_stem);
+            let intermediate_path = &format!("{}.{}", temp_file_stem, intermediate_ext);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let original_file = Ncgr::read_from_file(&original_file_path).unwrap();
+            let image = match original_file.metadata().mapping_type {
+                nitrogfx::NtrMappingType::Mode2D => original_file.to_image(),
+                _ => original_file.to_image_with_width(8),
+            };
+            Png::from_image(image)
+                .with_ncgr_metadata(original_file.metadata())
+                .write_to_file(intermediate_path)
+                .unwrap();
+
+            let created_file = Ncgr::read_from_png_with_sidecar(intermediate_path).unwrap();
+            created_file.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(intermediate_path).unwrap();
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn ncgr_to_truecolor_png_to_image() {
+    let file_ext = "ncgr";
+    let intermediate_ext = "png";
+
+    // Colors that are all distinct in BGR555, so every index can be recovered,
+    // with a transparent first color as it is never dithered into.
+    let mut colors = (0..256).map(|i| Color::from(i * 0x7F)).collect::<Vec<_>>();
+    let opaque_palette = Palette::new(colors.clone());
+    colors[0] = colors[0].with_alpha(0);
+    let palette = Palette::new(colors);
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}_truecolor", file_stem);
+            let intermediate_path = &format!("{}.{}", temp_file_stem, intermediate_ext);
+            let original_file = Ncgr::read_from_file(&original_file_path).unwrap();
+            let image = match original_file.metadata().mapping_type {
+                nitrogfx::NtrMappingType::Mode2D => original_file.to_image(),
+                _ => original_file.to_image_with_width(8),
+            };
+            let colors = image
+                .pixels()
+                .iter()
+                .map(|pixel| palette.colors()[usize::from(*pixel)])
+                .collect::<Vec<_>>();
+            Png::from_truecolor_image(TruecolorImage::new(image.width(), colors.clone()))
+                .write_to_file(intermediate_path)
+                .unwrap();
+
+            let png = Png::read_from_file(intermediate_path).unwrap();
+            assert!(png.truecolor_image().is_some());
+            let exact = png
+                .to_image_with_quantization(&Quantization::exact(palette.clone()))
+                .unwrap();
+            assert!(exact.pixels() == image.pixels());
+
+            // Colors already in the palette leave no difference to spread.
+            for dithering in [Dithering::FloydSteinberg, Dithering::Atkinson] {
+                let dithered = png
+                    .to_image_with_quantization(
+                        &Quantization::nearest(palette.clone()).with_dithering(dithering),
+                    )
+                    .unwrap();
+                assert!(dithered.pixels() == image.pixels());
+            }
+
+            for dithering in [
+                Dithering::None,
+                Dithering::FloydSteinberg,
+                Dithering::Bayer,
+                Dithering::Atkinson,
+            ] {
+                let automatic = png
+                    .to_image_with_quantization(
+                        &Quantization::automatic(256).with_dithering(dithering),
+                    )
+                    .unwrap();
+                let automatic_palette = automatic.palette().unwrap();
+                assert!(automatic
+                    .pixels()
+                    .iter()
+                    .zip(&colors)
+                    .all(
+                        |(pixel, color)| automatic_palette.colors()[usize::from(*pixel)] == *color
+                    ));
+            }
+
+            // Color 0 is reserved, so only 255 opaque colors fit in the palette.
+            let opaque_colors = image
+                .pixels()
+                .iter()
+                .map(|pixel| opaque_palette.colors()[usize::from(*pixel)])
+                .collect();
+            let automatic = TruecolorImage::new(image.width(), opaque_colors)
+                .quantize(&Quantization::automatic(256));
+            match image.pixels().iter().collect::<HashSet<_>>().len() {
+                256 => assert!(automatic.is_err()),
+                _ => assert!(automatic.is_ok()),
+            }
+            remove_file(intermediate_path).unwrap();
+        });
+}
+
+#[test]
+fn low_bit_depth_png_to_image() {
+    // A 3x2 image whose rows do not fill their last byte.
+    let pixels = [0, 1, 1, 1, 0, 1];
+    for (bit_depth, rows) in [
+        (png::BitDepth::One, vec![0b0110_0000, 0b1010_0000]),
+        (png::BitDepth::Two, vec![0b0001_0100, 0b0100_0100]),
+    ] {
+        let mut data = vec![];
+        let mut encoder = png::Encoder::new(&mut data, 3, 2);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(bit_depth);
+        encoder.set_palette(vec![0, 0, 0, 0xFF, 0xFF, 0xFF]);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&rows)
+            .unwrap();
+
+        let image = Png::read_from_data(&data).unwrap().to_image().unwrap();
+        assert_eq!(image.width(), 3);
+        assert_eq!(image.pixels(), pixels);
+        assert_eq!(image.palette().unwrap().colors().len(), 2);
+    }
+}
+
+/// Encodes a truecolor or grayscale PNG with 8-bit samples, along with a
+/// tRNS chunk if given.
+fn truecolor_png(
+    width: u32,
+    color_type: png::ColorType,
+    trns: Option<&[u8]>,
+    rows: &[u8],
+) -> Vec<u8> {
+    let height = u32::try_from(rows.len() / (width as usize * color_type.samples())).unwrap();
+    let mut data = vec![];
+    let mut encoder = png::Encoder::new(&mut data, width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(trns) = trns {
+        encoder.set_trns(trns.to_vec());
+    }
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(rows)
+        .unwrap();
+    data
+}
+
+/// Asserts that the image shows `colors`, reduced to BGR555, where
+/// transparent pixels take index 0.
+fn assert_quantized(image: &Image, colors: &[Color]) {
+    let palette = image.palette().unwrap();
+    assert!(palette.colors()[0].is_transparent());
+    for (pixel, color) in image.pixels().iter().zip(colors) {
+        match color.is_transparent() {
+            true => assert_eq!(*pixel, 0),
+            false => {
+                assert_ne!(*pixel, 0);
+                assert_eq!(
+                    palette.colors()[usize::from(*pixel)],
+                    Color::from(u16::from(*color) & 0x7FFF)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn truecolor_png_to_image() {
+    let gray = |value: u8| Color::new(value, value, value);
+
+    let data = truecolor_png(4, png::ColorType::Grayscale, None, &[0, 85, 170, 255]);
+    let png = Png::read_from_data(&data).unwrap();
+    let colors = [gray(0), gray(85), gray(170), gray(255)];
+    assert_eq!(png.truecolor_image().unwrap().pixels(), colors);
+    let image = png.to_image().unwrap();
+    assert_eq!(image.width(), 4);
+    assert_eq!(image.palette().unwrap().colors().len(), 5);
+    assert_quantized(&image, &colors);
+
+    // Samples that match tRNS are transparent.
+    let data = truecolor_png(
+        2,
+        png::ColorType::Grayscale,
+        Some(&[0x00, 0x55]),
+        &[85, 170],
+    );
+    let image = Png::read_from_data(&data).unwrap().to_image().unwrap();
+    assert_quantized(&image, &[gray(85).with_alpha(0), gray(170)]);
+
+    let rows = [
+        0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0x80, //
+        0x00, 0x00, 0xFF, 0x7F, 0x12, 0x34, 0x56, 0x00,
+    ];
+    let data = truecolor_png(2, png::ColorType::Rgba, None, &rows);
+    let png = Png::read_from_data(&data).unwrap();
+    let colors = [
+        Color::new(0xFF, 0x00, 0x00),
+        Color::new(0x00, 0xFF, 0x00).with_alpha(0x80),
+        Color::new(0x00, 0x00, 0xFF).with_alpha(0x7F),
+        Color::new(0x12, 0x34, 0x56).with_alpha(0),
+    ];
+    assert_eq!(png.truecolor_image().unwrap().pixels(), colors);
+    let image = png.to_image().unwrap();
+    assert_eq!(image.height(), 2);
+    // Pixels with an alpha below 128 count as transparent.
+    assert_eq!(image.pixels()[2], 0);
+    assert_quantized(&image, &[colors[0], colors[1], colors[3]]);
+
+    let data = truecolor_png(
+        3,
+        png::ColorType::Rgb,
+        Some(&[0x00, 0x12, 0x00, 0x34, 0x00, 0x56]),
+        &[0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF, 0x12, 0x34, 0x57],
+    );
+    let image = Png::read_from_data(&data).unwrap().to_image().unwrap();
+    assert_quantized(
+        &image,
+        &[
+            Color::new(0x12, 0x34, 0x56).with_alpha(0),
+            Color::new(0xFF, 0xFF, 0xFF),
+            Color::new(0x12, 0x34, 0x57),
+        ],
+    );
+
+    // 256 distinct colors do not fit along with the transparent color.
+    let rows = (0..256)
+        .flat_map(|i| [(i % 32 * 8) as u8, (i / 32 * 8) as u8, 0])
+        .collect::<Vec<_>>();
+    let data = truecolor_png(16, png::ColorType::Rgb, None, &rows);
+    let error = Png::read_from_data(&data)
+        .unwrap()
+        .to_image()
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&error, Error::InvalidInput { reason } if reason.contains("256 colors")),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn png_with_sidecar_to_ncgr() {
+    create_dir_all("tests/assets/temp/").unwrap();
+    let png_path = "tests/assets/temp/sidecar_test.png";
+    let pixels = (0..8 * 16).map(|i| (i % 16) as u8).collect::<Vec<_>>();
+    let image = Image::new(8, &pixels, Some(Palette::new(vec![Color::from(0); 16])));
+    Png::from_image(image.clone())
+        .write_to_file(png_path)
+        .unwrap();
+
+    // Without a sidecar, the default metadata is used.
+    let ncgr = Ncgr::read_from_png_with_sidecar(png_path).unwrap();
+    assert_eq!(
+        ncgr.write_to_data().unwrap(),
+        Ncgr::from_image(image.clone(), NcgrMetadata::default())
+            .write_to_data()
+            .unwrap()
+    );
+
+    let metadata = NcgrMetadata::default()
+        .with_version(NtrFileVersion::Version0101)
+        .with_texture_format(NtrTextureFormat::Palette16)
+        .with_mapping_type(NtrMappingType::Mode1D64K)
+        .with_character_format(NtrCharacterFormat::Bitmap);
+    metadata.write_sidecar(png_path).unwrap();
+    let ncgr = Ncgr::read_from_png_with_sidecar(png_path).unwrap();
+    assert_eq!(
+        ncgr.metadata().to_json().unwrap(),
+        metadata.to_json().unwrap()
+    );
+    assert_eq!(ncgr.to_image_with_width(8).pixels(), image.pixels());
+    assert_eq!(
+        ncgr.write_to_data().unwrap(),
+        Ncgr::from_image(image, metadata).write_to_data().unwrap()
+    );
+
+    remove_file(png_path).unwrap();
+    remove_file("tests/assets/temp/sidecar_test.ncgr.json").unwrap();
+}
+
+#[test]
+fn png_with_embedded_metadata_to_ncgr() {
+    create_dir_all("tests/assets/temp/").unwrap();
+    let png_path = "tests/assets/temp/embedded_test.png";
+    let image = Image::new(
+        16,
+        &[1; 16 * 8],
+        Some(Palette::new(vec![Color::from(0); 16])),
+    );
+    let metadata = NcgrMetadata::default()
+        .with_version(NtrFileVersion::Version0101)
+        .with_texture_format(NtrTextureFormat::Palette16)
+        .include_cpos(true);
+    let png = Png::from_image(image.clone()).with_ncgr_metadata(metadata);
+    png.write_to_file(png_path).unwrap();
+
+    // The embedded metadata takes precedence over the sidecar.
+    NcgrMetadata::default().write_sidecar(png_path).unwrap();
+    let ncgr = Ncgr::read_from_png_with_sidecar(png_path).unwrap();
+    assert_eq!(
+        ncgr.write_to_data().unwrap(),
+        Ncgr::from_image(image, metadata).write_to_data().unwrap()
+    );
+
+    remove_file(png_path).unwrap();
+    remove_file("tests/assets/temp/embedded_test.ncgr.json").unwrap();
+}
+
+#[test]
+fn png_with_malformed_embedded_metadata() {
+    let mut data = vec![];
+    let mut encoder = png::Encoder::new(&mut data, 8, 8);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(vec![0; 3]);
+    encoder
+        .add_ztxt_chunk("nitrogfx:ncgr".to_string(), "{\"version\":".to_string())
+        .unwrap();
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&[0; 64])
+        .unwrap();
+
+    let chunk = b"zTXtnitrogfx:ncgr\0";
+    let chunk_offset = data
+        .windows(chunk.len())
+        .position(|window| window == chunk)
+        .unwrap()
+        - 4;
+    let error = Png::read_from_data(&data).err().unwrap();
+    assert!(
+        matches!(
+            &error,
+            Error::InvalidData { offset, block, reason }
+                if *offset == chunk_offset && block == "zTXt" && reason.contains("nitrogfx:ncgr")
+        ),
+        "{:?}",
+        error
+    );
+}
+
+/// A horizontal gradient from black to white, `width` pixels wide.
+fn gradient(width: usize, height: usize) -> TruecolorImage {
+    let pixels = (0..width * height)
+        .map(|i| {
+            let value = u8::try_from(i % width * 255 / (width - 1)).unwrap();
+            Color::new(value, value, value)
+        })
+        .collect();
+    TruecolorImage::new(width, pixels)
+}
+
+/// A palette of a transparent color followed by black and white.
+fn black_and_white() -> Palette {
+    Palette::new(vec![
+        Color::new(0, 0, 0).with_alpha(0),
+        Color::new(0, 0, 0),
+        Color::new(255, 255, 255),
+    ])
+}
+
+#[test]
+fn gradient_dithering() {
+    let (width, height) = (64, 16);
+    let image = gradient(width, height);
+    let quantization =
+        Quantization::nearest(black_and_white()).with_color_scaling(ColorScaling::FullRange);
+    let white_share = |pixels: &[u8], x: usize| {
+        (0..height).filter(|y| pixels[y * width + x] == 2).count() as f32 / height as f32
+    };
+
+    // Without dithering, every column is either black or white.
+    let plain = image.quantize(&quantization).unwrap();
+    for x in 0..width {
+        assert!([0.0, 1.0].contains(&white_share(plain.pixels(), x)));
+    }
+
+    for dithering in [
+        Dithering::FloydSteinberg,
+        Dithering::Bayer,
+        Dithering::Atkinson,
+    ] {
+        let dithered = image
+            .quantize(&quantization.clone().with_dithering(dithering))
+            .unwrap();
+        let pixels = dithered.pixels();
+        assert!(pixels.iter().all(|pixel| [1, 2].contains(pixel)));
+        // The ends stay solid, and the share of white pixels follows the
+        // gradient in between.
+        assert_eq!(white_share(pixels, 0), 0.0, "{:?}", dithering);
+        assert_eq!(white_share(pixels, width - 1), 1.0, "{:?}", dithering);
+        for x in (8..width - 8).step_by(8) {
+            let expected = x as f32 / (width - 1) as f32;
+            let share = (x - 4..x + 4).map(|x| white_share(pixels, x)).sum::<f32>() / 8.0;
+            assert!(
+                (share - expected).abs() < 0.2,
+                "{:?} at {}: {} instead of {}",
+                dithering,
+                x,
+                share,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn bayer_dithering_is_positional() {
+    let gray = Color::new(100, 100, 100);
+    let quantization = Quantization::nearest(black_and_white())
+        .with_color_scaling(ColorScaling::FullRange)
+        .with_dithering(Dithering::Bayer);
+    let uniform = TruecolorImage::new(8, vec![gray; 64])
+        .quantize(&quantization)
+        .unwrap();
+    let pixels = uniform.pixels();
+    assert!(pixels.contains(&1) && pixels.contains(&2));
+    // The pattern repeats every 4 pixels in both directions.
+    for y in 0..8 {
+        for x in 0..8 {
+            assert_eq!(pixels[y * 8 + x], pixels[(y % 4) * 8 + x % 4]);
+        }
+    }
+
+    // Other pixels have no effect, unlike with error diffusion.
+    let mut colors = vec![gray; 64];
+    colors[0] = Color::new(255, 255, 255);
+    colors[9] = Color::new(0, 0, 0);
+    let changed = TruecolorImage::new(8, colors)
+        .quantize(&quantization)
+        .unwrap();
+    for (i, (a, b)) in pixels.iter().zip(changed.pixels()).enumerate() {
+        if ![0, 9].contains(&i) {
+            assert_eq!(a, b, "{}", i);
+        }
+    }
+}
+
+#[test]
+fn dithering_never_uses_transparent_index() {
+    // Dark colors are closest to the transparent black of index 0.
+    let mut pixels = gradient(32, 8).pixels().to_vec();
+    pixels[3] = pixels[3].with_alpha(0);
+    let image = TruecolorImage::new(32, pixels);
+    let palette = Palette::new(vec![
+        Color::new(0, 0, 0).with_alpha(0),
+        Color::new(64, 64, 64),
+        Color::new(255, 255, 255),
+    ]);
+    for dithering in [
+        Dithering::FloydSteinberg,
+        Dithering::Bayer,
+        Dithering::Atkinson,
+    ] {
+        let nearest = image
+            .quantize(&Quantization::nearest(palette.clone()).with_dithering(dithering))
+            .unwrap();
+        let automatic = image
+            .quantize(&Quantization::automatic(256).with_dithering(dithering))
+            .unwrap();
+        for result in [nearest, automatic] {
+            for (i, pixel) in result.pixels().iter().enumerate() {
+                assert_eq!(*pixel == 0, i == 3, "{:?} at {}", dithering, i);
+            }
+        }
+    }
+}
+
+#[test]
+fn automatic_dithering_fits_max_colors() {
+    // 15 gray levels, which only fit in 16 colors along with the transparent
+    // color if dithering uses no others.
+    let image = TruecolorImage::new(
+        60,
+        (0..60 * 4)
+            .map(|i| {
+                let value = u8::try_from(i % 60 * 2).unwrap();
+                Color::new(value, value, value)
+            })
+            .collect(),
+    );
+    let quantization = Quantization::automatic(16);
+    let plain = image.quantize(&quantization).unwrap();
+    assert_eq!(plain.palette().unwrap().colors().len(), 16);
+    for dithering in [
+        Dithering::FloydSteinberg,
+        Dithering::Bayer,
+        Dithering::Atkinson,
+    ] {
+        let dithered = image
+            .quantize(&quantization.clone().with_dithering(dithering))
+            .unwrap();
+        assert_eq!(
+            dithered.palette().unwrap().colors(),
+            plain.palette().unwrap().colors()
+        );
+        assert!(dithered.pixels() != plain.pixels(), "{:?}", dithering);
+    }
+}
+
+#[test]
+fn transparent_index() {
+    let transparent = Color::new(0, 0, 0).with_alpha(0);
+    let (red, blue) = (Color::new(248, 0, 0), Color::new(0, 0, 248));
+    let image = TruecolorImage::new(4, vec![red, transparent, blue, red]);
+
+    // Opaque colors fill the palette around the transparent index, which is
+    // padded with black if needed.
+    let automatic = image
+        .quantize(&Quantization::automatic(16).with_transparent_index(3))
+        .unwrap();
+    assert_eq!(automatic.pixels(), [0, 3, 1, 0]);
+    let colors = automatic.palette().unwrap().colors().to_vec();
+    assert_eq!(colors.len(), 4);
+    assert_eq!(colors[..3], [red, blue, Color::new(0, 0, 0)]);
+    assert!(colors[3].is_transparent());
+    let automatic = image
+        .quantize(&Quantization::automatic(16).with_transparent_index(1))
+        .unwrap();
+    assert_eq!(automatic.pixels(), [0, 1, 2, 0]);
+
+    // Opaque pixels never take the transparent index, even when it matches.
+    let palette = Palette::new(vec![blue, red, transparent, red]);
+    for quantization in [
+        Quantization::exact(palette.clone()),
+        Quantization::nearest(palette.clone()),
+        Quantization::nearest(palette.clone()).with_dithering(Dithering::FloydSteinberg),
+    ] {
+        let image = image
+            .quantize(&quantization.with_transparent_index(1))
+            .unwrap();
+        assert_eq!(image.pixels(), [3, 1, 0, 3]);
+    }
+
+    let errors = [
+        image.quantize(&Quantization::automatic(16).with_transparent_index(16)),
+        image.quantize(&Quantization::exact(palette.clone()).with_transparent_index(4)),
+        image.quantize(&Quantization::nearest(palette).with_transparent_index(4)),
+    ];
+    for result in errors {
+        assert!(
+            matches!(result, Err(Error::InvalidInput { .. })),
+            "{:?}",
+            result.err()
+        );
+    }
+}
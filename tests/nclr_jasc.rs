@@ -5,7 +5,10 @@ use std::fs::{create_dir_all, read_dir, remove_file};
 use rayon::iter::{ParallelBridge, ParallelIterator};
 
 use common::{assert_same_hash, file_has_extension};
-use nitrogfx::{FileFormat, Jasc, Nclr};
+use nitrogfx::{
+    Color, Error, FileFormat, Image, Jasc, Nclr, NclrMetadata, NtrFileVersion, NtrTextureFormat,
+    Palette, Png,
+};
 
 #[test]
 fn nclr_to_jasc_to_nclr() {
@@ -47,3 +50,309 @@ fn nclr_to_jasc_to_nclr() {
             remove_file(created_file_path).unwrap();
         });
 }
+
+#[test]
+fn nclr_to_jasc_with_sidecar_to_nclr() {
+    let file_ext = "nclr";
+    let intermediate_ext = "jasc";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}_sidecar", file_stem);
+            let intermediate_path = &format!("{}.{}", temp_file_stem, intermediate_ext);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let original_file = Nclr::read_from_file(&original_file_path).unwrap();
+            Jasc::from_palette(original_file.to_palette())
+                .write_to_file(intermediate_path)
+                .unwrap();
+            original_file
+                .metadata()
+                .write_sidecar(intermediate_path)
+                .unwrap();
+
+            let created_file = Nclr::read_from_jasc_with_sidecar(intermediate_path).unwrap();
+            created_file.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(intermediate_path).unwrap();
+            remove_file(format!("{}.nclr.json", temp_file_stem)).unwrap();
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn extended_nclr_banks_to_jasc_to_nclr() {
+    let file_ext = "nclr";
+    let intermediate_ext = "jasc";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let original_file = Nclr::read_from_file(&original_file_path).unwrap();
+            if !original_file.metadata().extended {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/banks_{}", file_stem);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let palette = original_file.to_palette();
+            let mut colors = vec![];
+            for bank in 0..palette.num_extended_banks() {
+                let intermediate_path =
+                    &format!("{}_{}.{}", temp_file_stem, bank, intermediate_ext);
+                Jasc::from_palette(palette.extended_bank(bank).unwrap())
+                    .write_to_file(intermediate_path)
+                    .unwrap();
+                let bank_palette = Jasc::read_from_file(intermediate_path)
+                    .unwrap()
+                    .to_palette();
+                assert!(bank_palette.colors().len() <= Palette::EXTENDED_BANK_SIZE);
+                colors.extend_from_slice(bank_palette.colors());
+                remove_file(intermediate_path).unwrap();
+            }
+
+            Nclr::from_palette(Palette::new(colors), original_file.metadata())
+                .write_to_file(created_file_path)
+                .unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn extended_palette_banks() {
+    let palette = Palette::new((0..4096).map(|i| Color::from(i * 7 % 0x8000)).collect());
+    let metadata = NclrMetadata::default()
+        .with_texture_format(NtrTextureFormat::Palette256)
+        .extended(true);
+    let data = Nclr::from_palette(palette.clone(), metadata.clone())
+        .write_to_data()
+        .unwrap();
+
+    let nclr = Nclr::read_from_data(&data).unwrap();
+    assert!(nclr.metadata().extended);
+    let read_palette = nclr.to_palette();
+    assert_eq!(read_palette.colors(), palette.colors());
+    assert_eq!(
+        read_palette.num_extended_banks(),
+        Palette::NUM_EXTENDED_BANKS
+    );
+    assert_eq!(
+        read_palette.extended_bank(15).unwrap().colors(),
+        &palette.colors()[3840..]
+    );
+    assert!(read_palette.extended_bank(16).is_none());
+
+    // Each bank is exported to JASC and PNG on its own.
+    let mut colors = vec![];
+    for n in 0..read_palette.num_extended_banks() {
+        let bank = read_palette.extended_bank(n).unwrap();
+        assert_eq!(bank.colors().len(), Palette::EXTENDED_BANK_SIZE);
+        let jasc_data = Jasc::from_palette(bank.clone()).write_to_data().unwrap();
+        let jasc_bank = Jasc::read_from_data(&jasc_data).unwrap().to_palette();
+        colors.extend_from_slice(jasc_bank.colors());
+
+        let pixels = (0..=u8::MAX).collect::<Vec<_>>();
+        let png_data = Png::from_image(Image::new(16, &pixels, Some(bank.clone())))
+            .write_to_data()
+            .unwrap();
+        let image = Png::read_from_data(&png_data).unwrap().to_image().unwrap();
+        assert_eq!(image.pixels(), pixels);
+        assert_eq!(image.palette().unwrap().colors().len(), bank.colors().len());
+    }
+    assert_eq!(
+        Nclr::from_palette(Palette::new(colors), metadata.clone())
+            .write_to_data()
+            .unwrap(),
+        data
+    );
+
+    // The full palette does not fit in a PNG, nor do more than 16 banks in an
+    // NCLR.
+    let pixels = vec![0; 16];
+    let error = Png::from_image(Image::new(16, &pixels, Some(palette.clone())))
+        .write_to_data()
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+    let too_many_colors = Palette::new(vec![Color::from(0); 4097]);
+    let error = Nclr::from_palette(too_many_colors, metadata)
+        .write_to_data()
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+}
+
+#[test]
+fn nclr_banks_to_jasc_to_nclr() {
+    let file_ext = "nclr";
+    let intermediate_ext = "jasc";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let original_file = Nclr::read_from_file(&original_file_path).unwrap();
+            let palette = original_file.to_palette();
+            if palette.colors().len() % Palette::BANK_SIZE != 0 {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/bank16_{}", file_stem);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let mut banks = vec![];
+            for (n, bank) in palette.banks().into_iter().enumerate() {
+                let intermediate_path = &format!("{}_{}.{}", temp_file_stem, n, intermediate_ext);
+                Jasc::from_palette(bank)
+                    .write_to_file(intermediate_path)
+                    .unwrap();
+                banks.push(
+                    Jasc::read_from_file(intermediate_path)
+                        .unwrap()
+                        .to_palette(),
+                );
+                remove_file(intermediate_path).unwrap();
+            }
+            assert_eq!(banks.len(), palette.num_banks());
+
+            Nclr::from_palette(
+                Palette::from_banks(&banks).unwrap(),
+                original_file.metadata(),
+            )
+            .write_to_file(created_file_path)
+            .unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn palette_banks() {
+    let colors = (0..40).map(|i| Color::from(i * 0x0421)).collect::<Vec<_>>();
+    let black = Color::new(0, 0, 0);
+    let mut palette = Palette::new(colors.clone());
+    assert_eq!(palette.num_banks(), 3);
+    assert_eq!(palette.bank(1).unwrap().colors(), &colors[16..32]);
+    assert_eq!(palette.bank(2).unwrap().colors(), &colors[32..]);
+    assert!(palette.bank(3).is_none());
+    let banks = palette.banks();
+    assert_eq!(banks.len(), 3);
+    assert_eq!(banks[2].colors(), &colors[32..]);
+    assert_eq!(
+        palette
+            .bank_for_format(2, NtrTextureFormat::Palette16)
+            .unwrap()
+            .colors(),
+        &colors[32..]
+    );
+    assert_eq!(
+        palette
+            .bank_for_format(0, NtrTextureFormat::Palette256)
+            .unwrap()
+            .colors(),
+        colors
+    );
+
+    // Setting a bank only replaces as many colors as it has.
+    let bank = Palette::new(vec![Color::from(0x7FFF); 4]);
+    palette.set_bank(1, &bank).unwrap();
+    assert_eq!(&palette.colors()[..16], &colors[..16]);
+    assert_eq!(&palette.colors()[16..20], bank.colors());
+    assert_eq!(&palette.colors()[20..], &colors[20..]);
+
+    // Setting a bank past the end pads the palette with black.
+    palette.set_bank(4, &bank).unwrap();
+    assert_eq!(palette.colors().len(), 80);
+    assert_eq!(&palette.colors()[32..40], &colors[32..]);
+    assert!(palette.colors()[40..64].iter().all(|color| *color == black));
+    assert_eq!(&palette.colors()[64..68], bank.colors());
+    assert!(palette.colors()[68..].iter().all(|color| *color == black));
+
+    let error = palette
+        .set_bank(0, &Palette::new(vec![black; 17]))
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+
+    let palette =
+        Palette::from_banks(&[bank.clone(), Palette::new(colors[..16].to_vec())]).unwrap();
+    assert_eq!(palette.colors().len(), 32);
+    assert_eq!(&palette.colors()[..4], bank.colors());
+    assert!(palette.colors()[4..16].iter().all(|color| *color == black));
+    assert_eq!(&palette.colors()[16..], &colors[..16]);
+    assert!(Palette::from_banks(&[]).unwrap().colors().is_empty());
+}
+
+#[test]
+fn jasc_with_sidecar_to_nclr() {
+    create_dir_all("tests/assets/temp/").unwrap();
+    let jasc_path = "tests/assets/temp/sidecar_test.pal";
+    let palette = Palette::new((0..16).map(|i| Color::from(i * 0x0421)).collect());
+    Jasc::from_palette(palette.clone())
+        .write_to_file(jasc_path)
+        .unwrap();
+
+    // Without a sidecar, the default metadata is used.
+    let nclr = Nclr::read_from_jasc_with_sidecar(jasc_path).unwrap();
+    assert_eq!(
+        nclr.write_to_data().unwrap(),
+        Nclr::from_palette(palette.clone(), NclrMetadata::default())
+            .write_to_data()
+            .unwrap()
+    );
+
+    let metadata = NclrMetadata::default()
+        .with_version(NtrFileVersion::Version0101)
+        .with_texture_format(NtrTextureFormat::Palette256)
+        .invert_size(true)
+        .with_palette_indexes(vec![0]);
+    metadata.write_sidecar(jasc_path).unwrap();
+    let nclr = Nclr::read_from_jasc_with_sidecar(jasc_path).unwrap();
+    assert_eq!(
+        nclr.metadata().to_json().unwrap(),
+        metadata.to_json().unwrap()
+    );
+    assert_eq!(nclr.to_palette().colors(), palette.colors());
+    assert_eq!(
+        nclr.write_to_data().unwrap(),
+        Nclr::from_palette(palette, metadata)
+            .write_to_data()
+            .unwrap()
+    );
+
+    remove_file(jasc_path).unwrap();
+    remove_file("tests/assets/temp/sidecar_test.nclr.json").unwrap();
+}
@@ -0,0 +1,325 @@
+mod common;
+
+use std::fs::{create_dir_all, read_dir, remove_file};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::{assert_same_hash, file_has_extension};
+use nitrogfx::{
+    Aco, Act, Color, ColorScaling, Error, FileFormat, Gpl, Nclr, PaintNet, Palette, PaletteFormat,
+    RawPalette, RawPaletteOptions, RiffPal,
+};
+
+/// Converts every NCLR to the palette format `F` and back, skipping palettes
+/// with more than `max_colors` colors.
+fn nclr_to_palette_to_nclr<F: PaletteFormat>(max_colors: usize) {
+    let file_ext = "nclr";
+    let intermediate_ext = F::extension();
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let original_file = Nclr::read_from_file(&original_file_path).unwrap();
+            if original_file.to_palette().colors().len() > max_colors {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}_{}", intermediate_ext, file_stem);
+            let intermediate_path = &format!("{}.{}", temp_file_stem, intermediate_ext);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            F::from_palette(original_file.to_palette())
+                .write_to_file(intermediate_path)
+                .unwrap();
+            original_file
+                .metadata()
+                .write_sidecar(intermediate_path)
+                .unwrap();
+
+            let created_file =
+                Nclr::read_from_palette_with_sidecar::<F, _>(intermediate_path).unwrap();
+            created_file.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(intermediate_path).unwrap();
+            remove_file(format!("{}.nclr.json", temp_file_stem)).unwrap();
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+#[test]
+fn nclr_to_gpl_to_nclr() {
+    nclr_to_palette_to_nclr::<Gpl>(usize::MAX);
+}
+
+#[test]
+fn nclr_to_riff_pal_to_nclr() {
+    nclr_to_palette_to_nclr::<RiffPal>(usize::MAX);
+}
+
+#[test]
+fn nclr_to_act_to_nclr() {
+    nclr_to_palette_to_nclr::<Act>(256);
+}
+
+#[test]
+fn nclr_to_aco_to_nclr() {
+    nclr_to_palette_to_nclr::<Aco>(usize::MAX);
+}
+
+#[test]
+fn nclr_to_paint_net_to_nclr() {
+    nclr_to_palette_to_nclr::<PaintNet>(usize::MAX);
+}
+
+#[test]
+fn nclr_to_raw_palette_to_nclr() {
+    nclr_to_palette_to_nclr::<RawPalette>(usize::MAX);
+}
+
+/// Asserts that `error` is an [`Error::InvalidData`] at `expected_offset`.
+fn assert_invalid_data(error: Error, expected_offset: usize) {
+    assert!(
+        matches!(error, Error::InvalidData { offset, .. } if offset == expected_offset),
+        "{:?}",
+        error
+    );
+}
+
+/// Asserts that the palette survives writing to `F` and reading back.
+fn assert_palette_round_trip<F: PaletteFormat>(palette: &Palette) {
+    let data = F::from_palette(palette.clone()).write_to_data().unwrap();
+    assert_eq!(
+        F::read_from_data(&data).unwrap().to_palette().colors(),
+        palette.colors()
+    );
+}
+
+fn colors() -> Vec<Color> {
+    vec![
+        Color::new(255, 0, 0),
+        Color::new(0, 255, 0),
+        Color::new(0, 0, 255),
+        Color::new(12, 34, 56),
+    ]
+}
+
+#[test]
+fn read_gpl() {
+    let data = b"GIMP Palette\r\nName: Test\nColumns: 4\n#\n255   0   0\tRed\n  0 255   0 Light green\n\n0 0 255\n";
+    let palette = Gpl::read_from_data(data).unwrap().to_palette();
+    assert_eq!(palette.colors(), &colors()[..3]);
+    assert_palette_round_trip::<Gpl>(&Palette::new(colors()));
+    assert_eq!(
+        Gpl::from_palette(Palette::new(colors()[3..].to_vec()))
+            .write_to_data()
+            .unwrap(),
+        b"GIMP Palette\nColumns: 16\n#\n 12  34  56\n"
+    );
+
+    let error = Gpl::read_from_data(b"GIMP Palette\n1 2\n").err().unwrap();
+    assert_invalid_data(error, 13);
+    let error = Gpl::read_from_data(b"GIMP Palette\n0 0 0\n0 0 256\n")
+        .err()
+        .unwrap();
+    assert_invalid_data(error, 19);
+}
+
+#[test]
+fn read_act() {
+    let mut data = vec![0; 768];
+    for (i, color) in colors().iter().enumerate() {
+        data[i * 3..i * 3 + 3].copy_from_slice(&[color.red, color.green, color.blue]);
+    }
+    let palette = Act::read_from_data(&data).unwrap().to_palette();
+    assert_eq!(palette.colors().len(), 256);
+    assert_eq!(&palette.colors()[..4], colors());
+    assert_eq!(palette.colors()[4], Color::new(0, 0, 0));
+
+    // The trailer holds the number of colors and the transparent color.
+    data.extend_from_slice(&[0x00, 0x04, 0x00, 0x01]);
+    let palette = Act::read_from_data(&data).unwrap().to_palette();
+    let mut expected = colors();
+    expected[1] = expected[1].with_alpha(0);
+    assert_eq!(palette.colors(), expected);
+    assert_eq!(
+        Act::from_palette(palette).write_to_data().unwrap(),
+        data,
+        "the trailer is written back"
+    );
+    assert_eq!(
+        Act::from_palette(Palette::new(vec![Color::new(1, 2, 3); 256]))
+            .write_to_data()
+            .unwrap()
+            .len(),
+        768
+    );
+
+    assert_invalid_data(Act::read_from_data(&data[..770]).err().unwrap(), 0);
+    data[768..770].copy_from_slice(&[0x01, 0x01]);
+    assert_invalid_data(Act::read_from_data(&data).err().unwrap(), 768);
+    let error = Act::from_palette(Palette::new(vec![Color::new(0, 0, 0); 257]))
+        .write_to_data()
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::InvalidInput { .. }), "{:?}", error);
+}
+
+#[test]
+fn read_aco() {
+    // A version 1 section with an RGB color and two grayscale colors.
+    let mut data = vec![0x00, 0x01, 0x00, 0x03];
+    for (color_space, values) in [
+        (0u16, [0xFFFF, 0x0000, 0x8080, 0x0000]),
+        (8, [0, 0, 0, 0]),
+        (8, [10000, 0, 0, 0]),
+    ] {
+        data.extend_from_slice(&color_space.to_be_bytes());
+        for value in values {
+            data.extend_from_slice(&u16::to_be_bytes(value));
+        }
+    }
+    let palette = Aco::read_from_data(&data).unwrap().to_palette();
+    assert_eq!(
+        palette.colors(),
+        [
+            Color::new(255, 0, 128),
+            Color::new(255, 255, 255),
+            Color::new(0, 0, 0),
+        ]
+    );
+
+    // Both sections are written, and the names of the version 2 section are
+    // skipped when reading.
+    let palette = Palette::new(colors());
+    let written = Aco::from_palette(palette.clone()).write_to_data().unwrap();
+    assert_eq!(&written[..4], [0x00, 0x01, 0x00, 0x04]);
+    assert_eq!(written.len(), 4 + 4 * 10 + 4 + 4 * 16);
+    assert_palette_round_trip::<Aco>(&palette);
+
+    assert_invalid_data(Aco::read_from_data(&[0x00, 0x03]).err().unwrap(), 0);
+    data[4..6].copy_from_slice(&[0x00, 0x07]);
+    assert_invalid_data(Aco::read_from_data(&data).err().unwrap(), 4);
+}
+
+#[test]
+fn read_riff_pal() {
+    let mut data = b"RIFF\x00\x00\x00\x00PAL ".to_vec();
+    // Chunks other than the data chunk are skipped, including their padding.
+    data.extend_from_slice(b"junk\x03\x00\x00\x00abc\x00");
+    data.extend_from_slice(b"data\x0C\x00\x00\x00\x00\x03\x02\x00");
+    data.extend_from_slice(&[255, 0, 0, 0, 12, 34, 56, 0]);
+    let file_size = u32::try_from(data.len() - 8).unwrap();
+    data[4..8].copy_from_slice(&file_size.to_le_bytes());
+    let palette = RiffPal::read_from_data(&data).unwrap().to_palette();
+    assert_eq!(palette.colors(), [colors()[0], colors()[3]]);
+    assert_palette_round_trip::<RiffPal>(&Palette::new(colors()));
+
+    let written = RiffPal::from_palette(palette).write_to_data().unwrap();
+    assert_eq!(&written[..4], b"RIFF");
+    assert_eq!(&written[4..8], (written.len() as u32 - 8).to_le_bytes());
+
+    let error = RiffPal::read_from_data(b"RIFF\x00\x00\x00\x00WAVE")
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&error, Error::BadMagic { offset: 8, expected, .. } if expected == "PAL "),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn read_paint_net() {
+    let data = b"; paint.net Palette File\r\nFFFF0000\r\n8000ff00\r\n\r\n000C2238";
+    let palette = PaintNet::read_from_data(data).unwrap().to_palette();
+    assert_eq!(
+        palette.colors(),
+        [
+            Color::new(255, 0, 0),
+            Color::new(0, 255, 0).with_alpha(0x80),
+            Color::new(12, 34, 56).with_alpha(0),
+        ]
+    );
+    assert_palette_round_trip::<PaintNet>(&palette);
+
+    let error = PaintNet::read_from_data(b"FF000000\nFF00\n").err().unwrap();
+    assert_invalid_data(error, 9);
+    let error = PaintNet::read_from_data(b"FF00000G").err().unwrap();
+    assert_invalid_data(error, 0);
+}
+
+#[test]
+fn read_raw_palette() {
+    let data = [0x1F, 0x00, 0xE0, 0x83, 0x00, 0x7C, 0xFF, 0x7F];
+    let raw = RawPalette::read_from_data(&data).unwrap();
+    let palette = raw.to_palette_with_options(RawPaletteOptions::default());
+    assert_eq!(
+        palette.colors(),
+        [
+            Color::from(0x001F),
+            Color::from(0x83E0),
+            Color::from(0x7C00),
+            Color::from(0x7FFF),
+        ]
+    );
+    assert!(palette.colors()[1].high_bit);
+    assert_eq!(palette.colors()[3], Color::new(248, 248, 248));
+    assert_eq!(
+        RawPalette::from_palette(palette.clone())
+            .write_to_data()
+            .unwrap(),
+        data
+    );
+
+    // The high bit can be cleared, both when reading and when writing.
+    let options = RawPaletteOptions::default().preserve_high_bit(false);
+    assert!(!raw.to_palette_with_options(options).colors()[1].high_bit);
+    let written = RawPalette::from_palette_with_options(palette.clone(), options)
+        .write_to_data()
+        .unwrap();
+    assert_eq!(written[3], 0x03);
+
+    // Padding is added with black when writing, and kept when reading.
+    let options = RawPaletteOptions::default().with_padding(16);
+    let written = RawPalette::from_palette_with_options(palette.clone(), options)
+        .write_to_data()
+        .unwrap();
+    assert_eq!(written.len(), 32);
+    assert_eq!(&written[..8], data);
+    assert!(written[8..].iter().all(|byte| *byte == 0));
+    assert_eq!(
+        RawPalette::read_from_data(&written)
+            .unwrap()
+            .to_palette()
+            .colors()
+            .len(),
+        16
+    );
+
+    let options = RawPaletteOptions::default().with_color_scaling(ColorScaling::FullRange);
+    let palette = raw.to_palette_with_options(options);
+    assert_eq!(palette.colors()[3], Color::new(255, 255, 255));
+    assert_eq!(
+        RawPalette::from_palette_with_options(palette, options)
+            .write_to_data()
+            .unwrap(),
+        data
+    );
+
+    assert_invalid_data(RawPalette::read_from_data(&data[..7]).err().unwrap(), 6);
+    assert!(RawPalette::read_from_data(&[])
+        .unwrap()
+        .to_palette()
+        .colors()
+        .is_empty());
+}
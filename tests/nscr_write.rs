@@ -0,0 +1,238 @@
+mod common;
+
+use std::fs::{create_dir_all, read_dir, remove_file};
+
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use common::{assert_same_hash, file_has_extension};
+use nitrogfx::{
+    Color, Error, FileFormat, Image, NcgrMetadata, Nscr, NscrMetadata, NtrBgType, NtrTextureFormat,
+    Palette,
+};
+
+#[test]
+fn nscr_to_nscr() {
+    let file_ext = "nscr";
+
+    create_dir_all("tests/assets/temp/").unwrap();
+    read_dir(format!("tests/assets/{}", file_ext))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .par_bridge()
+        .for_each(|entry| {
+            let original_file_path = entry.path();
+            if original_file_path.is_dir()
+                || original_file_path.metadata().unwrap().len() == 0
+                || !file_has_extension(&original_file_path, file_ext)
+            {
+                return;
+            }
+            let file_stem = original_file_path.file_stem().unwrap().to_str().unwrap();
+            let temp_file_stem = &format!("tests/assets/temp/{}", file_stem);
+            let created_file_path = &format!("{}.{}", temp_file_stem, file_ext.to_uppercase());
+            let original_file = Nscr::read_from_file(&original_file_path).unwrap();
+            original_file.write_to_file(created_file_path).unwrap();
+
+            assert_same_hash(original_file_path, created_file_path);
+            remove_file(created_file_path).unwrap();
+        });
+}
+
+/// A tile whose flipped versions all differ from each other.
+fn asymmetric_tile(color: u8) -> Vec<u8> {
+    (0..64)
+        .map(|i| if i % 8 < 3 && i / 8 < 2 { color } else { 0 })
+        .collect()
+}
+
+/// Arranges 8x8 tiles into an image that is `width_in_tiles` tiles wide.
+fn tiles_to_image(tiles: &[Vec<u8>], width_in_tiles: usize, palette: Palette) -> Image {
+    let mut pixels = vec![0; tiles.len() * 64];
+    for (i, tile) in tiles.iter().enumerate() {
+        for (j, pixel) in tile.iter().enumerate() {
+            let x = i % width_in_tiles * 8 + j % 8;
+            let y = i / width_in_tiles * 8 + j / 8;
+            pixels[y * width_in_tiles * 8 + x] = *pixel;
+        }
+    }
+    Image::new(width_in_tiles * 8, &pixels, Some(palette))
+}
+
+fn flip(tile: &[u8], h_flip: bool, v_flip: bool) -> Vec<u8> {
+    (0..64)
+        .map(|i| {
+            let x = if h_flip { 7 - i % 8 } else { i % 8 };
+            let y = if v_flip { 7 - i / 8 } else { i / 8 };
+            tile[y * 8 + x]
+        })
+        .collect()
+}
+
+#[test]
+fn image_to_nscr_with_flipped_tiles() {
+    let tile = asymmetric_tile(3);
+    let other = (0..64).map(|i| (i % 16) as u8).collect::<Vec<_>>();
+    let image = tiles_to_image(
+        &[
+            tile.clone(),
+            flip(&tile, true, false),
+            flip(&tile, false, true),
+            flip(&tile, true, true),
+            other,
+            tile,
+        ],
+        3,
+        Palette::new(vec![Color::new(0, 0, 0); 16]),
+    );
+
+    let (nscr, ncgr) =
+        Nscr::from_image(&image, NscrMetadata::default(), NcgrMetadata::default()).unwrap();
+    let tileset = ncgr.to_image().with_palette(image.palette().unwrap());
+    assert_eq!(tileset.pixels().len(), 2 * 64);
+
+    let data = nscr.write_to_data().unwrap();
+    let entries = data[36..]
+        .chunks(2)
+        .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+        .collect::<Vec<_>>();
+    assert_eq!(entries, [0x0000, 0x0400, 0x0800, 0x0C00, 0x0001, 0x0000]);
+    assert_eq!(&data[24..32], [24, 0, 16, 0, 0, 0, 0, 0]);
+
+    let nscr = Nscr::read_from_data(&data).unwrap();
+    assert!(nscr.to_image(&tileset).unwrap().pixels() == image.pixels());
+}
+
+#[test]
+fn image_to_affine_nscr() {
+    let tiles = (0..4).map(asymmetric_tile).collect::<Vec<_>>();
+    let image = tiles_to_image(
+        &[
+            tiles[1].clone(),
+            flip(&tiles[1], true, false),
+            tiles[1].clone(),
+        ],
+        3,
+        Palette::new(vec![Color::new(0, 0, 0); 256]),
+    );
+    let metadata = NscrMetadata::default()
+        .with_texture_format(NtrTextureFormat::Palette256)
+        .with_bg_type(NtrBgType::Affine);
+
+    // Affine backgrounds cannot flip tiles, so the flipped tile is kept.
+    let (nscr, _) = Nscr::from_image(&image, metadata, NcgrMetadata::default()).unwrap();
+    let data = nscr.write_to_data().unwrap();
+    assert_eq!(&data[36..], [0, 1, 0]);
+}
+
+#[test]
+fn image_to_nscr_errors() {
+    let tile = asymmetric_tile(1);
+    let image = tiles_to_image(&[tile], 1, Palette::new(vec![Color::new(0, 0, 0); 16]));
+    let affine = NscrMetadata::default().with_bg_type(NtrBgType::Affine);
+    assert!(matches!(
+        Nscr::from_image(&image, affine, NcgrMetadata::default()),
+        Err(Error::InvalidInput { .. })
+    ));
+
+    // Colors 0x11 and 0x02 belong to different 16-color banks.
+    let mixed = tiles_to_image(
+        &[(0..64).map(|i| if i == 0 { 0x11 } else { 0x02 }).collect()],
+        1,
+        Palette::new(vec![Color::new(0, 0, 0); 32]),
+    );
+    assert!(matches!(
+        Nscr::from_image(&mixed, NscrMetadata::default(), NcgrMetadata::default()),
+        Err(Error::InvalidInput { .. })
+    ));
+
+    // Affine backgrounds can only address 256 tiles.
+    let unique_tiles = (0..257)
+        .map(|i: usize| {
+            let mut tile = vec![0; 64];
+            tile[0] = i as u8;
+            tile[1] = (i >> 8) as u8;
+            tile
+        })
+        .collect::<Vec<_>>();
+    let image = tiles_to_image(
+        &unique_tiles,
+        257,
+        Palette::new(vec![Color::new(0, 0, 0); 256]),
+    );
+    let metadata = NscrMetadata::default()
+        .with_texture_format(NtrTextureFormat::Palette256)
+        .with_bg_type(NtrBgType::Affine);
+    assert!(matches!(
+        Nscr::from_image(&image, metadata, NcgrMetadata::default()),
+        Err(Error::InvalidInput { .. })
+    ));
+}
+
+/// Builds an NSCR file with a single row of 16-bit screen entries.
+fn nscr_data(color_mode: u16, entries: &[u16]) -> Vec<u8> {
+    let mut scrn = vec![];
+    for value in [(entries.len() * 8) as u16, 8, color_mode, 0] {
+        scrn.extend(value.to_le_bytes());
+    }
+    scrn.extend((entries.len() as u32 * 2).to_le_bytes());
+    for entry in entries {
+        scrn.extend(entry.to_le_bytes());
+    }
+
+    let mut data = b"RCSN\xFF\xFE\x00\x01".to_vec();
+    data.extend((16 + 8 + scrn.len() as u32).to_le_bytes());
+    data.extend([0x10, 0, 1, 0]);
+    data.extend(b"NRCS");
+    data.extend((8 + scrn.len() as u32).to_le_bytes());
+    data.extend(scrn);
+    data
+}
+
+#[test]
+fn nscr_to_image() {
+    let tiles = Image::new(8, &[vec![1; 64], vec![2; 64]].concat(), None);
+    let palette = (0..512)
+        .map(|i| Color::new((i % 256) as u8, (i / 256) as u8, 0))
+        .collect::<Vec<_>>();
+    let palette = Palette::new(palette);
+
+    // The palette bank of each entry selects a 256-color bank of an extended
+    // palette.
+    let nscr = Nscr::read_from_data(&nscr_data(2, &[0x1000, 0x1001])).unwrap();
+    let image = nscr
+        .to_image(&tiles.clone().with_palette(palette.clone()))
+        .unwrap();
+    assert_eq!(image.palette().unwrap().colors(), &palette.colors()[256..]);
+    assert_eq!(image.pixels()[..8], [1, 1, 1, 1, 1, 1, 1, 1]);
+    assert_eq!(image.pixels()[8..16], [2, 2, 2, 2, 2, 2, 2, 2]);
+
+    // Without an extended palette, the palette bank is ignored.
+    let bank = palette.extended_bank(0).unwrap();
+    let image = nscr
+        .to_image(&tiles.clone().with_palette(bank.clone()))
+        .unwrap();
+    assert_eq!(image.palette().unwrap().colors(), bank.colors());
+
+    let errors = [
+        (nscr_data(2, &[0x1000, 0x2001]), palette.clone()),
+        (nscr_data(2, &[0x2000]), palette.clone()),
+        (nscr_data(1, &[0x0002]), palette.clone()),
+        (
+            nscr_data(0, &[0x0001]),
+            Palette::new(palette.colors()[..2].to_vec()),
+        ),
+    ];
+    for (data, palette) in errors {
+        let nscr = Nscr::read_from_data(&data).unwrap();
+        let result = nscr.to_image(&tiles.clone().with_palette(palette));
+        assert!(
+            matches!(result, Err(Error::InvalidInput { .. })),
+            "{:?}",
+            result.err()
+        );
+    }
+    assert!(matches!(
+        nscr.to_image(&tiles),
+        Err(Error::InvalidInput { .. })
+    ));
+}